@@ -15,11 +15,14 @@ use {
     proc_macro::TokenStream,
     proc_macro2::Span,
     quote::{
+        ToTokens as _,
+        format_ident,
         quote,
         quote_spanned,
     },
     syn::{
         *,
+        ext::IdentExt as _,
         punctuated::Punctuated,
         spanned::Spanned as _,
     },
@@ -27,134 +30,564 @@ use {
 
 /// Registers a subcommand that you can run from a menu item's `command`.
 ///
-/// Commands may take any number of parameters implementing `FromStr` (with errors implementing `Debug` and `Display`) and `ToString`, and should return `Result<(), Error>`, where `Error` is any type that implements `Display`. If a command errors, `bitbar` will attempt to send a macOS notification containing the error message.
+/// Commands may take any number of parameters implementing `FromStr` (with errors implementing `Debug` and `Display`) and `ToString`, and should return `Result<(), Error>`, where `Error` is any type that implements `Display`. If a command errors, `bitbar` will attempt to send a macOS notification containing the error message. Commands may also return any other type implementing `bitbar::CommandOutput`, such as `Menu` (to refresh the plugin), `std::process::ExitCode`, or `bitbar::CommandResult`. With one of the async features, they may return a type implementing `bitbar::AsyncCommandOutput` instead, whose reporting can be async.
+///
+/// Trailing parameters may be optional, so that a command can be invoked with differing numbers of arguments:
+///
+/// * Parameters of type `Option<T>` are `None` if the argument is omitted.
+/// * Parameters annotated with `#[command(default = "...")]` are parsed from the given string if the argument is omitted.
+///
+/// Parameters of type `bool` are flags, which are passed as `--name` (with underscores in the parameter name replaced by hyphens) if `true` and omitted if `false`. Flags may be declared in any position, and the generated function takes them as `bool`. When parsing the command's arguments, flags are only recognized before the other parameters or after the required ones, and an argument `--` ends the flags, so parameter values which look like flags are passed through unchanged.
 ///
 /// Alternatively, use this arrtibute as `#[command(varargs)]` and define the command function with a single parameter of type `Vec<String>`.
 ///
-/// The `command` attribute generates a function that can be called with arguments of references to the original parameter types to obtain a `std::io::Result<Params>`. If the command has more than 5 parameters or is declared with `#[command(varargs)]`, the function takes an additional first parameter of type `SwiftBar`.
+/// With the `clap` feature, this attribute can also be used as `#[command(clap)]` on a function with a single parameter whose type implements `clap::Parser` (with a `clap` version compatible with the one used by `bitbar`). The command's arguments are parsed using `clap`, so options, validation, and `--help` are available when running the command from a terminal. If parsing fails, the error is shown as a notification.
+///
+/// Or, use this attribute as `#[command(subcommands)]` and define the command function with a single parameter whose type derives [`Subcommands`](macro@Subcommands). The function will be called with the parsed value for each of the subcommands defined by that type, and no function for constructing `Params` is generated since the type's `to_params` method can be used instead.
+///
+/// The `command` attribute generates a function that can be called with arguments of references to the original parameter types to obtain a `std::io::Result<Params>`. An error is returned if the path to the plugin binary can't be determined or is not valid UTF-8. Optional and defaulted parameters are taken as `Option<&T>` instead, where `None` omits the argument. Only trailing arguments may be omitted, so an error is returned if `None` is followed by `Some`. If the command has more than 5 parameters or is declared with `#[command(varargs)]` or `#[command(clap)]`, the function takes an additional first parameter of type `SwiftBar`.
+///
+/// Use `#[command(notify_success = "...")]` to send a notification with the given text when the command succeeds. For commands with regular parameters, occurrences of `{param}` in the text are replaced with the value of the parameter named `param`.
 ///
-/// The function must also be registered via `#[bitbar::main(commands(...))]`.
+/// By default, the subcommand has the same name as the function. Use `#[command(rename = "...")]` to specify a different name, or `#[command(kebab_case)]` to replace underscores in the function name with hyphens. This allows renaming the function without changing the commands in already rendered menus.
+///
+/// Commands are registered automatically, so they will be found by the `main` function generated by `#[bitbar::main]`. This requires the `inventory` feature of the `bitbar` crate, which is enabled by default. Without it, commands must be listed in the `commands` argument of `#[bitbar::main]` (or, for `#[command(subcommands)]`, passed as its `fallback_command`).
 #[proc_macro_attribute]
 pub fn command(args: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args with Punctuated::<Meta, Token![,]>::parse_terminated);
-    let varargs = match args.into_iter().at_most_one() {
-        Ok(None) => false,
-        Ok(Some(arg)) if arg.path().is_ident("varargs") => true,
-        _ => return quote!(compile_error!("unexpected bitbar::command arguments");).into(),
-    };
-    let command_fn = parse_macro_input!(item as ItemFn);
-    let vis = &command_fn.vis;
-    let asyncness = &command_fn.sig.asyncness;
-    let command_name = &command_fn.sig.ident;
-    let command_name_str = command_name.to_string();
-    let wrapper_name = Ident::new(&format!("bitbar_{command_name}_wrapper"), Span::call_site());
+    let mut varargs = false;
+    let mut clap = false;
+    let mut subcommands = false;
+    let mut kebab_case = false;
+    let mut rename = None;
+    let mut notify_success = None;
+    for arg in args {
+        if arg.path().is_ident("varargs") {
+            if let Err(e) = arg.require_path_only() { return e.into_compile_error().into() }
+            varargs = true;
+        } else if arg.path().is_ident("clap") {
+            if let Err(e) = arg.require_path_only() { return e.into_compile_error().into() }
+            clap = true;
+        } else if arg.path().is_ident("subcommands") {
+            if let Err(e) = arg.require_path_only() { return e.into_compile_error().into() }
+            subcommands = true;
+        } else if arg.path().is_ident("kebab_case") {
+            if let Err(e) = arg.require_path_only() { return e.into_compile_error().into() }
+            kebab_case = true;
+        } else if arg.path().is_ident("rename") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => rename = Some(lit.value()),
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("rename value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("notify_success") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => notify_success = Some(lit.clone()),
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("notify_success value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else {
+            return quote_spanned! {arg.span()=>
+                compile_error!("unexpected bitbar::command arguments");
+            }.into()
+        }
+    }
+    let mut command_fn = parse_macro_input!(item as ItemFn);
+    let vis = command_fn.vis.clone();
+    let asyncness = command_fn.sig.asyncness;
+    let command_name = command_fn.sig.ident.clone();
+    let unraw_name = command_name.unraw();
+    let command_name_str = rename.unwrap_or_else(|| if kebab_case {
+        unraw_name.to_string().trim_start_matches('_').replace('_', "-")
+    } else {
+        unraw_name.to_string()
+    });
+    let name_const = Ident::new(&format!("BITBAR_{}_NAME", unraw_name.to_string().to_uppercase()), Span::call_site());
+    let wrapper_name = Ident::new(&format!("bitbar_{unraw_name}_wrapper"), Span::call_site());
     let awaitness = asyncness.as_ref().map(|_| quote!(.await));
-    let (wrapper_body, command_params, command_args) = if varargs {
+    let wrap_output = |output: proc_macro2::TokenStream| if notify_success.is_some() {
+        quote!(::bitbar::NotifySuccess::new(#output, message))
+    } else {
+        output
+    };
+    let message_init = notify_success.as_ref().map(|lit| quote!(let message = ::std::string::ToString::to_string(#lit);));
+    let (report, report_awaitness) = report_output();
+    if subcommands {
+        let Ok(FnArg::Typed(PatType { ty, .. })) = command_fn.sig.inputs.iter().exactly_one() else {
+            return quote_spanned! {command_fn.sig.inputs.span()=>
+                compile_error!("a bitbar::command(subcommands) function must take a single parameter whose type implements bitbar::Subcommands");
+            }.into()
+        };
+        let ty = ty.clone();
+        let registration = cfg!(feature = "inventory").then(|| quote! {
+            ::bitbar::inventory::submit! {
+                ::bitbar::SubcommandsRegistration {
+                    names: <#ty as ::bitbar::Subcommands>::NAMES,
+                    run: #wrapper_name,
+                }
+            }
+        });
+        let output = wrap_output(quote!(#command_name(subcommand)#awaitness));
+        let (wrapper_ret, wrapper_body) = wrap_async(quote! {
+            match <#ty as ::bitbar::Subcommands>::parse(&cmd, args) {
+                ::core::result::Result::Ok(subcommand) => {
+                    #message_init
+                    #report(#output, &cmd)#report_awaitness
+                }
+                ::core::result::Result::Err(e) => {
+                    ::bitbar::notify_error(&::std::string::ToString::to_string(&e), &::std::format!("{e:?}"));
+                    ::std::process::exit(1)
+                }
+            }
+        });
+        return TokenStream::from(quote! {
+            fn #wrapper_name(cmd: ::std::string::String, args: ::std::vec::Vec<::std::string::String>) #wrapper_ret {
+                #command_fn
+
+                #wrapper_body
+            }
+
+            #registration
+        })
+    }
+    let mut param_descs = Vec::default();
+    let (wrapper_body, command_params, command_args) = if clap {
+        let Ok(FnArg::Typed(PatType { pat, ty, .. })) = command_fn.sig.inputs.iter().exactly_one() else {
+            return quote_spanned! {command_fn.sig.inputs.span()=>
+                compile_error!("a bitbar::command(clap) function must take a single parameter whose type implements clap::Parser");
+            }.into()
+        };
+        param_descs.push(param_desc(pat, 0, ty, "clap", None));
+        let output = wrap_output(quote!(#command_name(args)#awaitness));
         (
-            quote!(::bitbar::CommandOutput::report(#command_name(args)#awaitness, #command_name_str)),
-            quote!(::std::iter::Iterator::collect(::std::iter::Iterator::chain(::std::iter::once(::std::string::ToString::to_string(#command_name_str)), args))),
+            quote! {
+                #message_init
+                match <#ty as ::bitbar::clap::Parser>::try_parse_from(::std::iter::Iterator::chain(::std::iter::once(::std::string::ToString::to_string(#command_name_str)), args)) {
+                    ::core::result::Result::Ok(args) => #report(#output, #command_name_str)#report_awaitness,
+                    ::core::result::Result::Err(e) => {
+                        if e.use_stderr() {
+                            let message = ::std::string::ToString::to_string(&e);
+                            ::bitbar::notify_error(message.lines().next().unwrap_or_default(), &message);
+                        }
+                        e.exit()
+                    }
+                }
+            },
+            quote!(::std::io::Result::Ok(::bitbar::attr::Params::new(exe, ::std::iter::Iterator::collect(::std::iter::Iterator::chain(::std::iter::once(::std::string::ToString::to_string(#command_name_str)), args))))),
+            quote!(_: ::bitbar::flavor::SwiftBar, args: ::std::vec::Vec<::std::string::String>),
+        )
+    } else if varargs {
+        for (arg_idx, arg) in command_fn.sig.inputs.iter().enumerate() {
+            if let FnArg::Typed(PatType { pat, ty, .. }) = arg {
+                param_descs.push(param_desc(pat, arg_idx, ty, "varargs", None));
+            }
+        }
+        (
+            {
+                let output = wrap_output(quote!(#command_name(args)#awaitness));
+                quote! {
+                    #message_init
+                    #report(#output, #command_name_str)#report_awaitness
+                }
+            },
+            quote!(::std::io::Result::Ok(::bitbar::attr::Params::new(exe, ::std::iter::Iterator::collect(::std::iter::Iterator::chain(::std::iter::once(::std::string::ToString::to_string(#command_name_str)), args))))),
             quote!(_: ::bitbar::flavor::SwiftBar, args: ::std::vec::Vec<::std::string::String>),
         )
     } else {
-        let mut wrapper_params = Vec::default();
+        let mut wrapper_stmts = Vec::default();
         let mut wrapped_args = Vec::default();
         let mut command_params = Vec::default();
         let mut command_args = Vec::default();
-        for (arg_idx, arg) in command_fn.sig.inputs.iter().enumerate() {
+        let mut flag_inits = Vec::default();
+        let mut flag_names = Vec::default();
+        let mut flag_idents = Vec::default();
+        let mut required_count = 0usize;
+        let mut command_flags = Vec::default();
+        let mut optional_span = None;
+        let mut interpolations = Vec::default();
+        for (arg_idx, arg) in command_fn.sig.inputs.iter_mut().enumerate() {
+            let arg_span = arg.span();
             match arg {
-                FnArg::Receiver(_) => return quote_spanned! {arg.span()=>
-                    compile_error("unexpected `self` parameter in bitbar::command");
+                FnArg::Receiver(_) => return quote_spanned! {arg_span=>
+                    compile_error!("unexpected `self` parameter in bitbar::command");
                 }.into(),
-                FnArg::Typed(PatType { ty, .. }) => {
-                    let ident = Ident::new(&format!("arg{}", arg_idx), arg.span());
-                    wrapper_params.push(quote_spanned! {arg.span()=>
-                        #ident
+                FnArg::Typed(PatType { attrs, pat, ty, .. }) => {
+                    let mut default = None;
+                    let mut attr_error = None;
+                    attrs.retain(|attr| if attr.path().is_ident("command") {
+                        if let Err(e) = attr.parse_nested_meta(|meta| if meta.path.is_ident("default") {
+                            default = Some(meta.value()?.parse::<LitStr>()?);
+                            Ok(())
+                        } else {
+                            Err(meta.error("unexpected bitbar::command parameter attribute"))
+                        }) {
+                            attr_error = Some(e);
+                        }
+                        false
+                    } else {
+                        true
                     });
-                    wrapped_args.push(quote_spanned! {arg.span()=>
+                    if let Some(e) = attr_error { return e.into_compile_error().into() }
+                    let option_ty = option_inner_type(ty);
+                    let ident = Ident::new(&format!("arg{}", arg_idx), arg_span);
+                    let param_name = param_name(pat, arg_idx);
+                    let param_ty = option_ty.unwrap_or(ty).to_token_stream().to_string().replace(' ', "");
+                    let parse = quote_spanned! {arg_span=>
                         match #ident.parse() {
                             ::core::result::Result::Ok(arg) => arg,
-                            ::core::result::Result::Err(e) => {
-                                ::bitbar::notify_error(
-                                    &::std::format!("{}: error parsing parameter {}: {}", #command_name_str, #arg_idx, e),
-                                    &::std::format!("{e:?}"),
-                                );
-                                ::std::process::exit(1)
-                            }
+                            ::core::result::Result::Err(e) => ::bitbar::command_param_error(#command_name_str, #arg_idx, #param_name, #param_ty, &#ident, &e),
                         }
-                    });
-                    command_params.push(quote_spanned! {arg.span()=>
-                        #ident.to_string()
-                    });
-                    command_args.push(quote_spanned! {arg.span()=>
-                        #ident: &#ty
-                    });
+                    };
+                    if is_bool(ty) {
+                        let Pat::Ident(PatIdent { ident: param_ident, .. }) = &**pat else {
+                            return quote_spanned! {arg_span=>
+                                compile_error!("bool parameters of a bitbar::command must be simple identifiers");
+                            }.into()
+                        };
+                        let flag = format!("--{}", param_ident.to_string().trim_start_matches('_').replace('_', "-"));
+                        flag_inits.push(quote_spanned! {arg_span=>
+                            let mut #ident = false;
+                        });
+                        flag_names.push(flag.clone());
+                        flag_idents.push(ident.clone());
+                        command_flags.push(quote_spanned! {arg_span=>
+                            #ident.then_some(#flag)
+                        });
+                        command_args.push(quote_spanned! {arg_span=>
+                            #ident: bool
+                        });
+                        param_descs.push(param_desc(pat, arg_idx, ty, "flag", None));
+                    } else if let Some(default) = default {
+                        optional_span.get_or_insert(arg_span);
+                        wrapper_stmts.push(quote_spanned! {arg_span=>
+                            let #ident = ::core::option::Option::unwrap_or_else(::std::iter::Iterator::next(&mut args), || ::std::string::ToString::to_string(#default));
+                            let #ident = #parse;
+                        });
+                        command_params.push(quote_spanned! {arg_span=>
+                            #ident.map(::std::string::ToString::to_string)
+                        });
+                        command_args.push(quote_spanned! {arg_span=>
+                            #ident: ::core::option::Option<&#ty>
+                        });
+                        param_descs.push(param_desc(pat, arg_idx, ty, "optional", Some(&default)));
+                    } else if let Some(inner_ty) = option_ty {
+                        optional_span.get_or_insert(arg_span);
+                        wrapper_stmts.push(quote_spanned! {arg_span=>
+                            let #ident = match ::std::iter::Iterator::next(&mut args) {
+                                ::core::option::Option::Some(#ident) => ::core::option::Option::Some(#parse),
+                                ::core::option::Option::None => ::core::option::Option::None,
+                            };
+                        });
+                        command_params.push(quote_spanned! {arg_span=>
+                            #ident.map(::std::string::ToString::to_string)
+                        });
+                        command_args.push(quote_spanned! {arg_span=>
+                            #ident: ::core::option::Option<&#inner_ty>
+                        });
+                        param_descs.push(param_desc(pat, arg_idx, inner_ty, "optional", None));
+                    } else if optional_span.is_some() {
+                        return quote_spanned! {arg_span=>
+                            compile_error!("required parameters of a bitbar::command must come before any optional or defaulted parameters");
+                        }.into()
+                    } else {
+                        required_count += 1;
+                        wrapper_stmts.push(quote_spanned! {arg_span=>
+                            let #ident = match ::std::iter::Iterator::next(&mut args) {
+                                ::core::option::Option::Some(#ident) => #parse,
+                                ::core::option::Option::None => {
+                                    ::bitbar::notify_error(&::std::format!("{}: wrong number of command arguments", #command_name_str), "");
+                                    ::std::process::exit(1)
+                                }
+                            };
+                        });
+                        command_params.push(quote_spanned! {arg_span=>
+                            ::core::option::Option::Some(::std::string::ToString::to_string(#ident))
+                        });
+                        command_args.push(quote_spanned! {arg_span=>
+                            #ident: &#ty
+                        });
+                        param_descs.push(param_desc(pat, arg_idx, ty, "required", None));
+                    }
+                    if notify_success.is_some() {
+                        let placeholder = format!("{{{param_name}}}");
+                        let value = if option_ty.is_some() {
+                            quote!(::core::option::Option::unwrap_or_default(::core::option::Option::map(::core::option::Option::as_ref(&#ident), ::std::string::ToString::to_string)))
+                        } else {
+                            quote!(::std::string::ToString::to_string(&#ident))
+                        };
+                        interpolations.push(quote! {
+                            let message = message.replace(#placeholder, &#value);
+                        });
+                    }
+                    wrapped_args.push(ident);
                 }
             }
         }
         if command_args.len() > 5 {
             command_args.insert(0, quote!(_: ::bitbar::flavor::SwiftBar));
         }
+        let output = wrap_output(quote!(#command_name(#(#wrapped_args),*)#awaitness));
+        // flags are only recognized before the positional arguments (up to `--`, after which everything is positional) or after the required ones
+        let flag_parsing = (!flag_names.is_empty()).then(|| quote! {
+            let mut args = ::std::collections::VecDeque::from(args);
+            #(#flag_inits)*
+            let mut end_of_flags = false;
+            loop {
+                match ::core::option::Option::map(args.front(), ::std::string::String::as_str) {
+                    ::core::option::Option::Some("--") => {
+                        end_of_flags = true;
+                        args.pop_front();
+                        break
+                    }
+                    #(::core::option::Option::Some(#flag_names) => #flag_idents = true,)*
+                    _ => break,
+                }
+                args.pop_front();
+            }
+            if !end_of_flags {
+                while args.len() > #required_count {
+                    match ::core::option::Option::map(args.back(), ::std::string::String::as_str) {
+                        #(::core::option::Option::Some(#flag_names) => #flag_idents = true,)*
+                        _ => break,
+                    }
+                    args.pop_back();
+                }
+            }
+        });
         (
             quote! {
-                match &*args {
-                    [#(#wrapper_params),*] => ::bitbar::CommandOutput::report(#command_name(#(#wrapped_args),*)#awaitness, #command_name_str),
-                    _ => {
-                        ::bitbar::notify("wrong number of command arguments");
-                        ::std::process::exit(1)
-                    }
+                #flag_parsing
+                let mut args = ::std::iter::IntoIterator::into_iter(args);
+                #(#wrapper_stmts)*
+                if ::std::iter::Iterator::next(&mut args).is_some() {
+                    ::bitbar::notify_error(&::std::format!("{}: wrong number of command arguments", #command_name_str), "");
+                    ::std::process::exit(1)
                 }
+                #message_init
+                #(#interpolations)*
+                #report(#output, #command_name_str)#report_awaitness
             },
-            quote!(::std::vec![
-                ::std::string::ToString::to_string(#command_name_str),
+            quote!(::bitbar::attr::Params::new_optional(exe, ::std::vec![
+                ::core::option::Option::Some(::std::string::ToString::to_string(#command_name_str)),
                 #(#command_params,)*
-            ]),
+            ], ::std::vec![#(#command_flags),*])),
             quote!(#(#command_args),*),
         )
     };
-    #[cfg(not(feature = "tokio"))] let (wrapper_ret, wrapper_body) = (quote!(), wrapper_body);
-    #[cfg(feature = "tokio")] let (wrapper_ret, wrapper_body) = (
-        quote!(-> ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<Output = ()>>>),
-        quote!(::std::boxed::Box::pin(async move { #wrapper_body })),
-    );
+    let (wrapper_ret, wrapper_body) = wrap_async(wrapper_body);
+    let registration = cfg!(feature = "inventory").then(|| quote! {
+        ::bitbar::inventory::submit! {
+            ::bitbar::CommandRegistration {
+                name: #name_const,
+                params: &[#(#param_descs),*],
+                run: #wrapper_name,
+            }
+        }
+    });
     TokenStream::from(quote! {
+        const #name_const: &str = #command_name_str;
+
         fn #wrapper_name(args: ::std::vec::Vec<::std::string::String>) #wrapper_ret {
             #command_fn
 
             #wrapper_body
         }
 
+        #registration
+
         #vis fn #command_name(#command_args) -> ::std::io::Result<::bitbar::attr::Params> {
-            ::std::io::Result::Ok(
-                ::bitbar::attr::Params::new(::std::env::current_exe()?.into_os_string().into_string().expect("non-UTF-8 plugin path"), #command_params)
-            )
+            let exe = ::bitbar::current_exe()?;
+            #command_params
         }
     })
 }
 
+fn wrap_async(wrapper_body: proc_macro2::TokenStream) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    #[cfg(not(feature = "async"))] let (wrapper_ret, wrapper_body) = (quote!(), wrapper_body);
+    #[cfg(feature = "async")] let (wrapper_ret, wrapper_body) = (
+        quote!(-> ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<Output = ()>>>),
+        quote!(::std::boxed::Box::pin(async move { #wrapper_body })),
+    );
+    (wrapper_ret, wrapper_body)
+}
+
+/// The path of the function used to report the output of a command, and the `.await` to append to its call, if any.
+fn report_output() -> (proc_macro2::TokenStream, Option<proc_macro2::TokenStream>) {
+    #[cfg(not(feature = "async"))] let report = (quote!(::bitbar::CommandOutput::report), None);
+    #[cfg(feature = "async")] let report = (quote!(::bitbar::AsyncCommandOutput::report), Some(quote!(.await)));
+    report
+}
+
+/// The name of a command parameter as shown in error messages and the output of the `bitbar-commands` subcommand: the identifier for patterns like `x` or `mut x`, or `argN` for other patterns like tuples.
+fn param_name(pat: &Pat, arg_idx: usize) -> String {
+    if let Pat::Ident(PatIdent { ident, .. }) = pat {
+        ident.unraw().to_string()
+    } else {
+        format!("arg{arg_idx}")
+    }
+}
+
+fn is_bool(ty: &Type) -> bool {
+    matches!(ty, Type::Path(TypePath { qself: None, path }) if path.is_ident("bool"))
+}
+
+/// Generates a `bitbar::CommandParam` describing a command parameter for the `bitbar-commands` subcommand.
+fn param_desc(pat: &Pat, arg_idx: usize, ty: &Type, kind: &str, default: Option<&LitStr>) -> proc_macro2::TokenStream {
+    let name = param_name(pat, arg_idx);
+    let ty = ty.to_token_stream().to_string().replace(' ', "");
+    let default = if let Some(default) = default {
+        quote!(::core::option::Option::Some(#default))
+    } else {
+        quote!(::core::option::Option::None)
+    };
+    quote! {
+        ::bitbar::CommandParam {
+            name: #name,
+            ty: #ty,
+            kind: #kind,
+            default: #default,
+        }
+    }
+}
+
+/// Parses a duration like `30s` or `500ms` into milliseconds.
+fn parse_duration(duration: &str) -> Option<u64> {
+    let unit_start = duration.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = duration.split_at(unit_start);
+    let number = number.parse::<u64>().ok()?;
+    let factor = match unit.trim() {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return None,
+    };
+    number.checked_mul(factor)
+}
+
+fn is_swiftbar(ty: &Type) -> bool {
+    matches!(ty, Type::Path(TypePath { qself: None, path }) if path.segments.last().is_some_and(|segment| segment.ident == "SwiftBar"))
+}
+
+fn is_vars(ty: &Type) -> bool {
+    matches!(ty, Type::Path(TypePath { qself: None, path }) if path.is_ident("Vars"))
+}
+
+/// A plugin variable declared in `#[bitbar::main(vars(...))]`, of the form `NAME: Type` or `NAME: Type = default`.
+struct VarDecl {
+    name: Ident,
+    ty: Type,
+    default: Option<LitStr>,
+}
+
+impl parse::Parse for VarDecl {
+    fn parse(input: parse::ParseStream<'_>) -> Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        let default = if input.parse::<Option<Token![=]>>()?.is_some() {
+            let negative = input.parse::<Option<Token![-]>>()?.is_some();
+            let sign = if negative { "-" } else { "" };
+            Some(match input.parse()? {
+                Lit::Str(lit) if !negative => lit,
+                Lit::Int(lit) => LitStr::new(&format!("{sign}{}", lit.base10_digits()), lit.span()),
+                Lit::Float(lit) => LitStr::new(&format!("{sign}{}", lit.base10_digits()), lit.span()),
+                Lit::Bool(lit) if !negative => LitStr::new(&lit.value.to_string(), lit.span),
+                lit => return Err(Error::new(lit.span(), "plugin variable defaults must be string, number, or bool literals")),
+            })
+        } else {
+            None
+        };
+        Ok(Self { name, ty, default })
+    }
+}
+
+/// The xbar variable type for a plugin variable of the given Rust type.
+fn var_kind(ty: &Type) -> &'static str {
+    let Type::Path(TypePath { qself: None, path }) = option_inner_type(ty).unwrap_or(ty) else { return "string" };
+    let Some(ident) = path.get_ident() else { return "string" };
+    match &*ident.to_string() {
+        "bool" => "boolean",
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "f32" | "f64" => "number",
+        _ => "string",
+    }
+}
+
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(TypePath { qself: None, path }) = ty else { return None };
+    let segment = path.segments.last()?;
+    if segment.ident != "Option" { return None }
+    let PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) = &segment.arguments else { return None };
+    match args.iter().exactly_one() {
+        Ok(GenericArgument::Type(inner)) => Some(inner),
+        _ => None,
+    }
+}
+
 /// Defines a function that is called when no other `bitbar::command` matches.
 ///
-/// * It must take as arguments the subcommand name as a `String` and the remaining arguments as a `Vec<String>`.
-/// * It must return a member of the `bitbar::CommandOutput` trait.
-/// * It can be a `fn` or an `async fn`. In the latter case, `tokio`'s threaded runtime will be used. (This requires the `tokio` feature, which is on by default.)
+/// * It must take as arguments the subcommand name as a `String` and the remaining arguments as a type implementing `bitbar::FromArgs`, such as `Vec<String>`. If the arguments can't be parsed, an error notification is sent.
+/// * Alternatively, with `#[bitbar::fallback_command(clap)]` (requires the `clap` feature), the remaining arguments can be taken as a type implementing `clap::Parser`, which is parsed with the subcommand name as the binary name.
+/// * It must return a member of the `bitbar::CommandOutput` trait, or of the `bitbar::AsyncCommandOutput` trait if one of the async features is enabled.
+/// * It can be a `fn` or an `async fn`. In the latter case, `tokio`'s threaded runtime will be used. (This requires the `tokio` feature, which is on by default.) Alternatively, the `async-std` feature can be enabled instead of `tokio` to use `async-std`'s runtime, or the `futures-executor` feature to run the function using `futures::executor::block_on` without any runtime.
 ///
 /// If this attribute isn't used, `bitbar` will handle unknown subcommands by sending a notification and exiting.
 ///
 /// The function must also be registered via `#[bitbar::main(fallback_command = "...")]`.
 #[proc_macro_attribute]
-pub fn fallback_command(_: TokenStream, item: TokenStream) -> TokenStream {
+pub fn fallback_command(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args with Punctuated::<Meta, Token![,]>::parse_terminated);
+    let mut clap = false;
+    for arg in args {
+        if arg.path().is_ident("clap") {
+            if let Err(e) = arg.require_path_only() { return e.into_compile_error().into() }
+            clap = true;
+        } else {
+            return quote_spanned! {arg.span()=>
+                compile_error!("unexpected bitbar::fallback_command arguments");
+            }.into()
+        }
+    }
     let fallback_fn = parse_macro_input!(item as ItemFn);
     let asyncness = &fallback_fn.sig.asyncness;
     let fn_name = &fallback_fn.sig.ident;
-    let wrapper_name = Ident::new(&format!("bitbar_{fn_name}_wrapper"), Span::call_site());
+    let wrapper_name = Ident::new(&format!("bitbar_{}_wrapper", fn_name.unraw()), Span::call_site());
     let awaitness = asyncness.as_ref().map(|_| quote!(.await));
-    let wrapper_body = quote! {
-        ::bitbar::CommandOutput::report(#fn_name(cmd.clone(), args)#awaitness, &cmd);
+    let (report, report_awaitness) = report_output();
+    let wrapper_body = if clap {
+        let Some(FnArg::Typed(PatType { ty, .. })) = fallback_fn.sig.inputs.iter().nth(1) else {
+            return quote_spanned! {fallback_fn.sig.inputs.span()=>
+                compile_error!("a bitbar::fallback_command(clap) function must take the subcommand name and a parameter whose type implements clap::Parser");
+            }.into()
+        };
+        quote! {
+            match <#ty as ::bitbar::clap::Parser>::try_parse_from(::std::iter::Iterator::chain(::std::iter::once(::std::clone::Clone::clone(&cmd)), args)) {
+                ::core::result::Result::Ok(args) => #report(#fn_name(::std::clone::Clone::clone(&cmd), args)#awaitness, &cmd)#report_awaitness,
+                ::core::result::Result::Err(e) => {
+                    if e.use_stderr() {
+                        let message = ::std::string::ToString::to_string(&e);
+                        ::bitbar::notify_error(message.lines().next().unwrap_or_default(), &message);
+                    }
+                    e.exit()
+                }
+            }
+        }
+    } else {
+        quote! {
+            match ::bitbar::FromArgs::from_args(&cmd, args) {
+                ::core::result::Result::Ok(args) => #report(#fn_name(::std::clone::Clone::clone(&cmd), args)#awaitness, &cmd)#report_awaitness,
+                ::core::result::Result::Err(e) => {
+                    ::bitbar::notify_error(&::std::format!("{cmd}: {e}"), &::std::format!("{e:?}"));
+                    ::std::process::exit(1)
+                }
+            }
+        }
     };
-    #[cfg(not(feature = "tokio"))] let (wrapper_ret, wrapper_body) = (quote!(), wrapper_body);
-    #[cfg(feature = "tokio")] let (wrapper_ret, wrapper_body) = (
-        quote!(-> ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<Output = ()>>>),
-        quote!(::std::boxed::Box::pin(async move { #wrapper_body })),
-    );
+    let (wrapper_ret, wrapper_body) = wrap_async(wrapper_body);
     TokenStream::from(quote! {
         fn #wrapper_name(cmd: ::std::string::String, args: ::std::vec::Vec<::std::string::String>) #wrapper_ret {
             #fallback_fn
@@ -166,22 +599,46 @@ pub fn fallback_command(_: TokenStream, item: TokenStream) -> TokenStream {
 
 /// Annotate your `main` function with this.
 ///
-/// * It can optionally take an argument of type `bitbar::Flavor`.
+/// * It can optionally take an argument of type `bitbar::Flavor` (or `bitbar::flavor::SwiftBar`, see `flavor` below), and an argument of type `Vars` (see `vars` below).
 /// * It must return a member of the `bitbar::MainOutput` trait.
-/// * It can be a `fn` or an `async fn`. In the latter case, `tokio`'s threaded runtime will be used. (This requires the `tokio` feature, which is on by default.)
+/// * It can be a `fn` or an `async fn`. In the latter case, `tokio`'s threaded runtime will be used. (This requires the `tokio` feature, which is on by default.) Alternatively, the `async-std` feature can be enabled instead of `tokio` to use `async-std`'s runtime, or the `futures-executor` feature to run the function using `futures::executor::block_on` without any runtime.
+///
+/// If the binary is called with `--help` or `-h` as its only argument (and no command of that name exists), the package name, version, and description from `Cargo.toml` are printed along with a list of the registered commands. `--version` or `-V` prints the package name and version.
+///
+/// The reserved `bitbar-click` subcommand runs the handlers registered using `bitbar::ContentItem::on_click`. To find the handler, the `main` function is called again, but its output is discarded.
+///
+/// The reserved `bitbar-commands` subcommand prints a JSON array of all registered commands along with the name, type, kind (`required`, `optional`, `flag`, `varargs`, or `clap`), and default value of each parameter, so external tooling can check that the commands referenced by a menu exist. This and the registration of `vars` (see below) require the `inventory` feature.
+///
+/// If the binary is called with `--bitbar-preview` as its first argument, the menu is rendered as a human-readable tree with each item's parameters annotated (see `bitbar::Menu::preview`) instead of in the format expected by BitBar, regardless of the environment. This is useful for inspecting plugin output in CI. When the binary is run without arguments directly in a terminal, rather than by a BitBar implementation, the preview is printed with colors instead, so the plugin's output is readable during development. To see the raw output, pipe it into another command, e.g. `./my-plugin.o | cat`. Similarly, with `--bitbar-validate`, any issues found by `bitbar::Menu::validate` for the detected flavor are printed one per line instead of the menu. This is used by `cargo bitbar lint --run`.
 ///
 /// The `main` attribute optionally takes the following parameter:
 ///
-/// * `commands` can be set to a list of subcommand names (in parentheses) which will be used if the binary is called with command-line parameters.
-/// * `fallback_command` can be set to a function name (in quotes) which will be used if the binary is called with command-line parameters and the first parameter does not match any subcommand.
+/// * `commands` can be set to a list of subcommand names (in parentheses) which will be used if the binary is called with command-line parameters. This is optional since commands are registered automatically, unless the `inventory` feature is disabled.
+/// * `fallback_command` can be set to a function name (in quotes) which will be used if the binary is called with command-line parameters and the first parameter does not match any subcommand. Without a fallback command, an error notification is sent and the available subcommands are listed on stderr.
 /// * `error_template_image` can be set to a path (relative to the current file) to a PNG file which will be used as the template image for the menu when displaying an error.
+/// * `error_formatter` can be set to the path (in quotes) of a function with the signature of `bitbar::ErrorFormatter`, which formats the menu displayed when the `main` function returns an error, e.g. to add custom branding or a link for reporting the error. See `bitbar::set_error_formatter`.
+/// * `notifier` can be set to the path (in quotes) of a function returning a `bitbar::Notifier`, which configures the notifications sent for errors from commands and argument parsing as well as for successful commands, e.g. to set a custom summary or sound. See `bitbar::set_notifier`.
+/// * `on_drop` can be set to the name of a command (in quotes) which is run when content is dropped onto the plugin's menu bar item in SwiftBar, for plugins declaring `<swiftbar.droptypes>`. The command is called with the paths of the dropped files, or the dropped text as a single argument, so it's usually declared with `#[bitbar::command(varargs)]`. It can use `bitbar::flavor::swiftbar::Dropped::check` to tell these apart. If `commands` is also given and there is no `fallback_command`, `on_drop` must name one of the listed commands.
+/// * `timeout` can be set to a duration (in quotes) like `"30s"` or `"500ms"`. If the `main` function doesn't return within this time, a menu saying that the plugin timed out is displayed instead, followed by the menu most recently passed to `bitbar::partial` if any. For a non-`async` `main` function, the function runs on a separate thread, so its return type must be `Send`.
+/// * `on_error` can be set to a comma-separated list (in quotes) of where errors from commands and argument parsing are reported: `notify` sends a notification (the default), `stderr` prints the error, and `log` appends it to `errors.log` in the plugin cache directory (see `bitbar::cache_dir`), keeping one rotated old log file. For example, `on_error = "notify, log"` keeps a log in addition to sending notifications.
+/// * `flavor` can be set to `"swiftbar"` (requires the `assume-flavor` feature) for plugins that only support SwiftBar. The plugin then behaves as if it were running in the latest supported version of SwiftBar regardless of the environment (see `bitbar::flavor::SwiftBar::assume`): `bitbar::Flavor::check` always returns `Flavor::SwiftBar`, menus are rendered using SwiftBar syntax, and the `main` function can take an argument of type `bitbar::flavor::SwiftBar` instead of `bitbar::Flavor`.
+/// * `single_instance` makes the plugin exit immediately if another instance is still generating its menu, e.g. because it takes longer than the refresh interval (see `bitbar::lock`). With `single_instance = "cached"`, the last menu is saved to the plugin cache directory and printed again by the overlapping instance. This is not useful for streamable plugins, and only available on Unix.
+/// * `vars` can be set to a list of plugin variables (in parentheses) of the form `NAME: Type` or `NAME: Type = default`, e.g. `vars(API_TOKEN: String = "", INTERVAL: u64 = 60)`. This generates a struct named `Vars` with a lowercase field for each variable, implementing `bitbar::PluginConfig`. If the `main` function takes an argument of type `Vars`, the variables are read from the environment before calling it, and an error menu is displayed if this fails. The variables are also registered so that `cargo bitbar meta --vars` can add them to the plugin metadata.
 #[proc_macro_attribute]
 pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args with Punctuated::<Meta, Token![,]>::parse_terminated);
     let mut error_template_image = quote!(::core::option::Option::None);
+    let mut error_formatter = None;
+    let mut notifier = None;
+    let mut on_drop = None;
     let mut fallback_lit = None;
     let mut subcommand_names = Vec::default();
     let mut subcommand_fns = Vec::default();
+    let mut vars = None;
+    let mut timeout = None;
+    let mut error_sinks = None;
+    let mut assume_swiftbar = false;
+    let mut single_instance = None;
     for arg in args {
         if arg.path().is_ident("commands") {
             match arg.require_list() {
@@ -189,7 +646,8 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
                     Ok(nested) => for cmd in nested {
                         match cmd.require_path_only() {
                             Ok(path) => if let Some(ident) = path.get_ident() {
-                                subcommand_names.push(ident.to_string());
+                                let ident = ident.unraw();
+                                subcommand_names.push(Ident::new(&format!("BITBAR_{}_NAME", ident.to_string().to_uppercase()), ident.span()));
                                 subcommand_fns.push(Ident::new(&format!("bitbar_{ident}_wrapper"), ident.span()));
                             } else {
                                 return quote_spanned! {cmd.span()=>
@@ -206,7 +664,7 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
         } else if arg.path().is_ident("error_template_image") {
             match arg.require_name_value() {
                 Ok(MetaNameValue { value, .. }) => if let Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }) = value {
-                    error_template_image = quote!(::core::option::Option::Some(::bitbar::attr::Image::from(&include_bytes!(#lit)[..])));
+                    error_template_image = quote!(::core::option::Option::Some(::bitbar::include_image!(#lit)));
                 } else {
                     return quote_spanned! {value.span()=>
                         compile_error!("error_template_image value must be a string literal");
@@ -214,10 +672,122 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
                 },
                 Err(e) => return e.into_compile_error().into(),
             }
+        } else if arg.path().is_ident("error_formatter") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => match lit.parse::<Path>() {
+                    Ok(path) => error_formatter = Some(quote!(::bitbar::set_error_formatter(#path);)),
+                    Err(e) => return e.into_compile_error().into(),
+                },
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("error_formatter value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("notifier") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => match lit.parse::<Path>() {
+                    Ok(path) => notifier = Some(quote!(::bitbar::set_notifier(#path());)),
+                    Err(e) => return e.into_compile_error().into(),
+                },
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("notifier value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("on_drop") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => on_drop = Some(lit.clone()),
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("on_drop value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("timeout") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => match parse_duration(&lit.value()) {
+                    Some(millis) => timeout = Some(quote!(::std::time::Duration::from_millis(#millis))),
+                    None => return quote_spanned! {lit.span()=>
+                        compile_error!("timeout must be a number followed by one of the units ms, s, m, or h, e.g. \"30s\"");
+                    }.into(),
+                },
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("timeout value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("on_error") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => {
+                    let (mut notify, mut stderr, mut log) = (false, false, false);
+                    for sink in lit.value().split(',') {
+                        match sink.trim() {
+                            "notify" => notify = true,
+                            "stderr" => stderr = true,
+                            "log" => log = true,
+                            _ => return quote_spanned! {lit.span()=>
+                                compile_error!("on_error must be a comma-separated list of notify, stderr, and log");
+                            }.into(),
+                        }
+                    }
+                    error_sinks = Some(quote! {
+                        ::bitbar::set_error_sinks(::bitbar::ErrorSinks {
+                            notify: #notify,
+                            stderr: #stderr,
+                            log: #log,
+                        });
+                    });
+                }
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("on_error value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("flavor") {
+            match arg.require_name_value() {
+                Ok(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => if lit.value() == "swiftbar" {
+                    if cfg!(not(feature = "assume-flavor")) {
+                        return quote_spanned! {lit.span()=>
+                            compile_error!("the flavor argument of bitbar::main requires the assume-flavor feature of the bitbar crate");
+                        }.into()
+                    }
+                    assume_swiftbar = true;
+                } else {
+                    return quote_spanned! {lit.span()=>
+                        compile_error!("the only supported value for flavor is \"swiftbar\"");
+                    }.into()
+                },
+                Ok(MetaNameValue { value, .. }) => return quote_spanned! {value.span()=>
+                    compile_error!("flavor value must be a string literal");
+                }.into(),
+                Err(e) => return e.into_compile_error().into(),
+            }
+        } else if arg.path().is_ident("single_instance") {
+            match arg {
+                Meta::Path(_) => single_instance = Some(false),
+                Meta::NameValue(MetaNameValue { value: Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }), .. }) => if lit.value() == "cached" {
+                    single_instance = Some(true);
+                } else {
+                    return quote_spanned! {lit.span()=>
+                        compile_error!("the only supported value for single_instance is \"cached\"");
+                    }.into()
+                },
+                _ => return quote_spanned! {arg.span()=>
+                    compile_error!("single_instance must be used without a value or with the string literal \"cached\"");
+                }.into(),
+            }
+        } else if arg.path().is_ident("vars") {
+            match arg.require_list() {
+                Ok(list) => match list.parse_args_with(Punctuated::<VarDecl, Token![,]>::parse_terminated) {
+                    Ok(decls) => vars = Some(decls),
+                    Err(e) => return e.into_compile_error().into(),
+                },
+                Err(e) => return e.into_compile_error().into(),
+            }
         } else if arg.path().is_ident("fallback_command") {
             match arg.require_name_value() {
                 Ok(MetaNameValue { value, .. }) => if let Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }) = value {
-                    fallback_lit = Some(Ident::new(&format!("bitbar_{}_wrapper", lit.value()), lit.span()));
+                    let name = lit.value();
+                    fallback_lit = Some(Ident::new(&format!("bitbar_{}_wrapper", name.strip_prefix("r#").unwrap_or(&name)), lit.span()));
                 } else {
                     return quote_spanned! {value.span()=>
                         compile_error!("fallback_command value must be a string literal");
@@ -234,42 +804,175 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
     let main_fn = parse_macro_input!(item as ItemFn);
     let asyncness = &main_fn.sig.asyncness;
     let inner_params = &main_fn.sig.inputs;
-    let inner_args = if inner_params.len() >= 1 {
-        quote!(::bitbar::Flavor::check())
+    let mut takes_vars = false;
+    let inner_args = inner_params.iter().map(|param| match param {
+        FnArg::Typed(PatType { ty, .. }) if is_vars(ty) => {
+            takes_vars = true;
+            quote!(vars)
+        }
+        FnArg::Typed(PatType { ty, .. }) if assume_swiftbar && is_swiftbar(ty) => quote!(::bitbar::flavor::SwiftBar::assume()),
+        _ => if assume_swiftbar {
+            quote!(::bitbar::Flavor::SwiftBar(::bitbar::flavor::SwiftBar::assume()))
+        } else {
+            quote!(::bitbar::Flavor::check())
+        },
+    }).collect_vec();
+    let assume_flavor = assume_swiftbar.then(|| quote! {
+        ::bitbar::flavor::set_assumed(::bitbar::Flavor::SwiftBar(::bitbar::flavor::SwiftBar::assume()));
+    });
+    #[cfg(not(feature = "async"))] let (cmd_awaitness, awaitness) = (quote!(), quote!());
+    let instance_lock = single_instance.map(|cached| quote!(let _instance_lock = ::bitbar::lock::main_lock(#cached);));
+    #[cfg(not(feature = "async"))] let write_output = |output: proc_macro2::TokenStream| if single_instance == Some(true) {
+        quote!(::bitbar::lock::main_output(#output, #error_template_image);)
+    } else {
+        quote!(::bitbar::MainOutput::main_output(#output, #error_template_image);)
+    };
+    #[cfg(feature = "async")] let (cmd_awaitness, awaitness) = (quote!(.await), asyncness.as_ref().map(|_| quote!(.await)));
+    #[cfg(feature = "async")] let write_output = |output: proc_macro2::TokenStream| if single_instance == Some(true) {
+        quote!(::bitbar::lock::main_output_async(#output, #error_template_image).await;)
+    } else {
+        quote!(::bitbar::AsyncMainOutput::main_output(#output, #error_template_image).await;)
+    };
+    let wrapper_body = if let Some(timeout) = timeout {
+        let main_call = if asyncness.is_some() {
+            quote!(::bitbar::timeout(#timeout, main_inner(#(#inner_args),*)).await)
+        } else {
+            quote!(::bitbar::timeout_blocking(#timeout, move || main_inner(#(#inner_args),*)))
+        };
+        let output = write_output(quote!(output));
+        let timeout_output = write_output(quote!(::bitbar::Menu::from(::bitbar::TimeoutError(#timeout))));
+        quote! {
+            match #main_call {
+                ::core::option::Option::Some(output) => { #output }
+                ::core::option::Option::None => { #timeout_output }
+            }
+        }
+    } else {
+        write_output(quote!(main_inner(#(#inner_args),*)#awaitness))
+    };
+    let vars_error_body = write_output(quote!(::bitbar::Menu::from(e)));
+    let wrapper_body = if takes_vars {
+        quote! {
+            match <Vars as ::bitbar::PluginConfig>::from_env() {
+                ::core::result::Result::Ok(vars) => { #wrapper_body }
+                ::core::result::Result::Err(e) => { #vars_error_body }
+            }
+        }
+    } else {
+        wrapper_body
+    };
+    let click_body = quote!(::core::mem::drop(main_inner(#(#inner_args),*)#awaitness););
+    let click_body = if takes_vars {
+        quote! {
+            match <Vars as ::bitbar::PluginConfig>::from_env() {
+                ::core::result::Result::Ok(vars) => { #click_body }
+                ::core::result::Result::Err(e) => {
+                    ::bitbar::notify_error(&::std::string::ToString::to_string(&e), &::std::format!("{e:?}"));
+                    ::std::process::exit(1)
+                }
+            }
+        }
+    } else {
+        click_body
+    };
+    let vars_items = if let Some(vars) = vars {
+        let mut fields = Vec::default();
+        let mut registrations = Vec::default();
+        for VarDecl { name, ty, default } in vars {
+            let name_str = name.to_string();
+            let field = Ident::new(&name_str.to_lowercase(), name.span());
+            let kind = var_kind(&ty);
+            let default_attr = default.as_ref().map(|default| quote!(, default = #default));
+            let default = default.map(|default| default.value()).unwrap_or_default();
+            fields.push(quote! {
+                #[config(rename = #name_str #default_attr)]
+                #field: #ty
+            });
+            registrations.push(quote! {
+                ::bitbar::PluginVar {
+                    name: #name_str,
+                    kind: #kind,
+                    default: #default,
+                }
+            });
+        }
+        let vars_registration = cfg!(feature = "inventory").then(|| quote! {
+            ::bitbar::inventory::submit! {
+                ::bitbar::VarsRegistration {
+                    vars: &[#(#registrations),*],
+                }
+            }
+        });
+        quote! {
+            /// The plugin variables declared in `#[bitbar::main(vars(...))]`.
+            #[derive(::bitbar::PluginConfig)]
+            struct Vars {
+                #(#fields,)*
+            }
+
+            #vars_registration
+        }
     } else {
         quote!()
     };
-    #[cfg(not(feature = "tokio"))] let (cmd_awaitness, wrapper_body) = (
-        quote!(),
-        quote!(::bitbar::MainOutput::main_output(main_inner(#inner_args), #error_template_image);),
-    );
-    #[cfg(feature = "tokio")] let awaitness = asyncness.as_ref().map(|_| quote!(.await));
-    #[cfg(feature = "tokio")] let (cmd_awaitness, wrapper_body) = (
-        quote!(.await),
-        quote!(::bitbar::AsyncMainOutput::main_output(main_inner(#inner_args)#awaitness, #error_template_image).await;),
-    );
+    // commands registered via inventory aren't known here, so on_drop can only be checked against an explicit command list
+    let on_drop_check = on_drop.as_ref().filter(|_| !subcommand_names.is_empty() && fallback_lit.is_none()).map(|on_drop| quote_spanned! {on_drop.span()=>
+        const _: () = ::core::assert!(#(::bitbar::const_str_eq(#on_drop, #subcommand_names))||*, "on_drop must be the name of one of the commands listed in commands(...)");
+    });
     let fallback = if let Some(fallback_lit) = fallback_lit {
         quote!(#fallback_lit(subcommand, args.collect())#cmd_awaitness)
     } else {
-        quote! {{
-            ::bitbar::notify(format!("no such subcommand: {}", subcommand));
-            ::std::process::exit(1)
-        }}
+        quote!(::bitbar::unknown_subcommand(&subcommand))
+    };
+    let args = if let Some(on_drop) = on_drop {
+        quote!(::bitbar::args_with_drop(#on_drop))
+    } else {
+        quote!(::bitbar::args())
     };
     let wrapper_body = quote!({
         //TODO set up a more friendly panic hook (similar to human-panic but rendering the panic message as a menu)
-        let mut args = ::std::env::args();
+        #assume_flavor
+        #error_sinks
+        #error_formatter
+        #notifier
+        let mut args = #args;
         let _ = args.next().expect("missing program name");
-        if let ::core::option::Option::Some(subcommand) = args.next() {
+        let mut subcommand = args.next();
+        if subcommand.as_deref() == ::core::option::Option::Some("--bitbar-preview") {
+            ::bitbar::set_preview_mode();
+            subcommand = ::core::option::Option::None;
+        } else if subcommand.as_deref() == ::core::option::Option::Some("--bitbar-validate") {
+            ::bitbar::set_validate_mode();
+            subcommand = ::core::option::Option::None;
+        } else if subcommand.is_none() {
+            ::bitbar::detect_terminal_mode();
+        }
+        if let ::core::option::Option::Some(subcommand) = subcommand {
             match &*subcommand {
                 #(
-                    #subcommand_names => #subcommand_fns(args.collect())#cmd_awaitness,
+                    subcommand if subcommand == #subcommand_names => #subcommand_fns(args.collect())#cmd_awaitness,
                 )*
-                _ => #fallback,
+                _ => if let ::core::option::Option::Some(command) = ::bitbar::registered_command(&subcommand) {
+                    command(args.collect())#cmd_awaitness
+                } else if let ::core::option::Option::Some(command) = ::bitbar::registered_subcommands(&subcommand) {
+                    command(subcommand, args.collect())#cmd_awaitness
+                } else if subcommand == "bitbar-click" {
+                    ::bitbar::set_click_target(args.next());
+                    #click_body
+                    ::bitbar::click_target_not_found()
+                } else if subcommand == "--help" || subcommand == "-h" {
+                    ::bitbar::print_help(::core::env!("CARGO_PKG_NAME"), ::core::env!("CARGO_PKG_VERSION"), ::core::env!("CARGO_PKG_DESCRIPTION"))
+                } else if subcommand == "--version" || subcommand == "-V" {
+                    ::std::println!("{} {}", ::core::env!("CARGO_PKG_NAME"), ::core::env!("CARGO_PKG_VERSION"))
+                } else {
+                    #fallback
+                },
             }
         } else {
+            #instance_lock
             #wrapper_body
         }
+        ::bitbar::join_background_tasks()#cmd_awaitness;
     });
     #[cfg(feature = "tokio")] let wrapper_body = quote!({
         ::bitbar::tokio::runtime::Builder::new_multi_thread()
@@ -278,11 +981,543 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
             .unwrap()
             .block_on(async #wrapper_body)
     });
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))] let wrapper_body = quote!({
+        ::bitbar::async_std::task::block_on(async #wrapper_body)
+    });
+    #[cfg(all(feature = "futures-executor", not(any(feature = "async-std", feature = "tokio"))))] let wrapper_body = quote!({
+        ::bitbar::futures::executor::block_on(async #wrapper_body)
+    });
     let ret = main_fn.sig.output;
     let inner_body = main_fn.block;
     TokenStream::from(quote! {
+        #vars_items
+        #on_drop_check
+
         #asyncness fn main_inner(#inner_params) #ret #inner_body
 
-        fn main() #wrapper_body
+        fn main() -> ::std::process::ExitCode {
+            #wrapper_body
+            ::bitbar::exit_code()
+        }
     })
 }
+
+/// Implements `bitbar::Subcommands` for an enum, with one subcommand per variant.
+///
+/// The subcommand name is the name of the variant converted to kebab-case, e.g. `OpenUrl` and `OpenURL` both become `open-url`. This can be overridden using `#[subcommand(rename = "...")]` on the variant.
+///
+/// The fields of each variant (named or unnamed) are the positional arguments of the subcommand, and must implement `FromStr` (with errors implementing `Debug` and `Display`) and `ToString`.
+///
+/// Use a function annotated with `#[bitbar::command(subcommands)]` to handle the subcommands.
+#[proc_macro_derive(Subcommands, attributes(subcommand))]
+pub fn derive_subcommands(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let Data::Enum(DataEnum { variants, .. }) = &input.data else {
+        return quote_spanned! {input.span()=>
+            compile_error!("bitbar::Subcommands can only be derived for enums");
+        }.into()
+    };
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut names = Vec::default();
+    let mut parse_arms = Vec::default();
+    let mut to_args_arms = Vec::default();
+    for variant in variants {
+        let mut name = None;
+        for attr in &variant.attrs {
+            if attr.path().is_ident("subcommand") {
+                if let Err(e) = attr.parse_nested_meta(|meta| if meta.path.is_ident("rename") {
+                    name = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("unexpected bitbar::Subcommands variant attribute"))
+                }) {
+                    return e.into_compile_error().into()
+                }
+            }
+        }
+        let name = name.unwrap_or_else(|| kebab_case(&variant.ident.to_string()));
+        let variant_ident = &variant.ident;
+        let field_idents = variant.fields.iter().enumerate().map(|(idx, field)| field.ident.clone().unwrap_or_else(|| Ident::new(&format!("field{idx}"), field.span()))).collect_vec();
+        let binding_idents = variant.fields.iter().enumerate().map(|(idx, field)| format_ident!("__bitbar_field{idx}", span = field.span())).collect_vec();
+        let num_fields = field_idents.len();
+        let parsed_fields = binding_idents.iter().enumerate().map(|(idx, ident)| quote_spanned! {ident.span()=>
+            match #ident.parse() {
+                ::core::result::Result::Ok(value) => value,
+                ::core::result::Result::Err(e) => return ::core::result::Result::Err(::bitbar::SubcommandError::parse(name, #idx, &e)),
+            }
+        }).collect_vec();
+        let (construct, pattern) = match &variant.fields {
+            Fields::Named(_) => (
+                quote!(Self::#variant_ident { #(#field_idents: #parsed_fields),* }),
+                quote!(Self::#variant_ident { #(#field_idents),* }),
+            ),
+            Fields::Unnamed(_) => (
+                quote!(Self::#variant_ident(#(#parsed_fields),*)),
+                quote!(Self::#variant_ident(#(#field_idents),*)),
+            ),
+            Fields::Unit => (
+                quote!(Self::#variant_ident),
+                quote!(Self::#variant_ident),
+            ),
+        };
+        parse_arms.push(quote! {
+            #name => {
+                let [#(#binding_idents),*] = match <[::std::string::String; #num_fields] as ::core::convert::TryFrom<_>>::try_from(args) {
+                    ::core::result::Result::Ok(args) => args,
+                    ::core::result::Result::Err(args) => return ::core::result::Result::Err(::bitbar::SubcommandError::WrongNumberOfArguments {
+                        subcommand: ::std::string::ToString::to_string(name),
+                        expected: #num_fields,
+                        found: args.len(),
+                    }),
+                };
+                ::core::result::Result::Ok(#construct)
+            }
+        });
+        to_args_arms.push(quote! {
+            #pattern => ::std::vec![
+                ::std::string::ToString::to_string(#name),
+                #(::std::string::ToString::to_string(#field_idents),)*
+            ],
+        });
+        names.push(name);
+    }
+    TokenStream::from(quote! {
+        impl #impl_generics ::bitbar::Subcommands for #ty #ty_generics #where_clause {
+            const NAMES: &'static [&'static str] = &[#(#names),*];
+
+            fn parse(name: &str, args: ::std::vec::Vec<::std::string::String>) -> ::core::result::Result<Self, ::bitbar::SubcommandError> {
+                match name {
+                    #(#parse_arms)*
+                    _ => ::core::result::Result::Err(::bitbar::SubcommandError::Unknown(::std::string::ToString::to_string(name))),
+                }
+            }
+
+            fn to_args(&self) -> ::std::vec::Vec<::std::string::String> {
+                match self {
+                    #(#to_args_arms)*
+                }
+            }
+        }
+    })
+}
+
+/// Implements `bitbar::MenuOptions`, `FromStr`, and `Display` for a fieldless enum, for generating settings-style submenus.
+///
+/// Each variant is represented by its name converted to kebab-case (e.g. `DarkMode` becomes `dark-mode`), which is used by the `FromStr` and `Display` implementations so the enum can be used as a `#[bitbar::command]` parameter.
+/// The menu item text defaults to the variant name with spaces between words (e.g. `Dark Mode`) and can be overridden using `#[menu_option(label = "...")]` on the variant.
+#[proc_macro_derive(MenuOptions, attributes(menu_option))]
+pub fn derive_menu_options(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let Data::Enum(DataEnum { variants, .. }) = &input.data else {
+        return quote_spanned! {input.span()=>
+            compile_error!("bitbar::MenuOptions can only be derived for enums");
+        }.into()
+    };
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut variant_idents = Vec::default();
+    let mut names = Vec::default();
+    let mut labels = Vec::default();
+    for variant in variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return quote_spanned! {variant.span()=>
+                compile_error!("bitbar::MenuOptions can only be derived for enums without fields");
+            }.into()
+        }
+        let mut label = None;
+        for attr in &variant.attrs {
+            if attr.path().is_ident("menu_option") {
+                if let Err(e) = attr.parse_nested_meta(|meta| if meta.path.is_ident("label") {
+                    label = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("unexpected bitbar::MenuOptions variant attribute"))
+                }) {
+                    return e.into_compile_error().into()
+                }
+            }
+        }
+        let name = kebab_case(&variant.ident.to_string());
+        labels.push(label.unwrap_or_else(|| name.split('-').map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        }).join(" ")));
+        names.push(name);
+        variant_idents.push(&variant.ident);
+    }
+    TokenStream::from(quote! {
+        impl #impl_generics ::bitbar::MenuOptions for #ty #ty_generics #where_clause {
+            const OPTIONS: &'static [Self] = &[#(Self::#variant_idents),*];
+
+            fn name(&self) -> &'static str {
+                match self {
+                    #(Self::#variant_idents => #names,)*
+                }
+            }
+
+            fn label(&self) -> &'static str {
+                match self {
+                    #(Self::#variant_idents => #labels,)*
+                }
+            }
+        }
+
+        impl #impl_generics ::core::str::FromStr for #ty #ty_generics #where_clause {
+            type Err = ::bitbar::UnknownOptionError;
+
+            fn from_str(s: &str) -> ::core::result::Result<Self, ::bitbar::UnknownOptionError> {
+                match s {
+                    #(#names => ::core::result::Result::Ok(Self::#variant_idents),)*
+                    _ => ::core::result::Result::Err(::bitbar::UnknownOptionError(::std::string::ToString::to_string(s))),
+                }
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Display for #ty #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.write_str(::bitbar::MenuOptions::name(self))
+            }
+        }
+    })
+}
+
+/// Implements `bitbar::PluginConfig` for a struct with named fields, reading each field from an environment variable.
+///
+/// By default, the variable name is the field name in uppercase. It can be overridden using `#[config(rename = "...")]` on the field. Each variable is looked up both by its name (as declared in SwiftBar's `<swiftbar.environment>` metadata) and with a `VAR_` prefix (xbar's convention for plugin variables).
+///
+/// Values are parsed using `FromStr`. If a variable is unset, `#[config(default = "...")]` is parsed instead, fields of type `Option<T>` are `None`, and any other field causes an error.
+#[proc_macro_derive(PluginConfig, attributes(config))]
+pub fn derive_plugin_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let Data::Struct(DataStruct { fields: Fields::Named(FieldsNamed { named: fields, .. }), .. }) = &input.data else {
+        return quote_spanned! {input.span()=>
+            compile_error!("bitbar::PluginConfig can only be derived for structs with named fields");
+        }.into()
+    };
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut field_idents = Vec::default();
+    let mut field_values = Vec::default();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field without ident");
+        let mut name = None;
+        let mut default = None;
+        for attr in &field.attrs {
+            if attr.path().is_ident("config") {
+                if let Err(e) = attr.parse_nested_meta(|meta| if meta.path.is_ident("rename") {
+                    name = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else if meta.path.is_ident("default") {
+                    default = Some(meta.value()?.parse::<LitStr>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unexpected bitbar::PluginConfig field attribute"))
+                }) {
+                    return e.into_compile_error().into()
+                }
+            }
+        }
+        let name = name.unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_uppercase());
+        let lookup = if name.starts_with("VAR_") {
+            quote!(env.var(#name).map(|value| (#name, value)))
+        } else {
+            let xbar_name = format!("VAR_{name}");
+            quote!(env.var(#name).map(|value| (#name, value)).or_else(|| env.var(#xbar_name).map(|value| (#xbar_name, value))))
+        };
+        let (parse_ty, wrap) = match option_inner_type(&field.ty) {
+            Some(inner) => (inner, quote!(::core::option::Option::Some)),
+            None => (&field.ty, quote!()),
+        };
+        let parse = |value: proc_macro2::TokenStream, var: proc_macro2::TokenStream| quote_spanned! {field.ty.span()=>
+            match <#parse_ty as ::core::str::FromStr>::from_str(#value) {
+                ::core::result::Result::Ok(value) => #wrap(value),
+                ::core::result::Result::Err(e) => return ::core::result::Result::Err(::bitbar::ConfigError::parse(#var, #value, &e)),
+            }
+        };
+        let if_unset = if let Some(default) = default {
+            parse(quote!(#default), quote!(#name))
+        } else if option_inner_type(&field.ty).is_some() {
+            quote!(::core::option::Option::None)
+        } else {
+            quote!(return ::core::result::Result::Err(::bitbar::ConfigError::Missing(::std::string::ToString::to_string(#name))))
+        };
+        let parse_value = parse(quote!(&value), quote!(var));
+        field_values.push(quote! {
+            match #lookup {
+                ::core::option::Option::Some((var, value)) => #parse_value,
+                ::core::option::Option::None => #if_unset,
+            }
+        });
+        field_idents.push(ident);
+    }
+    TokenStream::from(quote! {
+        impl #impl_generics ::bitbar::PluginConfig for #ty #ty_generics #where_clause {
+            fn from_env_source(env: &dyn ::bitbar::flavor::EnvSource) -> ::core::result::Result<Self, ::bitbar::ConfigError> {
+                ::core::result::Result::Ok(Self {
+                    #(#field_idents: #field_values,)*
+                })
+            }
+        }
+    })
+}
+
+/// Implements `bitbar::ToMenuItem` for a struct, mapping its fields to the text and parameters of a `bitbar::ContentItem`.
+///
+/// Fields are mapped using `#[menu_item(...)]` attributes:
+///
+/// * `text`: The menu item text, using the field's `Display` implementation. If no field has this attribute, the struct's own `Display` implementation is used.
+/// * `href`, `color`, `command`, `image`: The corresponding parameter, converted from a clone of the field using `Into` (into `url::Url`, `bitbar::attr::Color`, `bitbar::attr::Command`, or `bitbar::attr::Image`). If the field is an `Option`, the parameter is only set if it's `Some`.
+///
+/// The struct itself can be annotated with `#[menu_item(refresh)]` to make clicking any of the menu items refresh the plugin, and with `#[menu_item(color = "...")]` to set a default color, which is checked at compile time like with `bitbar::color!`.
+#[proc_macro_derive(ToMenuItem, attributes(menu_item))]
+pub fn derive_to_menu_item(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let Data::Struct(DataStruct { fields, .. }) = &input.data else {
+        return quote_spanned! {input.span()=>
+            compile_error!("bitbar::ToMenuItem can only be derived for structs");
+        }.into()
+    };
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut refresh = false;
+    let mut default_color = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("menu_item") {
+            if let Err(e) = attr.parse_nested_meta(|meta| if meta.path.is_ident("refresh") {
+                refresh = true;
+                Ok(())
+            } else if meta.path.is_ident("color") {
+                let lit = meta.value()?.parse::<LitStr>()?;
+                let color = parse_color(&lit)?;
+                default_color = Some(quote!(item.color = ::core::option::Option::Some(::bitbar::attr::Color::from_rgba(#color, ::core::option::Option::None));));
+                Ok(())
+            } else {
+                Err(meta.error("unexpected bitbar::ToMenuItem attribute"))
+            }) {
+                return e.into_compile_error().into()
+            }
+        }
+    }
+    let mut text = None;
+    let mut params = Vec::default();
+    for (idx, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(idx)),
+        };
+        for attr in &field.attrs {
+            if attr.path().is_ident("menu_item") {
+                if let Err(e) = attr.parse_nested_meta(|meta| if meta.path.is_ident("text") {
+                    if text.is_some() { return Err(meta.error("only one field can be the menu item text")) }
+                    text = Some(quote_spanned! {field.span()=> &self.#member });
+                    Ok(())
+                } else if let Some(param) = ["href", "color", "command", "image"].into_iter().find(|param| meta.path.is_ident(param)) {
+                    let param = Ident::new(param, meta.path.span());
+                    params.push(if option_inner_type(&field.ty).is_some() {
+                        quote_spanned! {field.span()=>
+                            if let ::core::option::Option::Some(value) = &self.#member {
+                                item.#param = ::core::option::Option::Some(::core::convert::Into::into(::core::clone::Clone::clone(value)));
+                            }
+                        }
+                    } else {
+                        quote_spanned! {field.span()=>
+                            item.#param = ::core::option::Option::Some(::core::convert::Into::into(::core::clone::Clone::clone(&self.#member)));
+                        }
+                    });
+                    Ok(())
+                } else {
+                    Err(meta.error("unexpected bitbar::ToMenuItem field attribute"))
+                }) {
+                    return e.into_compile_error().into()
+                }
+            }
+        }
+    }
+    let text = text.unwrap_or_else(|| quote!(self));
+    TokenStream::from(quote! {
+        impl #impl_generics ::bitbar::ToMenuItem for #ty #ty_generics #where_clause {
+            fn to_menu_item(&self) -> ::bitbar::ContentItem {
+                let mut item = ::bitbar::ContentItem::new(#text);
+                #default_color
+                #(#params)*
+                item.refresh = #refresh;
+                item
+            }
+        }
+    })
+}
+
+/// The arguments to `include_image!`: a path, optionally followed by `template` and/or `max_size = N`.
+struct IncludeImageArgs {
+    path: LitStr,
+    template: bool,
+    max_size: Option<LitInt>,
+}
+
+impl parse::Parse for IncludeImageArgs {
+    fn parse(input: parse::ParseStream<'_>) -> Result<Self> {
+        let path = input.parse()?;
+        let mut template = false;
+        let mut max_size = None;
+        while input.parse::<Option<Token![,]>>()?.is_some() {
+            if input.is_empty() { break }
+            let arg = input.parse::<Ident>()?;
+            if arg == "template" {
+                template = true;
+            } else if arg == "max_size" {
+                input.parse::<Token![=]>()?;
+                max_size = Some(input.parse()?);
+            } else {
+                return Err(Error::new(arg.span(), "unexpected argument, expected `template` or `max_size`"))
+            }
+        }
+        Ok(Self { path, template, max_size })
+    }
+}
+
+/// Embeds an image file as a `bitbar::attr::Image`, base64-encoding it at compile time.
+///
+/// The path is relative to the current file, like with `include_bytes!`. It can be followed by these arguments:
+///
+/// * `template` makes the image a template image, like `bitbar::attr::Image::template`.
+/// * `max_size = N` (requires the `image` feature) downscales the image to fit within N×N pixels, preserving its aspect ratio, and embeds it as a PNG file. Images which already fit are embedded unchanged.
+///
+/// The macro expands to an expression rather than a constant, since `attr::Image` contains a `String`. Evaluating it only copies the already encoded data.
+#[proc_macro]
+pub fn include_image(input: TokenStream) -> TokenStream {
+    let IncludeImageArgs { path, template, max_size } = parse_macro_input!(input as IncludeImageArgs);
+    let base_dir = proc_macro::Span::call_site().local_file()
+        .and_then(|file| file.parent().map(|parent| parent.to_owned()))
+        .or_else(|| std::env::var_os("CARGO_MANIFEST_DIR").map(std::path::PathBuf::from))
+        .unwrap_or_default();
+    let full_path = base_dir.join(path.value());
+    #[cfg_attr(not(feature = "image"), allow(unused_mut))] let mut data = match std::fs::read(&full_path) {
+        Ok(data) => data,
+        Err(e) => {
+            let msg = format!("failed to read image {}: {e}", full_path.display());
+            return quote_spanned! {path.span()=>
+                compile_error!(#msg);
+            }.into()
+        }
+    };
+    if let Some(max_size) = max_size {
+        #[cfg(feature = "image")] {
+            let max_size = match max_size.base10_parse::<u32>() {
+                Ok(max_size) => max_size,
+                Err(e) => return e.into_compile_error().into(),
+            };
+            let img = match image::load_from_memory(&data) {
+                Ok(img) => img,
+                Err(e) => {
+                    let msg = format!("failed to decode image {}: {e}", full_path.display());
+                    return quote_spanned! {path.span()=>
+                        compile_error!(#msg);
+                    }.into()
+                }
+            };
+            if img.width() > max_size || img.height() > max_size {
+                data.clear();
+                if let Err(e) = img.thumbnail(max_size, max_size).write_to(&mut std::io::Cursor::new(&mut data), image::ImageOutputFormat::Png) {
+                    let msg = format!("failed to encode image {}: {e}", full_path.display());
+                    return quote_spanned! {path.span()=>
+                        compile_error!(#msg);
+                    }.into()
+                }
+            }
+        }
+        #[cfg(not(feature = "image"))] {
+            return quote_spanned! {max_size.span()=>
+                compile_error!("max_size requires the `image` feature of the bitbar crate");
+            }.into()
+        }
+    }
+    let base64_data = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &data);
+    // the source file path may be relative to the working directory of the compiler, while include_bytes! resolves relative paths from the current file
+    let full_path = full_path.canonicalize().unwrap_or(full_path).to_string_lossy().into_owned();
+    TokenStream::from(quote! {{
+        // makes cargo rebuild the calling crate when the image changes
+        const _: &[::core::primitive::u8] = ::core::include_bytes!(#full_path);
+        ::bitbar::attr::Image {
+            base64_data: ::std::string::String::from(#base64_data),
+            is_template: #template,
+        }
+    }})
+}
+
+/// The arguments to `color!`: a color, optionally followed by `dark = ` and a color for the dark system theme.
+struct ColorArgs {
+    light: LitStr,
+    dark: Option<LitStr>,
+}
+
+impl parse::Parse for ColorArgs {
+    fn parse(input: parse::ParseStream<'_>) -> Result<Self> {
+        let light = input.parse()?;
+        let mut dark = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let arg = input.parse::<Ident>()?;
+            if arg != "dark" { return Err(Error::new(arg.span(), "unexpected argument, expected `dark`")) }
+            input.parse::<Token![=]>()?;
+            dark = Some(input.parse()?);
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Self { light, dark })
+    }
+}
+
+/// Parses a CSS color literal into the tokens of an `(r, g, b, a)` tuple, reporting invalid colors as compile errors.
+fn parse_color(lit: &LitStr) -> Result<proc_macro2::TokenStream> {
+    let css_color_parser::Color { r, g, b, a } = lit.value().parse().map_err(|_| Error::new(lit.span(), format!("invalid color: {:?}", lit.value())))?;
+    Ok(quote!((#r, #g, #b, #a)))
+}
+
+/// Constructs a `bitbar::attr::Color` from a CSS color string literal like `"#ff8800"` or `"rgb(255, 136, 0)"`, which is checked at compile time.
+///
+/// A separate color for the dark system theme can be added as `color!("#ff8800", dark = "#333")`. On BitBar implementations other than SwiftBar, only the variant for the current system theme is rendered.
+///
+/// The macro expands to a constant expression, so it can be used to define `const` colors.
+#[proc_macro]
+pub fn color(input: TokenStream) -> TokenStream {
+    let ColorArgs { light, dark } = parse_macro_input!(input as ColorArgs);
+    let light = match parse_color(&light) {
+        Ok(light) => light,
+        Err(e) => return e.into_compile_error().into(),
+    };
+    let dark = match dark.as_ref().map(parse_color).transpose() {
+        Ok(Some(dark)) => quote!(::core::option::Option::Some(#dark)),
+        Ok(None) => quote!(::core::option::Option::None),
+        Err(e) => return e.into_compile_error().into(),
+    };
+    TokenStream::from(quote!(::bitbar::attr::Color::from_rgba(#light, #dark)))
+}
+
+/// Converts a variant name like `HttpGet` or `HTTPGet` to `http-get`. A hyphen is inserted before an uppercase letter following a lowercase letter or digit, and before the last uppercase letter of a run of them if it's followed by a lowercase letter.
+fn kebab_case(camel_case: &str) -> String {
+    let chars = camel_case.chars().collect_vec();
+    let mut kebab_case = String::default();
+    for (idx, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            if idx > 0 && (!chars[idx - 1].is_uppercase() || chars.get(idx + 1).is_some_and(|next| next.is_lowercase())) { kebab_case.push('-') }
+            kebab_case.extend(c.to_lowercase());
+        } else {
+            kebab_case.push(c);
+        }
+    }
+    kebab_case
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kebab_case_acronyms() {
+        assert_eq!(kebab_case("Refresh"), "refresh");
+        assert_eq!(kebab_case("HttpGet"), "http-get");
+        assert_eq!(kebab_case("HTTPGet"), "http-get");
+        assert_eq!(kebab_case("GetHTTP"), "get-http");
+        assert_eq!(kebab_case("OpenURLInBrowser"), "open-url-in-browser");
+        assert_eq!(kebab_case("Ipv4Addr"), "ipv4-addr");
+    }
+}
@@ -0,0 +1,87 @@
+use {
+    criterion::{
+        BenchmarkId,
+        Criterion,
+        black_box,
+        criterion_group,
+        criterion_main,
+    },
+    bitbar::{
+        ContentItem,
+        Flavor,
+        Menu,
+        MenuItem,
+        attr::{
+            Command,
+            Image,
+            Params,
+        },
+    },
+};
+
+/// A menu like a typical status plugin's: a title, a separator, and a few items with links and commands.
+fn small_menu() -> Menu {
+    Menu(vec![
+        ContentItem::new("3 unread").color("red").unwrap().into(),
+        MenuItem::Sep,
+        ContentItem::new("Open inbox").href("https://example.com/inbox").unwrap().into(),
+        ContentItem::new("Mark all as read").command(("/usr/bin/true", "mark-read")).unwrap().refresh().into(),
+        ContentItem::new("Settings").sub(vec![
+            MenuItem::new("Notifications: on"),
+            MenuItem::new("Interval: 5m"),
+        ]).into(),
+    ])
+}
+
+/// A menu with the given number of items, each with a color, a command, and a submenu with a few more items.
+fn large_menu(len: usize) -> Menu {
+    (0..len).map(|i| {
+        let item = ContentItem::new(format!("Item {i}"))
+            .color("#ff8800").unwrap()
+            .command(Command::from(Params::new("/usr/bin/true".to_owned(), vec![format!("item-{i}"), "with space".to_owned()]))).unwrap_or_else(|never| match never {});
+        if i % 10 == 0 {
+            MenuItem::from(item.sub((0..5).map(|j| MenuItem::new(format!("Subitem {j}")))))
+        } else {
+            MenuItem::from(item)
+        }
+    }).collect()
+}
+
+/// A menu whose items are nested `depth` levels deep, with a few siblings at each level.
+fn nested_menu(depth: usize) -> Menu {
+    let mut menu = Menu(vec![MenuItem::new("Leaf")]);
+    for level in (0..depth).rev() {
+        let mut items = vec![MenuItem::from(ContentItem::new(format!("Level {level}")).sub(menu))];
+        items.extend((0..3).map(|i| MenuItem::new(format!("Sibling {level}.{i}"))));
+        menu = Menu(items);
+    }
+    menu
+}
+
+/// A menu with the given number of items, each with an image whose base64-encoded data has the given length.
+fn image_menu(len: usize, image_len: usize) -> Menu {
+    let data = "iVBORw0K".chars().cycle().take(image_len).collect::<String>();
+    (0..len).map(|i| {
+        let image = if i % 2 == 0 { Image::from(data.clone()) } else { Image::template(data.clone()).unwrap_or_else(|never| match never {}) };
+        MenuItem::from(ContentItem::new(format!("Item {i}")).image(image).unwrap_or_else(|never| match never {}))
+    }).collect()
+}
+
+fn bench_menu(c: &mut Criterion, group_name: &str, cases: impl IntoIterator<Item = (String, Menu)>) {
+    let mut group = c.benchmark_group(group_name);
+    for (param, menu) in cases {
+        group.bench_with_input(BenchmarkId::new("display", &param), &menu, |b, menu| b.iter(|| black_box(menu.to_string())));
+        group.bench_with_input(BenchmarkId::new("display_for", &param), &menu, |b, menu| b.iter(|| black_box(menu.display_for(Flavor::BitBar).to_string())));
+    }
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    bench_menu(c, "render/small", [("5".to_owned(), small_menu())]);
+    bench_menu(c, "render/huge", [100, 1_000, 10_000].map(|len| (len.to_string(), large_menu(len))));
+    bench_menu(c, "render/nested", [10, 100].map(|depth| (depth.to_string(), nested_menu(depth))));
+    bench_menu(c, "render/images", [(1_024, 100), (16 * 1_024, 20)].map(|(image_len, len)| (format!("{len}x{image_len}"), image_menu(len, image_len))));
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
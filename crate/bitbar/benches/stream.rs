@@ -0,0 +1,64 @@
+use {
+    std::{
+        collections::BTreeMap,
+        io,
+    },
+    criterion::{
+        BatchSize,
+        BenchmarkId,
+        Criterion,
+        Throughput,
+        black_box,
+        criterion_group,
+        criterion_main,
+    },
+    bitbar::{
+        ContentItem,
+        MainOutput as _,
+        Menu,
+        MenuItem,
+        flavor::swiftbar::{
+            BlockingStream,
+            StreamFrame,
+            SwiftBar,
+        },
+    },
+};
+
+/// A SwiftBar version which uses trailing stream separators.
+fn swiftbar() -> SwiftBar {
+    SwiftBar::check_from(&BTreeMap::from([("SWIFTBAR_BUILD", "402")])).expect("failed to construct SwiftBar handle")
+}
+
+/// The `i`th frame of a stream showing a counter, with a submenu of the given length.
+fn frame(i: usize, len: usize) -> Menu {
+    let mut menu = Menu(vec![
+        ContentItem::new(format!("Count: {i}")).color(if i.is_multiple_of(2) { "red" } else { "blue" }).unwrap().into(),
+        MenuItem::Sep,
+    ]);
+    menu.extend((0..len).map(|j| MenuItem::new(format!("Entry {j} of frame {i}"))));
+    menu
+}
+
+fn stream(c: &mut Criterion) {
+    let swiftbar = swiftbar();
+    let mut group = c.benchmark_group("stream");
+    for len in [0, 100] {
+        let mut buf = Vec::default();
+        group.bench_with_input(BenchmarkId::new("frame", len), &len, |b, &len| b.iter_batched(|| frame(0, len), |menu| {
+            buf.clear();
+            StreamFrame::new(swiftbar).write(&mut buf, menu, None).expect("failed to write frame");
+            black_box(&buf);
+        }, BatchSize::SmallInput));
+    }
+    for frames in [100, 1_000] {
+        group.throughput(Throughput::Elements(frames as u64));
+        group.bench_with_input(BenchmarkId::new("blocking", frames), &frames, |b, &frames| b.iter(|| {
+            BlockingStream::new(swiftbar, (0..frames).map(|i| frame(i, 10))).write_main_output(&mut io::sink(), None).expect("failed to write stream");
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stream);
+criterion_main!(benches);
@@ -6,10 +6,15 @@ use {
             TryFrom,
             TryInto,
         },
+        borrow::Cow,
         fmt,
+        io,
+        mem,
+        ops::Deref,
         str::FromStr,
     },
     css_color_parser::ColorParseError,
+    thiserror::Error,
     url::Url,
     crate::{
         ContentItem,
@@ -32,14 +37,84 @@ use {
 
 /// Used in [`ContentItem::color`](ContentItem::color()).
 ///
-/// Construct via [`Into`] or [`TryInto`](std::convert::TryInto) implementations.
+/// Construct via [`Into`] or [`TryInto`](std::convert::TryInto) implementations, or at compile time using the [`color!`](crate::color!) macro.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub(crate) light: css_color_parser::Color,
-    /// SwiftBar only: separate color for dark system theme. If `None`, use `light`.
+    /// Separate color for dark system theme. If `None`, use `light`. Only SwiftBar supports rendering both, see [`Color::for_theme`].
     pub(crate) dark: Option<css_color_parser::Color>,
 }
 
+impl Color {
+    #[doc(hidden)] pub const fn from_rgba(light: (u8, u8, u8, f32), dark: Option<(u8, u8, u8, f32)>) -> Color { // used in proc macro
+        let (r, g, b, a) = light;
+        Color {
+            light: css_color_parser::Color { r, g, b, a },
+            dark: match dark {
+                Some((r, g, b, a)) => Some(css_color_parser::Color { r, g, b, a }),
+                None => None,
+            },
+        }
+    }
+
+    /// Returns the variant of this color used for the given system theme, without a separate dark variant.
+    ///
+    /// This is used when rendering a menu for a BitBar implementation which doesn't support separate colors for the dark theme, see [`Flavor::supports_dark_colors`](crate::Flavor::supports_dark_colors).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let color = bitbar::color!("#ff8800", dark = "#333");
+    /// assert_eq!(color.to_string(), "#ff8800,#333333");
+    /// assert_eq!(color.for_theme(false).to_string(), "#ff8800");
+    /// assert_eq!(color.for_theme(true).to_string(), "#333333");
+    /// ```
+    pub fn for_theme(&self, dark: bool) -> Color {
+        Color {
+            light: if dark { self.dark.unwrap_or(self.light) } else { self.light },
+            dark: None,
+        }
+    }
+
+    /// Returns a gradient between the given colors, e.g. for coloring items by the value of a metric. See [`Gradient::sample`].
+    ///
+    /// # Panics
+    ///
+    /// If `stops` is empty.
+    pub fn gradient(stops: impl IntoIterator<Item = impl Into<Color>>) -> Gradient {
+        let stops = stops.into_iter().map(Into::into).collect::<Vec<_>>();
+        assert!(!stops.is_empty(), "a gradient needs at least one color");
+        Gradient { stops }
+    }
+
+    /// Returns the colored circle emoji closest in hue to the light variant of this color.
+    pub(crate) fn circle_emoji(&self) -> &'static str {
+        let css_color_parser::Color { r, g, b, .. } = self.light;
+        let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 510.0;
+        if max - min < 40.0 { return if lightness < 0.5 { "⚫" } else { "⚪" } }
+        let hue = if max == r {
+            60.0 * ((g - b) / (max - min)).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / (max - min) + 2.0)
+        } else {
+            60.0 * ((r - g) / (max - min) + 4.0)
+        };
+        match hue {
+            15.0..45.0 if lightness < 0.35 => "🟤",
+            15.0..45.0 => "🟠",
+            45.0..70.0 if lightness < 0.3 => "🟤",
+            45.0..70.0 => "🟡",
+            70.0..170.0 => "🟢",
+            170.0..250.0 => "🔵",
+            250.0..330.0 => "🟣",
+            _ => "🔴",
+        }
+    }
+}
+
 impl From<css_color_parser::Color> for Color {
     fn from(light: css_color_parser::Color) -> Color {
         Color { light, dark: None }
@@ -57,7 +132,7 @@ impl FromStr for Color {
     }
 }
 
-impl<'a> TryFrom<&'a str> for Color {
+impl TryFrom<&str> for Color {
     type Error = ColorParseError;
 
     fn try_from(s: &str) -> Result<Color, ColorParseError> {
@@ -89,17 +164,25 @@ macro_rules! css_color_try_into_color {
 
 #[cfg(feature = "serenity")]
 #[cfg_attr(docsrs, doc(cfg(feature = "serenity")))]
-impl From<serenity::utils::Colour> for Color {
-    fn from(c: serenity::utils::Colour) -> Color {
-        Color {
-            light: css_color_parser::Color {
-                r: c.r(),
-                g: c.g(),
-                b: c.b(),
-                a: 1.0,
-            },
-            dark: None,
+impl From<serenity::model::Colour> for Color {
+    fn from(c: serenity::model::Colour) -> Color {
+        crate::discord::EmbedColor::from(c).into()
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Color {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Color> {
+        // alpha is not rendered, so only opaque colors are generated
+        fn opaque(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<css_color_parser::Color> {
+            Ok(css_color_parser::Color { r: u.arbitrary()?, g: u.arbitrary()?, b: u.arbitrary()?, a: 1.0 })
         }
+
+        Ok(Color {
+            light: opaque(u)?,
+            dark: if u.arbitrary()? { Some(opaque(u)?) } else { None },
+        })
     }
 }
 
@@ -113,15 +196,166 @@ impl fmt::Display for Color {
     }
 }
 
+/// Evenly spaced colors which can be interpolated between, created using [`Color::gradient`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<Color>,
+}
+
+impl Gradient {
+    /// Returns the color at position `t` of the gradient, where `0.0` is the first color and `1.0` is the last. Values outside of this range are clamped.
+    ///
+    /// If any of the colors has a separate variant for the dark system theme, so does the returned color. The dark variants are interpolated separately, using the light variant for colors without a dark one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bitbar::{attr::Color, color};
+    ///
+    /// let gradient = Color::gradient([color!("#000000"), color!("#ffffff", dark = "#ff0000")]);
+    /// assert_eq!(gradient.sample(0.0).to_string(), "#000000,#000000");
+    /// assert_eq!(gradient.sample(0.5).to_string(), "#808080,#800000");
+    /// assert_eq!(gradient.sample(2.0).to_string(), "#ffffff,#ff0000");
+    /// ```
+    pub fn sample(&self, t: f64) -> Color {
+        let pos = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) } * (self.stops.len() - 1) as f64;
+        let idx = (pos.floor() as usize).min(self.stops.len().saturating_sub(2));
+        let (start, end) = (self.stops[idx], self.stops[(idx + 1).min(self.stops.len() - 1)]);
+        let t = pos - idx as f64;
+        let lerp = |a: css_color_parser::Color, b: css_color_parser::Color| {
+            let channel = |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * t).round() as u8;
+            css_color_parser::Color {
+                r: channel(a.r, b.r),
+                g: channel(a.g, b.g),
+                b: channel(a.b, b.b),
+                a: (f64::from(a.a) + (f64::from(b.a) - f64::from(a.a)) * t) as f32,
+            }
+        };
+        Color {
+            light: lerp(start.light, end.light),
+            dark: self.stops.iter().any(|stop| stop.dark.is_some()).then(|| lerp(start.dark.unwrap_or(start.light), end.dark.unwrap_or(end.light))),
+        }
+    }
+}
+
+/// Returns a color between green for `0.0`, yellow for `0.5`, and red for `1.0`, e.g. for coloring an item by how full a disk is. Values outside of this range are clamped.
+///
+/// The colors are the system colors used by macOS, with separate variants for the dark system theme.
+///
+/// # Example
+///
+/// ```
+/// use bitbar::attr::color_for_fraction;
+///
+/// assert_eq!(color_for_fraction(0.0).to_string(), "#34c759,#30d158");
+/// assert_eq!(color_for_fraction(0.5).to_string(), "#ffcc00,#ffd60a");
+/// assert_eq!(color_for_fraction(1.0).to_string(), "#ff3b30,#ff453a");
+/// ```
+pub fn color_for_fraction(fraction: f64) -> Color {
+    Color::gradient([
+        Color::from_rgba((0x34, 0xc7, 0x59, 1.0), Some((0x30, 0xd1, 0x58, 1.0))),
+        Color::from_rgba((0xff, 0xcc, 0x00, 1.0), Some((0xff, 0xd6, 0x0a, 1.0))),
+        Color::from_rgba((0xff, 0x3b, 0x30, 1.0), Some((0xff, 0x45, 0x3a, 1.0))),
+    ]).sample(fraction)
+}
+
 #[derive(Debug)]
 /// A menu item's alternate mode or submenu.
 pub enum Extra {
     /// A menu item's alternate mode, shown when <key>⌥</key> is held.
-    Alternate(Box<ContentItem>), //TODO make sure alts don't have submenus
+    Alternate(Box<AltItem>),
     /// A submenu.
     Submenu(Menu),
 }
 
+/// Generates submenus and alternate items nested up to a fixed depth, so that large inputs don't overflow the stack.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Extra {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Extra> {
+        Ok(if u.arbitrary()? {
+            Extra::Alternate(u.arbitrary()?)
+        } else {
+            Extra::Submenu(u.arbitrary()?)
+        })
+    }
+}
+
+/// A menu item's alternate mode, used by [`ContentItem::alt`](ContentItem::alt()).
+///
+/// This is a [`ContentItem`] which has neither a submenu nor its own alternate item, since BitBar doesn't display these. Its parameters can be read via [`Deref`].
+#[derive(Debug)]
+pub struct AltItem(pub(crate) ContentItem);
+
+impl AltItem {
+    /// Returns a new alternate item with the given text.
+    pub fn new(text: impl ToString) -> AltItem {
+        AltItem(ContentItem::new(text))
+    }
+
+    /// Converts this alternate item into a regular menu item.
+    pub fn into_inner(self) -> ContentItem {
+        self.0
+    }
+}
+
+impl Deref for AltItem {
+    type Target = ContentItem;
+
+    fn deref(&self) -> &ContentItem {
+        &self.0
+    }
+}
+
+/// Creates an alternate item with the given text without copying it.
+impl From<&'static str> for AltItem {
+    fn from(text: &'static str) -> AltItem {
+        AltItem(ContentItem::from(text))
+    }
+}
+
+/// Creates an alternate item with the given text without copying it.
+impl From<Cow<'static, str>> for AltItem {
+    fn from(text: Cow<'static, str>) -> AltItem {
+        AltItem(ContentItem::from(text))
+    }
+}
+
+/// Fails if the item has a submenu or its own alternate item.
+impl TryFrom<ContentItem> for AltItem {
+    type Error = AltItemError;
+
+    fn try_from(item: ContentItem) -> Result<AltItem, AltItemError> {
+        if item.extra.is_some() {
+            Err(AltItemError(item))
+        } else {
+            Ok(AltItem(item))
+        }
+    }
+}
+
+impl From<AltItem> for ContentItem {
+    fn from(AltItem(item): AltItem) -> ContentItem {
+        item
+    }
+}
+
+/// Generates alternate items with arbitrary text and parameters.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for AltItem {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<AltItem> {
+        let mut item = u.arbitrary::<ContentItem>()?;
+        item.extra = None;
+        Ok(AltItem(item))
+    }
+}
+
+/// Returned when converting a [`ContentItem`] with a submenu or alternate item into an [`AltItem`]. Contains the original item.
+#[derive(Debug, Error)]
+#[error("alternate items can't have submenus or their own alternate items")]
+pub struct AltItemError(pub ContentItem);
+
 /// Used by [`ContentItem::href`](ContentItem::href()).
 pub trait IntoUrl {
     /// Converts `self` into a [`Url`].
@@ -140,7 +374,7 @@ impl IntoUrl for String {
     }
 }
 
-impl<'a> IntoUrl for &'a str {
+impl IntoUrl for &str {
     fn into_url(self) -> Result<Url, url::ParseError> {
         Url::parse(self)
     }
@@ -153,11 +387,54 @@ pub struct Params {
     pub(crate) params: Vec<String>,
 }
 
+/// The number of parameters of an [inline script](Params::inline_script) before its arguments.
+const INLINE_SCRIPT_PARAMS: usize = 3;
+
 impl Params {
     #[doc(hidden)] // used in proc macro
     pub fn new(cmd: String, params: Vec<String>) -> Self {
         Self { cmd, params }
     }
+
+    /// Runs the given shell script using `/bin/sh`, with the given arguments available as `$1`, `$2`, etc.
+    pub fn inline_script(script: impl ToString, args: impl IntoIterator<Item = impl ToString>) -> Self {
+        Self {
+            cmd: "/bin/sh".to_owned(),
+            params: ["-c".to_owned(), script.to_string(), "sh".to_owned()].into_iter().chain(args.into_iter().map(|arg| arg.to_string())).collect(),
+        }
+    }
+
+    #[doc(hidden)] // used in proc macro
+    pub fn new_optional(cmd: String, params: Vec<Option<String>>, flags: Vec<Option<&str>>) -> io::Result<Self> {
+        let mut params = params.into_iter();
+        let present = params.by_ref().map_while(|param| param).collect::<Vec<_>>();
+        if params.any(|param| param.is_some()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "only trailing command parameters may be omitted"))
+        }
+        if flags.is_empty() { return Ok(Self { cmd, params: present }) }
+        // the first parameter is the subcommand name, flags go between it and the other parameters
+        let mut present = present.into_iter();
+        let mut params = present.next().into_iter().chain(flags.into_iter().flatten().map(str::to_owned)).collect::<Vec<_>>();
+        let present = present.collect::<Vec<_>>();
+        // make sure parameter values aren't mistaken for flags
+        if present.iter().any(|param| param.starts_with('-')) { params.push("--".to_owned()) }
+        params.extend(present);
+        Ok(Self { cmd, params })
+    }
+}
+
+/// Generates a command with 0–5 parameters, the maximum supported by BitBar.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Params {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Params> {
+        let cmd = u.arbitrary()?;
+        let num_params = u.int_in_range(0..=5)?;
+        Ok(Params {
+            cmd,
+            params: (0..num_params).map(|_| u.arbitrary()).collect::<arbitrary::Result<_>>()?,
+        })
+    }
 }
 
 macro_rules! params_from {
@@ -230,6 +507,8 @@ impl<T: ToString> TryFrom<Vec<T>> for Params {
 pub struct Command {
     pub(crate) params: Params,
     pub(crate) terminal: bool,
+    /// The number of environment variables set using [`Command::public_env`], which are the first arguments of the inline script wrapping the actual command.
+    pub(crate) env_len: usize,
 }
 
 impl Command {
@@ -238,6 +517,7 @@ impl Command {
         Command {
             params: args.into(),
             terminal: true,
+            env_len: 0,
         }
     }
 
@@ -248,6 +528,7 @@ impl Command {
         Ok(Command {
             params: args.try_into()?,
             terminal: false,
+            env_len: 0,
         })
     }
 
@@ -256,6 +537,77 @@ impl Command {
         Ok(Command {
             params: args.try_into()?,
             terminal: true,
+            env_len: 0,
+        })
+    }
+
+    /// Sets an environment variable for the command, passing its value as a parameter.
+    ///
+    /// BitBar can't pass environment variables to commands, so this runs the command via an [inline script](Params::inline_script) which exports the variables and then runs the actual command. The variables and the script take up parameters, so this counts towards the [limit](crate::validate::MAX_BITBAR_PARAMS) of the original BitBar.
+    ///
+    /// Like any other parameter, the variable is visible in the plugin's output and in the process list while the command runs, so this must not be used for secrets.
+    ///
+    /// # Errors
+    ///
+    /// If `key` is not a valid environment variable name for the shell, i.e. if it's empty, contains `=` or any other character other than ASCII letters, digits, and `_`, or starts with a digit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bitbar::{
+    ///     ContentItem,
+    ///     attr::Command,
+    /// };
+    ///
+    /// let item = ContentItem::new("Deploy").command(Command::from(("/usr/local/bin/deploy", "production")).public_env("DRY_RUN", 1)?).unwrap();
+    /// assert_eq!(item.to_string(), "Deploy | bash=/bin/sh param1=-c param2=export${IFS}\"${1}\";shift${IFS}1;exec${IFS}\"$@\" param3=sh param4=DRY_RUN=1 param5=/usr/local/bin/deploy param6=production terminal=false\n");
+    /// assert!(Command::from(["/usr/local/bin/deploy"]).public_env("DRY=RUN", 1).is_err());
+    /// assert!(Command::from(["/usr/local/bin/deploy"]).public_env("", 1).is_err());
+    /// # Ok::<(), bitbar::attr::EnvKeyError>(())
+    /// ```
+    pub fn public_env(mut self, key: impl ToString, value: impl fmt::Display) -> Result<Self, EnvKeyError> {
+        let key = key.to_string();
+        if !key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(EnvKeyError(key))
+        }
+        if self.env_len == 0 {
+            let Params { cmd, params } = mem::replace(&mut self.params, Params::inline_script("", Vec::<String>::default()));
+            self.params.params.push(cmd);
+            self.params.params.extend(params);
+        }
+        self.params.params.insert(INLINE_SCRIPT_PARAMS + self.env_len, format!("{key}={value}"));
+        self.env_len += 1;
+        // export the variables passed as the first arguments of the script, then run the remaining arguments
+        // uses ${IFS} instead of spaces since a parameter can't contain both spaces and double quotes
+        let exports = (1..=self.env_len).map(|i| format!("${{IFS}}\"${{{i}}}\"")).collect::<String>();
+        self.params.params[1] = format!("export{exports};shift${{IFS}}{};exec${{IFS}}\"$@\"", self.env_len);
+        Ok(self)
+    }
+
+    /// The actual command and its parameters, excluding the inline script used by [`Command::public_env`].
+    pub(crate) fn target_mut(&mut self) -> (&str, &mut [String]) {
+        if self.env_len == 0 {
+            (&self.params.cmd, &mut self.params.params)
+        } else {
+            let (cmd, params) = self.params.params[INLINE_SCRIPT_PARAMS + self.env_len..].split_first_mut().expect("inline script has a target");
+            (cmd, params)
+        }
+    }
+}
+
+/// The error returned by [`Command::public_env`] for an invalid environment variable name.
+#[derive(Debug, Error)]
+#[error("invalid environment variable name: {0:?}")]
+pub struct EnvKeyError(pub String);
+
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Command {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Command> {
+        Ok(Command {
+            params: u.arbitrary()?,
+            terminal: u.arbitrary()?,
+            env_len: 0,
         })
     }
 }
@@ -266,6 +618,7 @@ impl<P: Into<Params>> From<P> for Command {
         Command {
             params: args.into(),
             terminal: false,
+            env_len: 0,
         }
     }
 }
@@ -279,6 +632,17 @@ pub struct Image {
     pub is_template: bool,
 }
 
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Image {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Image> {
+        Ok(Image {
+            base64_data: u.arbitrary()?,
+            is_template: u.arbitrary()?,
+        })
+    }
+}
+
 impl Image {
     /// Constructs a template image, even if the `TryInto` implementation would otherwise construct a non-template image.
     pub fn template<T: TryInto<Image>>(img: T) -> Result<Image, T::Error> {
@@ -333,3 +697,84 @@ impl TryFrom<DynamicImage> for Image {
         Ok(Image::from(buf.into_inner()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Color,
+        Command,
+        Params,
+    };
+
+    fn params(params: &[Option<&str>], flags: Vec<Option<&str>>) -> Vec<String> {
+        Params::new_optional("/plugin".to_owned(), params.iter().map(|param| param.map(str::to_owned)).collect(), flags).unwrap().params
+    }
+
+    #[test]
+    fn flags_between_subcommand_and_params() {
+        assert_eq!(params(&[Some("greet"), Some("bob")], vec![Some("--loud"), None]), ["greet", "--loud", "bob"]);
+        assert_eq!(params(&[Some("greet"), Some("bob")], vec![None]), ["greet", "bob"]);
+    }
+
+    #[test]
+    fn flag_like_params_after_separator() {
+        assert_eq!(params(&[Some("greet"), Some("--loud")], vec![Some("--loud")]), ["greet", "--loud", "--", "--loud"]);
+        assert_eq!(params(&[Some("greet"), Some("-1")], vec![None]), ["greet", "--", "-1"]);
+        // commands without flags don't parse `--`
+        assert_eq!(params(&[Some("greet"), Some("--loud")], Vec::default()), ["greet", "--loud"]);
+    }
+
+    #[test]
+    fn omitted_params_must_be_trailing() {
+        assert!(Params::new_optional("/plugin".to_owned(), vec![Some("greet".to_owned()), None, Some("x".to_owned())], Vec::default()).is_err());
+    }
+
+    fn circle(color: &str) -> &'static str {
+        color.parse::<Color>().unwrap().circle_emoji()
+    }
+
+    #[test]
+    fn circle_emoji_hues() {
+        assert_eq!(circle("#ff0000"), "🔴");
+        assert_eq!(circle("#ff0040"), "🔴");
+        assert_eq!(circle("#ff8800"), "🟠");
+        assert_eq!(circle("#ffd700"), "🟡");
+        assert_eq!(circle("#00ff00"), "🟢");
+        assert_eq!(circle("#00ccff"), "🔵");
+        assert_eq!(circle("#0000ff"), "🔵");
+        assert_eq!(circle("#8000ff"), "🟣");
+        assert_eq!(circle("#ff00aa"), "🟣");
+    }
+
+    #[test]
+    fn circle_emoji_dark_and_gray() {
+        assert_eq!(circle("#8b4513"), "🟤");
+        assert_eq!(circle("#665500"), "🟤");
+        assert_eq!(circle("#000000"), "⚫");
+        assert_eq!(circle("#333333"), "⚫");
+        assert_eq!(circle("#808080"), "⚪");
+        assert_eq!(circle("#ffffff"), "⚪");
+        // nearly gray colors don't have a meaningful hue
+        assert_eq!(circle("#b0a0a0"), "⚪");
+    }
+
+    #[test]
+    fn env_wraps_target() {
+        let mut command = Command::from(("/plugin", "greet", "bob")).public_env("A", 1).unwrap().public_env("B", "x y").unwrap();
+        assert_eq!(command.params.cmd, "/bin/sh");
+        assert_eq!(command.params.params, ["-c", "export${IFS}\"${1}\"${IFS}\"${2}\";shift${IFS}2;exec${IFS}\"$@\"", "sh", "A=1", "B=x y", "/plugin", "greet", "bob"]);
+        let (cmd, params) = command.target_mut();
+        assert_eq!(cmd, "/plugin");
+        assert_eq!(params, ["greet", "bob"]);
+    }
+
+    #[test]
+    fn env_key_validation() {
+        for key in ["", "A=B", "1A", "A B", "Ä"] {
+            assert!(Command::from(["/plugin"]).public_env(key, 1).is_err(), "{key:?}");
+        }
+        for key in ["A", "_a1", "PATH"] {
+            assert!(Command::from(["/plugin"]).public_env(key, 1).is_ok(), "{key:?}");
+        }
+    }
+}
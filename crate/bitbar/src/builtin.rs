@@ -0,0 +1,101 @@
+//! Subcommands built into every plugin, used by `cargo-bitbar` and other tooling.
+
+use crate::{
+    CommandParam,
+    CommandRegistration,
+    PluginVar,
+    SubcommandsRegistration,
+    VarsRegistration,
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use std::{
+    future::Future,
+    pin::Pin,
+};
+
+/// Prints the variables declared via `#[bitbar::main(vars(...))]`, one per line as tab-separated name, xbar type, and default value. Used by `cargo bitbar meta --vars`.
+fn print_vars(_: Vec<String>) {
+    for registration in inventory::iter::<VarsRegistration> {
+        for PluginVar { name, kind, default } in registration.vars {
+            println!("{name}\t{kind}\t{default}");
+        }
+    }
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+fn print_vars_command(args: Vec<String>) -> Pin<Box<dyn Future<Output = ()>>> {
+    Box::pin(async move { print_vars(args) })
+}
+
+#[cfg(not(any(feature = "async-std", feature = "futures-executor", feature = "tokio")))]
+fn print_vars_command(args: Vec<String>) {
+    print_vars(args)
+}
+
+inventory::submit! {
+    CommandRegistration {
+        name: "bitbar-vars",
+        params: &[],
+        run: print_vars_command,
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c.is_control() => buf.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+/// Prints all registered commands (including those built into this crate) and their parameters as a JSON array, sorted by name.
+///
+/// Commands defined via [`derive(Subcommands)`](macro@Subcommands) are listed with `"params": null` since their parameters aren't known.
+fn print_commands(_: Vec<String>) {
+    let mut commands = inventory::iter::<CommandRegistration>.into_iter().map(|CommandRegistration { name, params, .. }| {
+        let params = params.iter().map(|CommandParam { name, ty, kind, default }| format!(
+            r#"{{"name":{},"type":{},"kind":{},"default":{}}}"#,
+            json_string(name),
+            json_string(ty),
+            json_string(kind),
+            default.map_or_else(|| "null".to_owned(), json_string),
+        )).collect::<Vec<_>>();
+        (*name, format!("[{}]", params.join(",")))
+    })
+        .chain(inventory::iter::<SubcommandsRegistration>.into_iter().flat_map(|registration| registration.names.iter().map(|name| (*name, "null".to_owned()))))
+        .collect::<Vec<_>>();
+    commands.sort_unstable_by_key(|(name, _)| *name);
+    println!("[");
+    for (idx, (name, params)) in commands.iter().enumerate() {
+        let comma = if idx < commands.len() - 1 { "," } else { "" };
+        println!(r#"    {{"name":{},"params":{params}}}{comma}"#, json_string(name));
+    }
+    println!("]");
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+fn print_commands_command(args: Vec<String>) -> Pin<Box<dyn Future<Output = ()>>> {
+    Box::pin(async move { print_commands(args) })
+}
+
+#[cfg(not(any(feature = "async-std", feature = "futures-executor", feature = "tokio")))]
+fn print_commands_command(args: Vec<String>) {
+    print_commands(args)
+}
+
+inventory::submit! {
+    CommandRegistration {
+        name: "bitbar-commands",
+        params: &[],
+        run: print_commands_command,
+    }
+}
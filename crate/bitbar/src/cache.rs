@@ -0,0 +1,255 @@
+//! Caching of expensive data, such as responses from rate-limited APIs, across plugin refreshes.
+//!
+//! Cached values are stored as JSON files in the [plugin cache directory](crate::cache_dir), in a subdirectory named `cache`. If a cache entry can't be read, e.g. because the cached type has changed, it is treated as missing and the value is fetched again.
+//!
+//! With the `base64` feature, the base64 encodings of images can also be cached using [`image`] and [`image_file`], in a subdirectory named `images`. Cached images which haven't been used for 30 days are removed.
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     std::time::Duration,
+//!     bitbar::{
+//!         Menu,
+//!         MenuItem,
+//!         cache,
+//!     },
+//! };
+//!
+//! fn fetch_followers() -> Result<u64, std::convert::Infallible> {
+//!     // call some API
+//!     Ok(42)
+//! }
+//!
+//! #[bitbar::main]
+//! fn main() -> Result<Menu, cache::Error<std::convert::Infallible>> {
+//!     let followers = cache::get("followers", Duration::from_secs(5 * 60), fetch_followers)?;
+//!     Ok(Menu(vec![MenuItem::new(format!("{followers} followers"))]))
+//! }
+//! ```
+
+use {
+    std::{
+        fmt,
+        fs,
+        io,
+        path::PathBuf,
+        sync::Arc,
+        time::{
+            Duration,
+            SystemTime,
+        },
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+        de::DeserializeOwned,
+    },
+    thiserror::Error,
+    url::form_urlencoded,
+    crate::{
+        Menu,
+        MenuItem,
+    },
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use std::future::Future;
+#[cfg(feature = "base64")] use {
+    std::{
+        path::Path,
+        time::UNIX_EPOCH,
+    },
+    crate::attr::Image,
+};
+
+/// An error that can occur when getting a cached value.
+#[derive(Debug, Error, Clone)]
+pub enum Error<E> {
+    /// The function fetching a fresh value returned an error
+    #[error(transparent)] Fetch(E),
+    /// An I/O error occurred while writing the cache entry
+    #[error(transparent)] Io(Arc<io::Error>),
+    /// The fetched value could not be serialized
+    #[error(transparent)] Json(Arc<serde_json::Error>),
+}
+
+impl<E> From<io::Error> for Error<E> {
+    fn from(e: io::Error) -> Self {
+        Self::Io(Arc::new(e))
+    }
+}
+
+impl<E> From<serde_json::Error> for Error<E> {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(Arc::new(e))
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> From<Error<E>> for Menu {
+    fn from(e: Error<E>) -> Menu {
+        let mut menu = vec![MenuItem::new("Error fetching data")];
+        match e {
+            Error::Fetch(e) => {
+                menu.push(MenuItem::new(&e));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Io(e) => {
+                menu.push(MenuItem::new(format!("I/O error in cache: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Json(e) => {
+                menu.push(MenuItem::new(format!("error serializing cache entry: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+        }
+        Menu(menu)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    fetched: SystemTime,
+    value: T,
+}
+
+impl<T> Entry<T> {
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        SystemTime::now().duration_since(self.fetched).is_ok_and(|age| age < ttl)
+    }
+}
+
+/// Returns the path to the file in which the value for the given key is cached.
+pub fn path(key: &str) -> io::Result<PathBuf> {
+    Ok(crate::cache_dir()?.join("cache").join(format!("{}.json", form_urlencoded::byte_serialize(key.as_bytes()).collect::<String>())))
+}
+
+fn read<T: DeserializeOwned>(key: &str) -> Option<Entry<T>> {
+    serde_json::from_slice(&fs::read(path(key).ok()?).ok()?).ok()
+}
+
+fn write<T: Serialize, E>(key: &str, value: &T) -> Result<(), Error<E>> {
+    let buf = serde_json::to_vec(&Entry { fetched: SystemTime::now(), value })?;
+    crate::write_atomic(&path(key)?, &buf)?;
+    Ok(())
+}
+
+/// Returns the cached value for the given key if it was fetched less than `ttl` ago. Otherwise, calls `fetch` and caches its result if it is `Ok`.
+pub fn get<T: Serialize + DeserializeOwned, E>(key: &str, ttl: Duration, fetch: impl FnOnce() -> Result<T, E>) -> Result<T, Error<E>> {
+    if let Some(entry) = read(key) {
+        if entry.is_fresh(ttl) { return Ok(entry.value) }
+    }
+    let value = fetch().map_err(Error::Fetch)?;
+    write(key, &value)?;
+    Ok(value)
+}
+
+/// Like [`get`], but with an `async` function to fetch the value.
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+pub async fn get_async<T: Serialize + DeserializeOwned, E, Fut: Future<Output = Result<T, E>>>(key: &str, ttl: Duration, fetch: impl FnOnce() -> Fut) -> Result<T, Error<E>> {
+    if let Some(entry) = read(key) {
+        if entry.is_fresh(ttl) { return Ok(entry.value) }
+    }
+    let value = fetch().await.map_err(Error::Fetch)?;
+    write(key, &value)?;
+    Ok(value)
+}
+
+/// Reports an error from refreshing a stale cache entry in the background.
+fn report_revalidate_error<E: fmt::Debug + fmt::Display>(key: &str, e: Error<E>) {
+    crate::notify_error(&format!("failed to refresh cached {key}: {e}"), &format!("{e:?}"));
+}
+
+/// Like [`get`], but if the cached value is stale, it is returned immediately while a fresh value is fetched on a background thread.
+///
+/// The value is fetched in the foreground if it isn't cached at all. Errors from fetching in the background are reported like errors from commands (see `on_error` in [`main`](macro@crate::main)).
+///
+/// The plugin process doesn't exit until the background fetch has finished, but when using [`main`](macro@crate::main), the menu is written before waiting for it.
+pub fn get_stale_while_revalidate<T, E, F>(key: &str, ttl: Duration, fetch: F) -> Result<T, Error<E>>
+where T: Serialize + DeserializeOwned, E: fmt::Debug + fmt::Display + Send + 'static, F: FnOnce() -> Result<T, E> + Send + 'static {
+    let Some(entry) = read(key) else { return get(key, ttl, fetch) };
+    if !entry.is_fresh(ttl) {
+        let key = key.to_owned();
+        crate::spawn_background(move || if let Err(e) = fetch().map_err(Error::Fetch).and_then(|value| write(&key, &value)) {
+            report_revalidate_error(&key, e);
+        });
+    }
+    Ok(entry.value)
+}
+
+/// Like [`get_stale_while_revalidate`], but with an `async` function to fetch the value. The background fetch is awaited by the `main` function generated by [`main`](macro@crate::main) after the menu has been written.
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+pub async fn get_stale_while_revalidate_async<T, E, Fut>(key: &str, ttl: Duration, fetch: impl FnOnce() -> Fut) -> Result<T, Error<E>>
+where T: Serialize + DeserializeOwned + Send, E: fmt::Debug + fmt::Display + Send, Fut: Future<Output = Result<T, E>> + Send + 'static {
+    let Some(entry) = read(key) else { return get_async(key, ttl, fetch).await };
+    if !entry.is_fresh(ttl) {
+        let key = key.to_owned();
+        let fut = fetch();
+        crate::spawn_background_async(async move {
+            if let Err(e) = fut.await.map_err(Error::Fetch).and_then(|value| write(&key, &value)) {
+                report_revalidate_error(&key, e);
+            }
+        });
+    }
+    Ok(entry.value)
+}
+
+/// Removes the cached value for the given key, so that it is fetched again on next access.
+pub fn invalidate(key: &str) -> io::Result<()> {
+    match fs::remove_file(path(key)?) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Cached images which haven't been used for this long are removed when a new image is cached.
+#[cfg(feature = "base64")]
+const IMAGE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Returns the cached base64 encoding for the given key, or encodes the data returned by `read` and caches the result.
+///
+/// The file name is a hash of the key which is stable across runs and Rust versions.
+#[cfg(feature = "base64")]
+fn cached_image(key: &[u8], read: impl FnOnce() -> io::Result<Vec<u8>>) -> io::Result<Image> {
+    let dir = crate::cache_dir()?.join("images");
+    let path = dir.join(format!("{:016x}.b64", crate::stable_hash(key)));
+    match fs::read_to_string(&path) {
+        Ok(base64_data) => {
+            crate::touch(&path)?;
+            Ok(Image::from(base64_data))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound || e.kind() == io::ErrorKind::InvalidData => {
+            let img = Image::from(read()?);
+            crate::write_atomic(&path, img.base64_data.as_bytes())?;
+            crate::prune_dir(&dir, IMAGE_MAX_AGE)?;
+            Ok(img)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Converts PNG data to a non-template image like the [`From`] implementation of [`Image`], but reuses the base64 encoding of the same data from a previous call, even from an earlier run of the plugin.
+///
+/// Cache entries are keyed by a hash of the data, so the data still needs to be available on each call. Use [`image_file`] to also avoid reading the image.
+#[cfg(feature = "base64")]
+#[cfg_attr(docsrs, doc(cfg(feature = "base64")))]
+pub fn image(data: impl AsRef<[u8]>) -> io::Result<Image> {
+    let data = data.as_ref();
+    cached_image(data, || Ok(data.to_owned()))
+}
+
+/// Reads a PNG file and converts it to a non-template image. If the file hasn't changed since a previous call, even from an earlier run of the plugin, the cached base64 encoding is used without reading the file.
+///
+/// Cache entries are keyed by the canonical path, size, and modification time of the file.
+#[cfg(feature = "base64")]
+#[cfg_attr(docsrs, doc(cfg(feature = "base64")))]
+pub fn image_file(path: impl AsRef<Path>) -> io::Result<Image> {
+    let path = fs::canonicalize(path)?;
+    let metadata = fs::metadata(&path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut key = path.as_os_str().as_encoded_bytes().to_owned();
+    key.push(0);
+    key.extend_from_slice(&metadata.len().to_le_bytes());
+    key.extend_from_slice(&modified.as_nanos().to_le_bytes());
+    cached_image(&key, || fs::read(&path))
+}
@@ -0,0 +1,291 @@
+//! Typed plugin configuration, layered from several sources.
+//!
+//! From lowest to highest priority, a configuration value is taken from:
+//!
+//! 1. the `Default` implementation of the configuration type,
+//! 2. the TOML file at [`path`] (in the plugin data directory),
+//! 3. xbar plugin variables, i.e. environment variables named like the key in uppercase with a `VAR_` prefix,
+//! 4. SwiftBar environment variables (as declared in `<swiftbar.environment>` metadata), named like the key in uppercase.
+//!
+//! Environment variables are only considered for keys which are present in the serialized default configuration, so `Option` fields which default to `None` can only be set in the config file.
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     serde::{
+//!         Deserialize,
+//!         Serialize,
+//!     },
+//!     bitbar::{
+//!         ContentItem,
+//!         Flavor,
+//!         Menu,
+//!         MenuItem,
+//!         config,
+//!     },
+//! };
+//!
+//! #[derive(Default, Serialize, Deserialize)]
+//! #[serde(default)]
+//! struct Config {
+//!     show_seconds: bool,
+//!     label: String,
+//! }
+//!
+//! #[bitbar::main]
+//! fn main(flavor: Flavor) -> Result<Menu, config::Error> {
+//!     let config = config::load::<Config>()?;
+//!     Ok(Menu(vec![
+//!         MenuItem::new(config.label.clone()),
+//!         MenuItem::Sep,
+//!         ContentItem::new("Settings").sub(config::settings_menu(&config, flavor)?).into(),
+//!     ]))
+//! }
+//! ```
+
+use {
+    std::{
+        fs,
+        io,
+        path::{
+            Path,
+            PathBuf,
+        },
+        process::Command,
+        sync::Arc,
+    },
+    serde::{
+        Serialize,
+        de::DeserializeOwned,
+    },
+    thiserror::Error,
+    toml::{
+        Table,
+        Value,
+    },
+    crate::{
+        Flavor,
+        Menu,
+        MenuItem,
+        attr::Params,
+        checked_item,
+        current_exe,
+        flavor::{
+            EnvSource,
+            ProcessEnv,
+        },
+    },
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use std::{
+    future::Future,
+    pin::Pin,
+};
+
+const SET_COMMAND: &str = "bitbar-config-set";
+
+/// An error that can occur when loading or editing the plugin configuration.
+#[derive(Debug, Error, Clone)]
+pub enum Error {
+    /// The config file could not be parsed, or the layered configuration did not match the configuration type
+    #[error(transparent)] De(#[from] toml::de::Error),
+    /// An I/O error occurred while reading or writing the config file
+    #[error(transparent)] Io(Arc<io::Error>),
+    /// Neither the `SWIFTBAR_PLUGIN_DATA_PATH` nor the `HOME` environment variable is set
+    #[error("could not determine the plugin data directory")]
+    MissingDataDir,
+    /// The configuration type could not be serialized
+    #[error(transparent)] Ser(#[from] toml::ser::Error),
+    /// The configuration type does not serialize to a table
+    #[error("configuration must serialize to a table")]
+    NotATable,
+    /// The settings command was called with invalid arguments
+    #[error("invalid arguments for {SET_COMMAND}")]
+    SetCommandArgs,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(Arc::new(e))
+    }
+}
+
+impl From<Error> for Menu {
+    fn from(e: Error) -> Menu {
+        let mut menu = vec![MenuItem::new("Error in plugin configuration")];
+        match e {
+            Error::De(e) => {
+                menu.push(MenuItem::new(format!("error parsing configuration: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Io(e) => {
+                menu.push(MenuItem::new(format!("I/O error: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Ser(e) => {
+                menu.push(MenuItem::new(format!("error serializing configuration: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::MissingDataDir | Error::NotATable | Error::SetCommandArgs => menu.push(MenuItem::new(e)),
+        }
+        Menu(menu)
+    }
+}
+
+/// Returns the plugin data directory. See [`crate::data_dir`] for details.
+pub fn data_dir() -> Result<PathBuf, Error> {
+    Ok(crate::find_data_dir().ok_or(Error::MissingDataDir)??)
+}
+
+/// Returns the path to the config file, `config.toml` in the [plugin data directory](data_dir).
+pub fn path() -> Result<PathBuf, Error> {
+    Ok(data_dir()?.join("config.toml"))
+}
+
+/// Loads the configuration for the current plugin from all sources.
+pub fn load<T: Default + Serialize + DeserializeOwned>() -> Result<T, Error> {
+    load_from(&path()?, &ProcessEnv)
+}
+
+/// Loads the configuration from the given config file (which may not exist) and environment.
+pub fn load_from<T: Default + Serialize + DeserializeOwned>(path: &Path, env: &dyn EnvSource) -> Result<T, Error> {
+    let Value::Table(mut table) = Value::try_from(T::default())? else { return Err(Error::NotATable) };
+    merge(&mut table, read_file(path)?);
+    for (key, value) in &mut table {
+        let var_name = key.to_uppercase();
+        if let Some(raw) = env.var(&var_name).or_else(|| env.var(&format!("VAR_{var_name}"))) {
+            *value = coerce(value, raw);
+        }
+    }
+    Ok(Value::Table(table).try_into()?)
+}
+
+/// Sets a single top-level key in the config file, creating the file if it doesn't exist.
+pub fn set(key: &str, value: impl Into<Value>) -> Result<(), Error> {
+    let path = path()?;
+    let mut table = read_file(&path)?;
+    table.insert(key.to_owned(), value.into());
+    crate::write_atomic(&path, toml::to_string_pretty(&table)?.as_bytes())?;
+    Ok(())
+}
+
+/// Generates a submenu for editing the given configuration.
+///
+/// Boolean values are toggled by clicking them, while clicking a string or number value shows a dialog to enter a new value. Other values are displayed but can't be edited from the menu. Edited values are written to the [config file](path), so they are overridden by any environment variables.
+pub fn settings_menu<T: Serialize>(config: &T, flavor: Flavor) -> Result<Menu, Error> {
+    let Value::Table(table) = Value::try_from(config)? else { return Err(Error::NotATable) };
+    let exe = current_exe()?;
+    Ok(table.into_iter().map(|(key, value)| {
+        let command = |args: Vec<String>| Params::new(exe.clone(), [SET_COMMAND.to_owned()].into_iter().chain(args).collect());
+        match value {
+            Value::Boolean(value) => checked_item(&key, value, flavor)
+                .command(command(vec![key.clone(), "boolean".to_owned(), (!value).to_string()])).unwrap_or_else(|never| match never {})
+                .refresh()
+                .into(),
+            Value::String(ref text) => prompt_item(&key, text, command(vec!["--prompt".to_owned(), key.clone(), "string".to_owned(), text.clone()])),
+            Value::Integer(_) => prompt_item(&key, &value, command(vec!["--prompt".to_owned(), key.clone(), "integer".to_owned(), value.to_string()])),
+            Value::Float(_) => prompt_item(&key, &value, command(vec!["--prompt".to_owned(), key.clone(), "float".to_owned(), value.to_string()])),
+            Value::Datetime(_) | Value::Array(_) | Value::Table(_) => MenuItem::new(format!("{key}: {value}")),
+        }
+    }).collect())
+}
+
+fn prompt_item(key: &str, value: &dyn std::fmt::Display, command: Params) -> MenuItem {
+    crate::ContentItem::new(format!("{key}: {value}"))
+        .command(command).unwrap_or_else(|never| match never {})
+        .refresh()
+        .into()
+}
+
+fn read_file(path: &Path) -> Result<Table, Error> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.parse()?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Table::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overrides)) => merge(base, overrides),
+            (_, value) => { base.insert(key, value); }
+        }
+    }
+}
+
+/// Converts the raw value of an environment variable to the type of the value it replaces.
+fn coerce(existing: &Value, raw: String) -> Value {
+    match existing {
+        Value::Integer(_) => raw.parse().map(Value::Integer).unwrap_or(Value::String(raw)),
+        Value::Float(_) => raw.parse().map(Value::Float).unwrap_or(Value::String(raw)),
+        Value::Boolean(_) => raw.parse().map(Value::Boolean).unwrap_or(Value::String(raw)),
+        _ => Value::String(raw),
+    }
+}
+
+fn parse_typed(ty: &str, raw: String) -> Option<Value> {
+    match ty {
+        "string" => Some(Value::String(raw)),
+        "integer" => raw.trim().parse().ok().map(Value::Integer),
+        "float" => raw.trim().parse().ok().map(Value::Float),
+        "boolean" => raw.trim().parse().ok().map(Value::Boolean),
+        _ => None,
+    }
+}
+
+/// Shows a dialog asking for a new value. Returns `None` if the dialog was cancelled.
+fn prompt(key: &str, current: &str) -> io::Result<Option<String>> {
+    let output = Command::new("osascript")
+        .arg("-e").arg("on run argv")
+        .arg("-e").arg("text returned of (display dialog (item 1 of argv) default answer (item 2 of argv))")
+        .arg("-e").arg("end run")
+        .arg(key)
+        .arg(current)
+        .output()?;
+    if !output.status.success() { return Ok(None) }
+    let mut text = String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if text.ends_with('\n') { text.pop(); }
+    Ok(Some(text))
+}
+
+fn run_set_command(args: Vec<String>) -> Result<(), Error> {
+    let (key, ty, raw) = match <[String; 3]>::try_from(args) {
+        Ok([key, ty, raw]) => (key, ty, raw),
+        Err(args) => match <[String; 4]>::try_from(args) {
+            Ok([flag, key, ty, current]) if flag == "--prompt" => {
+                let Some(raw) = prompt(&key, &current)? else { return Ok(()) };
+                (key, ty, raw)
+            }
+            _ => return Err(Error::SetCommandArgs),
+        },
+    };
+    set(&key, parse_typed(&ty, raw).ok_or(Error::SetCommandArgs)?)
+}
+
+fn set_command_sync(args: Vec<String>) {
+    if let Err(e) = run_set_command(args) {
+        crate::notify_error(&format!("{SET_COMMAND}: {e}"), &format!("{e:?}"));
+        std::process::exit(1)
+    }
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+fn set_command(args: Vec<String>) -> Pin<Box<dyn Future<Output = ()>>> {
+    Box::pin(async move { set_command_sync(args) })
+}
+
+#[cfg(not(any(feature = "async-std", feature = "futures-executor", feature = "tokio")))]
+fn set_command(args: Vec<String>) {
+    set_command_sync(args)
+}
+
+inventory::submit! {
+    crate::CommandRegistration {
+        name: SET_COMMAND,
+        params: &[
+            crate::CommandParam { name: "args", ty: "Vec<String>", kind: "varargs", default: None },
+        ],
+        run: set_command,
+    }
+}
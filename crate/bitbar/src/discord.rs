@@ -0,0 +1,123 @@
+//! Helpers for plugins showing Discord status, e.g. the presence of friends or the role colors of server members.
+//!
+//! These don't depend on any Discord library, so they can be used with any version of [`serenity`](https://docs.rs/serenity) or another library. With the `serenity` feature, the corresponding [`serenity`](https://docs.rs/serenity) types can also be converted directly.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{
+//!     ContentItem,
+//!     Menu,
+//!     discord::{
+//!         EmbedColor,
+//!         Presence,
+//!     },
+//! };
+//!
+//! let menu = Menu(vec![
+//!     Presence::Online.item("fenhl").into(),
+//!     Presence::DoNotDisturb.item("Wurstmineberg").into(),
+//!     ContentItem::new("@Moderator").color(EmbedColor(0x1abc9c)).unwrap().into(),
+//! ]);
+//! assert_eq!(menu.to_string(), "● fenhl | color=#23a55a\n● Wurstmineberg | color=#f23f43\n@Moderator | color=#1abc9c\n");
+//! ```
+
+use {
+    std::fmt,
+    crate::{
+        ContentItem,
+        attr::Color,
+    },
+};
+
+/// A color in the `0xRRGGBB` format used by the Discord API for embeds and roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EmbedColor(pub u32);
+
+impl From<EmbedColor> for Color {
+    fn from(EmbedColor(rgb): EmbedColor) -> Color {
+        Color {
+            light: css_color_parser::Color {
+                r: (rgb >> 16) as u8,
+                g: (rgb >> 8) as u8,
+                b: rgb as u8,
+                a: 1.0,
+            },
+            dark: None,
+        }
+    }
+}
+
+#[cfg(feature = "serenity")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serenity")))]
+impl From<serenity::model::Colour> for EmbedColor {
+    fn from(c: serenity::model::Colour) -> EmbedColor {
+        EmbedColor(c.0)
+    }
+}
+
+/// The online status of a Discord user, as shown by the colored dot on their avatar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Presence {
+    /// Displayed as a green dot.
+    Online,
+    /// Displayed as a yellow dot.
+    Idle,
+    /// Displayed as a red dot.
+    DoNotDisturb,
+    /// Displayed as a purple dot. Discord reports this as an activity rather than a status.
+    Streaming,
+    /// Displayed as a gray dot. Invisible users also appear offline to others.
+    Offline,
+}
+
+impl Presence {
+    /// The color of the dot Discord uses to display this status.
+    pub fn color(&self) -> EmbedColor {
+        EmbedColor(match self {
+            Self::Online => 0x23a55a,
+            Self::Idle => 0xf0b232,
+            Self::DoNotDisturb => 0xf23f43,
+            Self::Streaming => 0x593695,
+            Self::Offline => 0x80848e,
+        })
+    }
+
+    /// A menu item showing the given text, e.g. a user name, prefixed with a dot in the color of this status.
+    pub fn item(&self, text: impl fmt::Display) -> ContentItem {
+        ContentItem::new(format!("● {text}")).color(self.color()).unwrap_or_else(|never| match never {})
+    }
+}
+
+impl From<Presence> for Color {
+    fn from(presence: Presence) -> Color {
+        presence.color().into()
+    }
+}
+
+impl fmt::Display for Presence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Online => write!(f, "Online"),
+            Self::Idle => write!(f, "Idle"),
+            Self::DoNotDisturb => write!(f, "Do Not Disturb"),
+            Self::Streaming => write!(f, "Streaming"),
+            Self::Offline => write!(f, "Offline"),
+        }
+    }
+}
+
+#[cfg(feature = "serenity")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serenity")))]
+impl From<serenity::model::user::OnlineStatus> for Presence {
+    fn from(status: serenity::model::user::OnlineStatus) -> Presence {
+        use serenity::model::user::OnlineStatus;
+
+        match status {
+            OnlineStatus::Online => Self::Online,
+            OnlineStatus::Idle => Self::Idle,
+            OnlineStatus::DoNotDisturb => Self::DoNotDisturb,
+            _ => Self::Offline,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Conversion of `:shortcode:`-style emoji names to Unicode emoji.
+//!
+//! BitBar implementations differ in whether and how they replace shortcodes in menu item text (see the `emojize` parameter), so converting them in the plugin makes the output consistent across implementations.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{
+//!     ContentItem,
+//!     emoji,
+//! };
+//!
+//! assert_eq!(emoji::emojize(":package: shipped :tada:"), "📦 shipped 🎉");
+//! assert_eq!(emoji::emojize("12:30:00"), "12:30:00");
+//! assert_eq!(ContentItem::new(":white_check_mark: done").emojize().text, "✅ done");
+//! ```
+
+use std::borrow::Cow;
+
+/// Replaces `:shortcode:`-style emoji names (as used by GitHub and Slack) in the given text with the corresponding Unicode emoji.
+///
+/// Text between colons that isn't a known shortcode is left unchanged.
+pub fn emojize(text: &str) -> Cow<'_, str> {
+    let mut result = String::default();
+    let mut rest = text;
+    let mut replaced = false;
+    while let Some(start) = rest.find(':') {
+        let Some(len) = rest[start + 1..].find(':') else { break };
+        let shortcode = &rest[start + 1..start + 1 + len];
+        if let Some(emoji) = emojis::get_by_shortcode(shortcode) {
+            result.push_str(&rest[..start]);
+            result.push_str(emoji.as_str());
+            rest = &rest[start + len + 2..];
+            replaced = true;
+        } else {
+            // the closing colon may start another shortcode
+            result.push_str(&rest[..start + 1 + len]);
+            rest = &rest[start + 1 + len..];
+        }
+    }
+    if replaced {
+        result.push_str(rest);
+        Cow::Owned(result)
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::borrow::Cow,
+        super::emojize,
+    };
+
+    #[test]
+    fn shortcodes() {
+        assert_eq!(emojize(":tada: Deployed :rocket:"), "🎉 Deployed 🚀");
+        assert_eq!(emojize("über :tada:!"), "über 🎉!");
+    }
+
+    #[test]
+    fn unknown_shortcodes() {
+        assert!(matches!(emojize("no shortcodes"), Cow::Borrowed("no shortcodes")));
+        assert!(matches!(emojize("at 12:30:45"), Cow::Borrowed("at 12:30:45")));
+        assert!(matches!(emojize(":not_an_emoji:"), Cow::Borrowed(":not_an_emoji:")));
+        assert!(matches!(emojize("unclosed :tada"), Cow::Borrowed("unclosed :tada")));
+    }
+
+    #[test]
+    fn adjacent_colons() {
+        // the closing colon of an unknown shortcode can open the next one
+        assert_eq!(emojize("ratio 3:tada:"), "ratio 3🎉");
+        assert_eq!(emojize(":nope::tada:"), ":nope:🎉");
+        assert_eq!(emojize(":tada::tada:"), "🎉🎉");
+    }
+}
@@ -1,14 +1,45 @@
 //! Features specific to individual BitBar implementations (e.g. [SwiftBar](https://swiftbar.app/))
 
-use std::{
-    borrow::Cow,
-    collections::BTreeMap,
-    fmt,
+use {
+    std::{
+        borrow::Borrow,
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        env,
+        fmt,
+        hash::{
+            BuildHasher,
+            Hash,
+        },
+        sync::{
+            OnceLock,
+            RwLock,
+        },
+    },
+    crate::RenderedParams,
 };
 pub use self::swiftbar::SwiftBar;
 
 pub mod swiftbar;
 
+static FLAVOR: OnceLock<Flavor> = OnceLock::new();
+static DARK_MODE: OnceLock<bool> = OnceLock::new();
+#[cfg(feature = "test")] static OVERRIDE: RwLock<Option<(Flavor, bool)>> = RwLock::new(None);
+
+/// Makes [`Flavor::check`] and [`dark_mode`] return the given flavor and dark mode state instead of the cached ones until reset to `None`.
+#[cfg(feature = "test")]
+pub(crate) fn set_override(flavor_and_dark_mode: Option<(Flavor, bool)>) {
+    *OVERRIDE.write().unwrap_or_else(std::sync::PoisonError::into_inner) = flavor_and_dark_mode;
+}
+
+/// Makes [`Flavor::check`] return the given flavor regardless of the environment. Has no effect if the flavor has already been checked.
+#[cfg(feature = "assume-flavor")]
+#[doc(hidden)] pub fn set_assumed(flavor: Flavor) { // used in proc macro
+    let _ = FLAVOR.set(flavor);
+}
+
 #[derive(Debug, Clone, Copy)]
 /// A BitBar implementation.
 pub enum Flavor {
@@ -16,6 +47,8 @@ pub enum Flavor {
     BitBar,
     /// [SwiftBar](https://swiftbar.app/)
     SwiftBar(SwiftBar),
+    /// A BitBar implementation not natively supported by this crate, registered via [`Flavor::register`].
+    Custom(&'static dyn FlavorImpl),
     //TODO xbar support, blocked on https://github.com/matryer/xbar/issues/753
     //TODO Argos (https://github.com/p-e-w/argos) support? (envar ARGOS_VERSION)
     //TODO kargos (https://github.com/lipido/kargos) support? (needs envar)
@@ -25,13 +58,102 @@ impl Flavor {
     /// Checks which of the supported BitBar implementations the plugin is currently running on,
     /// returning a handle allowing use of implementation-specific features, if any are supported.
     /// Any unsupported implementation will be reported as `BitBar`.
+    ///
+    /// The result is cached for the lifetime of the process, so changes to the environment after the first call are not reflected. Use [`Flavor::check_from`] to check a different environment.
     pub fn check() -> Flavor {
-        if let Some(swiftbar) = SwiftBar::check() {
+        #[cfg(feature = "test")] if let Some((flavor, _)) = *OVERRIDE.read().unwrap_or_else(std::sync::PoisonError::into_inner) { return flavor }
+        *FLAVOR.get_or_init(|| Flavor::check_from(&ProcessEnv))
+    }
+
+    /// Like [`Flavor::check`], but reads environment variables from the given source instead of the process environment. The result is not cached.
+    pub fn check_from(env: &impl EnvSource) -> Flavor {
+        if let Some(custom) = CUSTOM_FLAVORS.read().expect("custom flavor registry poisoned").iter().find(|custom| custom.detect(env)) {
+            Flavor::Custom(*custom)
+        } else if let Some(swiftbar) = SwiftBar::check_from(env) {
             Flavor::SwiftBar(swiftbar)
         } else {
             Flavor::BitBar
         }
     }
+
+    /// Registers a custom BitBar implementation, e.g. a fork of one of the supported implementations.
+    ///
+    /// Custom implementations are checked in the order they were registered, before any of the natively supported implementations.
+    /// Since the result of [`Flavor::check`] is cached, this should be called at the start of the plugin, before the flavor is checked for the first time.
+    pub fn register(custom: &'static dyn FlavorImpl) {
+        CUSTOM_FLAVORS.write().expect("custom flavor registry poisoned").push(custom);
+    }
+
+    /// Returns whether a menu item parameter with the given name (e.g. `sfimage`) will be displayed correctly on this implementation.
+    ///
+    /// Parameters that are not supported are omitted when rendering a menu using [`Menu::display_for`](crate::Menu::display_for).
+    pub fn supports_param(&self, name: &str) -> bool {
+        match self {
+            Flavor::BitBar | Flavor::SwiftBar(_) => true,
+            Flavor::Custom(custom) => custom.supports_param(name),
+        }
+    }
+
+    /// Returns whether this implementation supports [`Color`](crate::attr::Color)s with a separate variant for the dark system theme.
+    ///
+    /// On implementations which don't, [`Menu::display_for`](crate::Menu::display_for) renders only the variant for the current system theme, as reported by [`dark_mode`].
+    pub fn supports_dark_colors(&self) -> bool {
+        match self {
+            Flavor::BitBar => false,
+            Flavor::SwiftBar(_) => true,
+            Flavor::Custom(custom) => custom.supports_dark_colors(),
+        }
+    }
+}
+
+/// Returns whether the system is in dark mode, as reported by the BitBar implementation via environment variables. Returns `false` if this can't be determined.
+///
+/// The result is cached for the lifetime of the process, so changes to the system theme are only reflected when the plugin is rerun.
+pub fn dark_mode() -> bool {
+    #[cfg(feature = "test")] if let Some((_, dark_mode)) = *OVERRIDE.read().unwrap_or_else(std::sync::PoisonError::into_inner) { return dark_mode }
+    *DARK_MODE.get_or_init(|| dark_mode_from(&ProcessEnv))
+}
+
+/// Like [`dark_mode`], but reads environment variables from the given source instead of the process environment. The result is not cached.
+pub fn dark_mode_from(env: &impl EnvSource) -> bool {
+    env.var("OS_APPEARANCE").is_some_and(|appearance| appearance == "Dark") // SwiftBar
+    || env.var("BitBarDarkMode").is_some_and(|dark_mode| dark_mode == "1") // BitBar
+    || env.var("XBARDarkMode").is_some_and(|dark_mode| dark_mode == "true") // xbar
+}
+
+/// Returns whether the plugin was run on its own, e.g. from a terminal, rather than by a BitBar implementation.
+pub(crate) fn standalone() -> bool {
+    matches!(Flavor::check(), Flavor::BitBar)
+    && env::var_os("BitBar").is_none() // BitBar
+    && env::var_os("XBARDarkMode").is_none() // xbar
+}
+
+static CUSTOM_FLAVORS: RwLock<Vec<&'static dyn FlavorImpl>> = RwLock::new(Vec::new());
+
+/// A BitBar implementation not natively supported by this crate.
+///
+/// Implement this trait and call [`Flavor::register`] to support a fork of one of the supported implementations without waiting for upstream support in this crate.
+pub trait FlavorImpl: fmt::Debug + Send + Sync {
+    /// The name of this implementation, used by the [`Display`](fmt::Display) implementation of [`Flavor`].
+    fn name(&self) -> &str;
+
+    /// Checks whether the plugin is running on this implementation, based on the given environment variables.
+    fn detect(&self, env: &dyn EnvSource) -> bool;
+
+    /// Returns whether a menu item parameter with the given name will be displayed correctly on this implementation.
+    ///
+    /// The default implementation only supports the parameters supported by the original BitBar.
+    fn supports_param(&self, name: &str) -> bool {
+        matches!(name,
+            "href" | "color" | "font" | "size" | "bash" | "terminal" | "refresh" | "dropdown" | "length" | "trim" | "alternate" | "emojize" | "ansi" | "image" | "templateImage"
+            | "param1" | "param2" | "param3" | "param4" | "param5"
+        )
+    }
+
+    /// Returns whether this implementation supports [`Color`](crate::attr::Color)s with a separate variant for the dark system theme, like SwiftBar's `color=light,dark` syntax.
+    ///
+    /// The default implementation returns `false`, so only the variant for the current system theme is rendered.
+    fn supports_dark_colors(&self) -> bool { false }
 }
 
 impl fmt::Display for Flavor {
@@ -39,10 +161,47 @@ impl fmt::Display for Flavor {
         match self {
             Flavor::SwiftBar(_) => write!(f, "SwiftBar"),
             Flavor::BitBar => write!(f, "BitBar"),
+            Flavor::Custom(custom) => write!(f, "{}", custom.name()),
         }
     }
 }
 
+/// A source of environment variables, used to check which BitBar implementation a plugin is running on.
+///
+/// This is implemented for [`ProcessEnv`] as well as for maps from variable names to values, which can be used to simulate environments in tests.
+pub trait EnvSource {
+    /// Returns the value of the given environment variable, or `None` if it is unset or not valid Unicode.
+    fn var(&self, key: &str) -> Option<String>;
+}
+
+/// The environment of the current process, as read by [`std::env::var`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessEnv;
+
+impl EnvSource for ProcessEnv {
+    fn var(&self, key: &str) -> Option<String> {
+        env::var(key).ok()
+    }
+}
+
+impl<K: Borrow<str> + Ord, V: AsRef<str>> EnvSource for BTreeMap<K, V> {
+    fn var(&self, key: &str) -> Option<String> {
+        self.get(key).map(|value| value.as_ref().to_owned())
+    }
+}
+
+impl<K: Borrow<str> + Eq + Hash, V: AsRef<str>, S: BuildHasher> EnvSource for HashMap<K, V, S> {
+    fn var(&self, key: &str) -> Option<String> {
+        self.get(key).map(|value| value.as_ref().to_owned())
+    }
+}
+
+impl<T: EnvSource + ?Sized> EnvSource for &T {
+    fn var(&self, key: &str) -> Option<String> {
+        (**self).var(key)
+    }
+}
+
 /// Flavor-specific [`ContentItem`](crate::ContentItem) attributes.
 #[derive(Debug)]
 #[allow(missing_docs)]
@@ -50,8 +209,16 @@ pub enum Attrs {
     SwiftBar(swiftbar::Attrs),
 }
 
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Attrs {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Attrs> {
+        Ok(Attrs::SwiftBar(u.arbitrary()?))
+    }
+}
+
 impl Attrs {
-    pub(crate) fn render<'a>(&'a self, rendered_params: &mut BTreeMap<Cow<'a, str>, Cow<'a, str>>) {
+    pub(crate) fn render<'a>(&'a self, rendered_params: &mut RenderedParams<'a>) {
         match self {
             Attrs::SwiftBar(params) => params.render(rendered_params),
         }
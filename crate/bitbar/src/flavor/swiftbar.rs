@@ -3,23 +3,30 @@
 use {
     std::{
         borrow::Cow,
-        collections::BTreeMap,
         convert::TryInto,
         env,
         io,
         iter,
-        path::Path,
+        path::{
+            Path,
+            PathBuf,
+        },
         sync::Arc,
     },
     open::that as open,
     semver::Version,
     thiserror::Error,
     url::Url,
+    super::{
+        EnvSource,
+        ProcessEnv,
+    },
     crate::{
         ContentItem,
         MainOutput,
         Menu,
         MenuItem,
+        RenderedParams,
         attr::{
             Color,
             Command,
@@ -30,17 +37,28 @@ use {
     },
 };
 #[cfg(feature = "assume-flavor")] use static_assertions::const_assert;
-#[cfg(feature = "tokio")] use {
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use {
     std::pin::Pin,
     futures::{
         future::Future,
+        io::{
+            AllowStdIo,
+            AsyncWrite,
+            AsyncWriteExt as _,
+        },
         stream::StreamExt as _,
     },
+    std::time::Duration,
     crate::AsyncMainOutput,
 };
 
-/// The highest build number checked for conditional features.
-#[cfg(feature = "assume-flavor")] const MAX_BUILD: usize = 402;
+/// The build number of SwiftBar 1.4.3 beta 1, the first version which expects [trailing stream separators](StreamFrame).
+pub const BUILD_1_4_3_BETA_1: usize = 399;
+/// The build number of SwiftBar 1.4.3 beta 4, the first version which can run a [command](Notification::command) when a notification is clicked.
+pub const BUILD_1_4_3_BETA_4: usize = 402;
+
+/// The highest build number checked for conditional features. [`SwiftBar::assume`] assumes this build.
+pub const MAX_BUILD: usize = BUILD_1_4_3_BETA_4;
 
 macro_rules! build_ge {
     ($swiftbar:expr, $build:expr) => {{
@@ -62,9 +80,19 @@ pub struct SwiftBar {
 impl SwiftBar {
     /// Checks whether the plugins is running in SwiftBar by checking environment variables.
     /// If it does, returns a handle allowing use of SwiftBar-specific features.
+    ///
+    /// This uses the cached result of [`Flavor::check`](super::Flavor::check).
     pub fn check() -> Option<Self> {
+        match super::Flavor::check() {
+            super::Flavor::SwiftBar(swiftbar) => Some(swiftbar),
+            _ => None,
+        }
+    }
+
+    /// Like [`SwiftBar::check`], but reads environment variables from the given source instead of the process environment. The result is not cached.
+    pub fn check_from(env: &impl EnvSource) -> Option<Self> {
         Some(Self {
-            build: env::var("SWIFTBAR_BUILD").ok()?.parse().ok()?,
+            build: env.var("SWIFTBAR_BUILD")?.parse().ok()?,
         })
     }
 
@@ -87,11 +115,118 @@ impl SwiftBar {
         )
     }
 
+    /// A `file:` URL for SwiftBar's plugin folder, which opens the folder in Finder when used as a menu item's [`href`](ContentItem::href).
+    pub fn plugins_folder_url(&self) -> Result<Url, PluginsFolderError> {
+        Url::from_directory_path(env::var_os("SWIFTBAR_PLUGINS_PATH").ok_or(PluginsFolderError::Env)?).map_err(|()| PluginsFolderError::RelativePath)
+    }
+
+    /// A command which opens SwiftBar's preferences window, for use with a menu item's [`command`](ContentItem::command).
+    ///
+    /// SwiftBar's URL scheme has no endpoint for this, so the command activates SwiftBar, waits up to 5 seconds for it to become the frontmost app, and then sends it the <kbd>⌘,</kbd> keyboard shortcut using JavaScript for Automation. If SwiftBar doesn't become frontmost in time, the command fails instead of sending the shortcut to another app.
+    ///
+    /// Sending keystrokes requires SwiftBar to be granted Accessibility permission (in System Settings → Privacy & Security → Accessibility) in addition to the permission to control System Events which macOS asks for the first time the command is run. Without it, the command fails with an error saying that it's not allowed to send keystrokes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use {
+    ///     std::collections::BTreeMap,
+    ///     bitbar::{
+    ///         ContentItem,
+    ///         Flavor,
+    ///         Menu,
+    ///         flavor::SwiftBar,
+    ///     },
+    /// };
+    ///
+    /// let swiftbar = SwiftBar::check_from(&BTreeMap::from([("SWIFTBAR_BUILD", "402")])).unwrap();
+    /// let menu = Menu(vec![ContentItem::new("Preferences…").command(swiftbar.preferences_command()).unwrap().into()]);
+    /// assert!(menu.validate(Flavor::SwiftBar(swiftbar)).is_empty());
+    /// ```
+    pub fn preferences_command(&self) -> Params {
+        // JavaScript is used instead of AppleScript since its string literals can use single quotes, and parameter values can't contain both spaces and double quotes
+        Params::new("/usr/bin/osascript".to_owned(), [
+            "-l",
+            "JavaScript",
+            "-e",
+            "const events = Application('System Events');",
+            "-e",
+            "Application('com.ameba.SwiftBar').activate();",
+            "-e",
+            "const swiftbar = events.applicationProcesses.whose({ bundleIdentifier: 'com.ameba.SwiftBar' })[0];",
+            "-e",
+            "for (let i = 0; i < 50 && !swiftbar.frontmost(); i++) delay(0.1);",
+            "-e",
+            "if (!swiftbar.frontmost()) throw new Error('SwiftBar did not become the frontmost app');",
+            "-e",
+            "events.keystroke(',', { using: 'command down' });",
+        ].into_iter().map(str::to_owned).collect())
+    }
+
     /// Returns the SwiftBar version on which the plugin is running by checking environment variables.
     pub fn running_version(&self) -> Result<Version, VersionCheckError> {
         Ok(env::var("SWIFTBAR_VERSION")?.parse()?)
     }
 
+    /// The build number of the SwiftBar version on which the plugin is running, e.g. [`BUILD_1_4_3_BETA_4`].
+    ///
+    /// With [`SwiftBar::assume`], this is the latest build known to this crate.
+    pub fn build(&self) -> usize {
+        self.build
+    }
+
+    /// Whether the running SwiftBar version has at least the given build number. This can be used to check for features which this crate doesn't know about.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use {
+    ///     std::collections::BTreeMap,
+    ///     bitbar::flavor::swiftbar::{
+    ///         self,
+    ///         SwiftBar,
+    ///     },
+    /// };
+    ///
+    /// let swiftbar = SwiftBar::check_from(&BTreeMap::from([("SWIFTBAR_BUILD", "400")])).unwrap();
+    /// assert_eq!(swiftbar.build(), 400);
+    /// assert!(swiftbar.at_least_build(swiftbar::BUILD_1_4_3_BETA_1));
+    /// assert!(!swiftbar.at_least_build(swiftbar::BUILD_1_4_3_BETA_4));
+    /// ```
+    pub fn at_least_build(&self, build: usize) -> bool {
+        self.build >= build
+    }
+
+    /// Whether the running SwiftBar version, as reported by [`SwiftBar::running_version`], is at least the given [semantic version](https://semver.org/), e.g. `"1.4.3"`. Prerelease versions like `1.4.3-beta.4` are older than the corresponding release.
+    ///
+    /// Unlike the build number, the version is read from the environment each time, so [`SwiftBar::at_least_build`] should be preferred if the build number is known.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`SwiftBar::running_version`], returns [`VersionCheckError::Parse`] if `version` is not a valid semantic version.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use {
+    ///     std::collections::BTreeMap,
+    ///     bitbar::flavor::swiftbar::{
+    ///         SwiftBar,
+    ///         VersionCheckError,
+    ///     },
+    /// };
+    ///
+    /// std::env::set_var("SWIFTBAR_VERSION", "1.4.3");
+    /// let swiftbar = SwiftBar::check_from(&BTreeMap::from([("SWIFTBAR_BUILD", "403")])).unwrap();
+    /// assert!(swiftbar.at_least_version("1.4.3-beta.4").unwrap());
+    /// assert!(!swiftbar.at_least_version("1.4.4").unwrap());
+    /// assert!(matches!(swiftbar.at_least_version("1.4"), Err(VersionCheckError::Parse(_))));
+    /// ```
+    pub fn at_least_version(&self, version: &str) -> Result<bool, VersionCheckError> {
+        let version = version.parse::<Version>()?;
+        Ok(self.running_version()? >= version)
+    }
+
     /// Unlike BitBar, SwiftBar supports more than 5 parameters for `bash=` commands.
     pub fn command(&self, cmd: impl IntoParams) -> Params {
         cmd.into_params(self)
@@ -171,7 +306,7 @@ impl_into_params!(14, param1: A, param2: B, param3: C, param4: D, param5: E, par
 impl_into_params!(15, param1: A, param2: B, param3: C, param4: D, param5: E, param6: F, param7: G, param8: H, param9: I, param10: J, param11: K, param12: L, param13: M, param14: N);
 impl_into_params!(16, param1: A, param2: B, param3: C, param4: D, param5: E, param6: F, param7: G, param8: H, param9: I, param10: J, param11: K, param12: L, param13: M, param14: N, param15: O);
 
-impl<'a, T: ToString> IntoParams for &'a [T] {
+impl<T: ToString> IntoParams for &[T] {
     /// # Panics
     ///
     /// If `self` is empty.
@@ -202,9 +337,20 @@ pub struct Attrs {
     sf_image: Option<String>,
 }
 
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Attrs {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Attrs> {
+        Ok(Attrs {
+            checked: u.arbitrary()?,
+            sf_image: u.arbitrary()?,
+        })
+    }
+}
+
 impl Attrs {
     fn for_item(item: &mut ContentItem) -> &mut Attrs {
-        match item.flavor_attrs.get_or_insert_with(|| super::Attrs::SwiftBar(Attrs {
+        match item.flavor_attrs.get_or_insert(super::Attrs::SwiftBar(Attrs {
             checked: false,
             sf_image: None,
         })) {
@@ -212,12 +358,12 @@ impl Attrs {
         }
     }
 
-    pub(crate) fn render<'a>(&'a self, rendered_params: &mut BTreeMap<Cow<'a, str>, Cow<'a, str>>) {
+    pub(crate) fn render<'a>(&'a self, rendered_params: &mut RenderedParams<'a>) {
         if self.checked {
-            rendered_params.insert(Cow::Borrowed("checked"), Cow::Borrowed("true"));
+            rendered_params.push((Cow::Borrowed("checked"), Cow::Borrowed("true")));
         }
         if let Some(ref sf_image) = self.sf_image {
-            rendered_params.insert(Cow::Borrowed("sfimage"), Cow::Borrowed(sf_image));
+            rendered_params.push((Cow::Borrowed("sfimage"), Cow::Borrowed(sf_image)));
         }
     }
 }
@@ -227,7 +373,7 @@ impl Attrs {
 pub enum VersionCheckError {
     /// The `SWIFTBAR_VERSION` environment variable was unset or not valid UTF-8
     #[error(transparent)] Env(#[from] env::VarError),
-    /// The `SWIFTBAR_VERSION` environment variable was not a valid semantic version
+    /// The `SWIFTBAR_VERSION` environment variable, or the version passed to [`SwiftBar::at_least_version`], was not a valid semantic version
     #[error(transparent)] Parse(Arc<semver::Error>),
 }
 
@@ -274,6 +420,26 @@ impl From<PluginNameError> for Menu {
     }
 }
 
+/// An error that can occur in [`SwiftBar::plugins_folder_url`].
+#[derive(Debug, Error, Clone)]
+pub enum PluginsFolderError {
+    /// The `SWIFTBAR_PLUGINS_PATH` environment variable was unset
+    #[error("missing `SWIFTBAR_PLUGINS_PATH` environment variable")]
+    Env,
+    /// The `SWIFTBAR_PLUGINS_PATH` environment variable was not an absolute path
+    #[error("`SWIFTBAR_PLUGINS_PATH` environment variable is not an absolute path")]
+    RelativePath,
+}
+
+impl From<PluginsFolderError> for Menu {
+    fn from(e: PluginsFolderError) -> Menu {
+        Menu(vec![
+            MenuItem::new("Error checking SwiftBar plugin folder"),
+            MenuItem::new(e.to_string()),
+        ])
+    }
+}
+
 /// An error that can occur in [`Notification::command`].
 #[derive(Debug, Error, Clone)]
 pub enum NotificationCommandError<C: TryInto<Command>>
@@ -309,7 +475,7 @@ pub struct Notification {
     body: Option<String>,
     href: Option<Url>,
     command: Option<Command>,
-    silent: bool,
+    sound: Sound,
 }
 
 impl Notification {
@@ -325,7 +491,7 @@ impl Notification {
             body: None,
             href: None,
             command: None,
-            silent: false,
+            sound: Sound::Default,
         })
     }
 
@@ -356,7 +522,7 @@ impl Notification {
     /// Makes this notification run the given command when clicked.
     pub fn command<C: TryInto<Command>>(mut self, cmd: C) -> Result<Self, NotificationCommandError<C>>
     where C::Error: std::error::Error {
-        if build_ge!(self.swiftbar, 402) {
+        if build_ge!(self.swiftbar, BUILD_1_4_3_BETA_4) {
             self.command = Some(cmd.try_into().map_err(NotificationCommandError::Command)?);
             Ok(self)
         } else {
@@ -364,9 +530,39 @@ impl Notification {
         }
     }
 
-    /// Disables sound for this notification.
-    pub fn silent(mut self) -> Self {
-        self.silent = true;
+    /// Disables sound for this notification. Same as `.sound(Sound::None)`.
+    pub fn silent(self) -> Self {
+        self.sound(Sound::None)
+    }
+
+    /// Sets the sound played for this notification.
+    ///
+    /// SwiftBar only documents disabling the sound, so [`Sound::None`] is passed as the `silent` parameter of the notification URL, while all other sounds currently play the default sound.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use {
+    ///     std::collections::BTreeMap,
+    ///     bitbar::{
+    ///         attr::IntoUrl as _,
+    ///         flavor::swiftbar::{
+    ///             Notification,
+    ///             Sound,
+    ///             SwiftBar,
+    ///         },
+    ///     },
+    /// };
+    ///
+    /// std::env::set_var("SWIFTBAR_PLUGIN_PATH", "/plugins/example.1m.o");
+    /// let swiftbar = SwiftBar::check_from(&BTreeMap::from([("SWIFTBAR_BUILD", "402")])).unwrap();
+    /// let url = Notification::new(swiftbar).unwrap().sound(Sound::Glass).into_url().unwrap();
+    /// assert_eq!(url.as_str(), "swiftbar://notify?plugin=example.1m.o");
+    /// let url = Notification::new(swiftbar).unwrap().silent().into_url().unwrap();
+    /// assert_eq!(url.as_str(), "swiftbar://notify?plugin=example.1m.o&silent=true");
+    /// ```
+    pub fn sound(mut self, sound: impl Into<Sound>) -> Self {
+        self.sound = sound.into();
         self
     }
 
@@ -382,23 +578,205 @@ impl IntoUrl for Notification {
     }
 }
 
-impl<'a> IntoUrl for &'a Notification {
+impl IntoUrl for &Notification {
     fn into_url(self) -> Result<Url, url::ParseError> {
-        let Notification { swiftbar: _, plugin_name, title, subtitle, body, command, href, silent } = self;
+        let Notification { swiftbar: _, plugin_name, title, subtitle, body, command, href, sound } = self;
         Url::parse_with_params("swiftbar://notify", iter::once((Cow::Borrowed("plugin"), &**plugin_name))
             .chain(title.as_deref().map(|title| (Cow::Borrowed("title"), title)))
             .chain(subtitle.as_deref().map(|subtitle| (Cow::Borrowed("subtitle"), subtitle)))
             .chain(body.as_deref().map(|body| (Cow::Borrowed("body"), body)))
             .chain(command.iter().flat_map(|command| iter::once((Cow::Borrowed("bash"), &*command.params.cmd))
                 .chain(command.params.params.iter().enumerate().map(|(n, arg)| (Cow::Owned(format!("param{}", n + 1)), &**arg)))
-                .chain((!command.terminal).then(|| (Cow::Borrowed("terminal"), "false")))
+                .chain((!command.terminal).then_some((Cow::Borrowed("terminal"), "false")))
             ))
             .chain(href.as_ref().map(|href| (Cow::Borrowed("href"), href.as_str())))
-            .chain(silent.then(|| (Cow::Borrowed("silent"), "true")))
+            //TODO pass other sounds as the `sound` parameter, gated on the first SwiftBar build which supports it (blocked on a SwiftBar build that does)
+            .chain(matches!(sound, Sound::None).then_some((Cow::Borrowed("silent"), "true")))
         )
     }
 }
 
+/// A notification sound, used by [`Notification::sound`].
+///
+/// The named variants are the system sounds which come with macOS. Other sounds installed in `~/Library/Sounds` can be used via [`Sound::Custom`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[allow(missing_docs)]
+pub enum Sound {
+    /// The default notification sound.
+    Default,
+    /// No sound.
+    None,
+    Basso,
+    Blow,
+    Bottle,
+    Frog,
+    Funk,
+    Glass,
+    Hero,
+    Morse,
+    Ping,
+    Pop,
+    Purr,
+    Sosumi,
+    Submarine,
+    Tink,
+    /// A sound with the given name, without file extension.
+    Custom(String),
+}
+
+impl Sound {
+    const NAMED: [Self; 14] = [Self::Basso, Self::Blow, Self::Bottle, Self::Frog, Self::Funk, Self::Glass, Self::Hero, Self::Morse, Self::Ping, Self::Pop, Self::Purr, Self::Sosumi, Self::Submarine, Self::Tink];
+
+    /// The name of this sound, as used by macOS. This is empty for [`Sound::Default`] and [`Sound::None`].
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Default | Self::None => "",
+            Self::Basso => "Basso",
+            Self::Blow => "Blow",
+            Self::Bottle => "Bottle",
+            Self::Frog => "Frog",
+            Self::Funk => "Funk",
+            Self::Glass => "Glass",
+            Self::Hero => "Hero",
+            Self::Morse => "Morse",
+            Self::Ping => "Ping",
+            Self::Pop => "Pop",
+            Self::Purr => "Purr",
+            Self::Sosumi => "Sosumi",
+            Self::Submarine => "Submarine",
+            Self::Tink => "Tink",
+            Self::Custom(name) => name,
+        }
+    }
+}
+
+/// Returns the system sound with the given name, or [`Sound::Custom`] if there is none.
+impl From<&str> for Sound {
+    fn from(name: &str) -> Self {
+        Self::NAMED.into_iter().find(|sound| sound.name() == name).unwrap_or_else(|| Self::Custom(name.to_owned()))
+    }
+}
+
+/// The environment variable in which SwiftBar passes the paths of files dropped onto the plugin, separated by newlines.
+const DROPPED_FILES_VAR: &str = "SWIFTBAR_DROPPED_FILES";
+/// The environment variable in which SwiftBar passes text dropped onto the plugin.
+const DROPPED_TEXT_VAR: &str = "SWIFTBAR_DROPPED_TEXT";
+
+/// Content dropped onto the plugin's menu bar item, for plugins which declare the types they accept using `<swiftbar.droptypes>`.
+///
+/// SwiftBar runs the plugin with the dropped content in its environment. With `#[bitbar::main(on_drop = "...")]`, this is routed to a command (see [`main`](macro@crate::main)), which can then use [`Dropped::check`] to distinguish files from text.
+///
+/// # Example
+///
+/// ```rust
+/// use {
+///     std::{
+///         collections::BTreeMap,
+///         path::PathBuf,
+///     },
+///     bitbar::flavor::swiftbar::Dropped,
+/// };
+///
+/// let env = BTreeMap::from([("SWIFTBAR_DROPPED_FILES", "/Users/me/a.txt\n/Users/me/b.txt")]);
+/// assert_eq!(Dropped::check_from(&env), Some(Dropped::Files(vec![PathBuf::from("/Users/me/a.txt"), PathBuf::from("/Users/me/b.txt")])));
+/// assert_eq!(Dropped::check_from(&env).unwrap().into_args(), ["/Users/me/a.txt", "/Users/me/b.txt"]);
+/// assert_eq!(Dropped::check_from(&BTreeMap::from([("SWIFTBAR_DROPPED_TEXT", "hello")])), Some(Dropped::Text("hello".to_owned())));
+/// assert_eq!(Dropped::check_from(&BTreeMap::<&str, &str>::new()), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dropped {
+    /// One or more files or folders.
+    Files(Vec<PathBuf>),
+    /// Text, e.g. a selection dragged from another app.
+    Text(String),
+}
+
+impl Dropped {
+    /// Checks whether the plugin was run because content was dropped onto it, and returns that content if so.
+    pub fn check() -> Option<Self> {
+        Self::check_from(&ProcessEnv)
+    }
+
+    /// Like [`Dropped::check`], but reads environment variables from the given source instead of the process environment.
+    pub fn check_from(env: &impl EnvSource) -> Option<Self> {
+        if let Some(files) = env.var(DROPPED_FILES_VAR).filter(|files| !files.is_empty()) {
+            Some(Self::Files(files.lines().filter(|line| !line.is_empty()).map(PathBuf::from).collect()))
+        } else {
+            env.var(DROPPED_TEXT_VAR).map(Self::Text)
+        }
+    }
+
+    /// The dropped content as command arguments: one per file, or the text as a single argument.
+    pub fn into_args(self) -> Vec<String> {
+        match self {
+            Self::Files(paths) => paths.into_iter().map(|path| path.to_string_lossy().into_owned()).collect(),
+            Self::Text(text) => vec![text],
+        }
+    }
+}
+
+/// Describes where the `~~~` separators go between the frames of a [streamable](https://github.com/swiftbar/SwiftBar#streamable) plugin.
+///
+/// SwiftBar 1.4.3 beta 1 (build 399) and newer expect the separator after each frame (with `<swiftbar.useTrailingStreamSeparator>true</swiftbar.useTrailingStreamSeparator>`), while older versions expect it before each frame.
+///
+/// [`Stream`] and [`BlockingStream`] use this internally. It can be used directly to drive streaming manually, e.g. to mix menus with raw lines:
+///
+/// ```rust,no_run
+/// # fn main() -> std::io::Result<()> {
+/// use {
+///     std::io::Write as _,
+///     bitbar::{Menu, MenuItem, flavor::swiftbar::{StreamFrame, SwiftBar}},
+/// };
+///
+/// let frame = StreamFrame::new(SwiftBar::check().expect("not running in SwiftBar"));
+/// let mut stdout = std::io::stdout().lock();
+/// frame.begin(&mut stdout)?;
+/// write!(stdout, "{}", Menu(vec![MenuItem::new("Title"), MenuItem::Sep]))?;
+/// writeln!(stdout, "raw line | color=red")?;
+/// frame.end(&mut stdout)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct StreamFrame {
+    trailing_separator: bool,
+}
+
+impl StreamFrame {
+    /// Returns the frame layout for the given SwiftBar version.
+    pub fn new(swiftbar: SwiftBar) -> Self {
+        Self { trailing_separator: build_ge!(swiftbar, BUILD_1_4_3_BETA_1) }
+    }
+
+    /// The text that must be written before each frame. This includes the trailing newline, if any.
+    pub fn prefix(&self) -> &'static str {
+        if self.trailing_separator { "" } else { "~~~\n" }
+    }
+
+    /// The text that must be written after each frame. This includes the trailing newline, if any.
+    pub fn suffix(&self) -> &'static str {
+        if self.trailing_separator { "~~~\n" } else { "" }
+    }
+
+    /// Writes the [`prefix`](Self::prefix) to the given output.
+    pub fn begin(&self, output: &mut dyn io::Write) -> io::Result<()> {
+        output.write_all(self.prefix().as_bytes())
+    }
+
+    /// Writes the [`suffix`](Self::suffix) to the given output and flushes it so the frame is displayed immediately.
+    pub fn end(&self, output: &mut dyn io::Write) -> io::Result<()> {
+        output.write_all(self.suffix().as_bytes())?;
+        output.flush()
+    }
+
+    /// Writes a complete frame containing the given menu to the given output.
+    pub fn write(&self, output: &mut dyn io::Write, menu: impl MainOutput, error_template_image: Option<Image>) -> io::Result<()> {
+        self.begin(output)?;
+        menu.write_main_output(output, error_template_image)?;
+        self.end(output)
+    }
+}
+
 /// A type that [streams](https://github.com/swiftbar/SwiftBar#streamable) menus from an iterator.
 ///
 /// Note that the following [plugin metadata](https://github.com/swiftbar/SwiftBar#script-metadata) items must be set for this to work:
@@ -410,39 +788,81 @@ impl<'a> IntoUrl for &'a Notification {
 /// ```toml
 /// [workspace.metadata.bitbar]
 /// type = "streamable"
+/// use-trailing-stream-separator = true # the default for streamable plugins
 /// ```
 ///
-/// Then, after building the plugin, run `cargo bitbar attr target/release/my-bitbar-plugin`.
+/// Then, after building the plugin, run `cargo bitbar meta target/release/my-bitbar-plugin`.
 pub struct BlockingStream<'a, I: MainOutput> {
     swiftbar: SwiftBar,
     inner: Box<dyn Iterator<Item = I> + 'a>,
+    output: Option<Box<dyn io::Write + 'a>>,
+    skip_unchanged: bool,
 }
 
 impl<'a, I: MainOutput> BlockingStream<'a, I> {
     #[allow(missing_docs)]
     pub fn new(swiftbar: SwiftBar, iter: impl IntoIterator<Item = I> + 'a) -> Self {
-        Self { swiftbar, inner: Box::new(iter.into_iter()) }
+        Self { swiftbar, inner: Box::new(iter.into_iter()), output: None, skip_unchanged: false }
+    }
+
+    /// Writes the frames to the given output instead of stdout when this stream is displayed using [`MainOutput::main_output`].
+    ///
+    /// This can be used to capture frames in tests or to tee them to a log file.
+    pub fn output(mut self, output: impl io::Write + 'a) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Skips frames which render identically to the previous frame.
+    ///
+    /// SwiftBar redraws the menu for each frame, closing any open submenu, so this is useful for plugins which poll for changes and would otherwise emit the same frame repeatedly.
+    ///
+    /// Items using [`ContentItem::on_click`](crate::ContentItem::on_click) are compared by their [`id`](crate::ContentItem::id) if they have one, and otherwise by their position among the `on_click` items built at the same source location in the same frame.
+    pub fn skip_unchanged(mut self) -> Self {
+        self.skip_unchanged = true;
+        self
     }
 }
 
 impl<'a, I: MainOutput> MainOutput for BlockingStream<'a, I> {
-    fn main_output(self, error_template_image: Option<Image>) {
-        if build_ge!(self.swiftbar, 399) {
-            for elt in self.inner {
-                elt.main_output(error_template_image.clone());
-                println!("~~~");
+    fn write_main_output(self, output: &mut dyn io::Write, error_template_image: Option<Image>) -> io::Result<()> {
+        let Self { swiftbar, mut inner, skip_unchanged, .. } = self;
+        let frame = StreamFrame::new(swiftbar);
+        let frames = iter::from_fn(|| {
+            // the next frame is built when the iterator is advanced
+            crate::reset_click_counts();
+            inner.next()
+        });
+        if skip_unchanged {
+            let mut last_frame = None;
+            for elt in frames {
+                let mut buf = Vec::default();
+                elt.write_main_output(&mut buf, error_template_image.clone())?;
+                if last_frame.as_ref() == Some(&buf) { continue }
+                frame.begin(output)?;
+                output.write_all(&buf)?;
+                frame.end(output)?;
+                last_frame = Some(buf);
             }
         } else {
-            for elt in self.inner {
-                println!("~~~");
-                elt.main_output(error_template_image.clone());
+            for elt in frames {
+                frame.write(output, elt, error_template_image.clone())?;
             }
         }
+        Ok(())
+    }
+
+    fn main_output(mut self, error_template_image: Option<Image>) {
+        if let Some(mut output) = self.output.take() {
+            MainOutput::write_main_output(self, &mut output, error_template_image).expect("failed to write menu to stream output");
+        } else {
+            MainOutput::write_main_output(self, &mut crate::buffered_stdout(), error_template_image).expect("failed to write menu to stdout");
+        }
     }
 }
 
-#[cfg(feature = "tokio")]
-#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
 /// A type that [streams](https://github.com/swiftbar/SwiftBar#streamable) menus from a stream (async iterator).
 ///
 /// Note that the following [plugin metadata](https://github.com/swiftbar/SwiftBar#script-metadata) items must be set for this to work:
@@ -454,41 +874,134 @@ impl<'a, I: MainOutput> MainOutput for BlockingStream<'a, I> {
 /// ```toml
 /// [workspace.metadata.bitbar]
 /// type = "streamable"
+/// use-trailing-stream-separator = true # the default for streamable plugins
 /// ```
 ///
-/// Then, after building the plugin, run `cargo bitbar attr target/release/my-bitbar-plugin`.
+/// Then, after building the plugin, run `cargo bitbar meta target/release/my-bitbar-plugin`.
 pub struct Stream<'a, I: AsyncMainOutput<'a> + 'a> {
     swiftbar: SwiftBar,
     inner: Pin<Box<dyn futures::stream::Stream<Item = I> + 'a>>,
+    output: Option<Box<dyn AsyncWrite + Unpin + 'a>>,
+    loading: Option<(Duration, Menu)>,
+    skip_unchanged: bool,
 }
 
-#[cfg(feature = "tokio")]
-#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
 impl<'a, I: AsyncMainOutput<'a> + 'a> Stream<'a, I> {
     #[allow(missing_docs)]
     pub fn new(swiftbar: SwiftBar, stream: impl futures::stream::Stream<Item = I> + 'a) -> Self {
-        Self { swiftbar, inner: Box::pin(stream) }
+        Self { swiftbar, inner: Box::pin(stream), output: None, loading: None, skip_unchanged: false }
+    }
+
+    /// Writes the frames to the given output instead of stdout when this stream is displayed using [`AsyncMainOutput::main_output`].
+    ///
+    /// This can be used to capture frames in tests or to tee them to a log file.
+    pub fn output(mut self, output: impl AsyncWrite + Unpin + 'a) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Displays `placeholder` as an interim frame if the first frame isn't ready within `delay`, so the menu bar doesn't appear frozen while the plugin fetches its data.
+    ///
+    /// The placeholder is replaced by the first frame once it's ready. Later frames don't get a placeholder since the previous frame remains visible in the meantime.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use {
+    ///     std::time::Duration,
+    ///     bitbar::{
+    ///         Menu,
+    ///         MenuItem,
+    ///         flavor::swiftbar::{
+    ///             Stream,
+    ///             SwiftBar,
+    ///         },
+    ///     },
+    /// };
+    ///
+    /// # async fn fetch() -> Menu { Menu::default() }
+    /// #[bitbar::main]
+    /// async fn main() -> Stream<'static, Menu> {
+    ///     Stream::new(SwiftBar::check().expect("not running in SwiftBar"), futures::stream::once(fetch()))
+    ///         .loading(Duration::from_millis(500), Menu(vec![MenuItem::new("Loading…")]))
+    /// }
+    /// ```
+    pub fn loading(mut self, delay: Duration, placeholder: Menu) -> Self {
+        self.loading = Some((delay, placeholder));
+        self
+    }
+
+    /// Skips frames which render identically to the previous frame.
+    ///
+    /// SwiftBar redraws the menu for each frame, closing any open submenu, so this is useful for plugins which poll for changes and would otherwise emit the same frame repeatedly.
+    ///
+    /// Items using [`ContentItem::on_click`](crate::ContentItem::on_click) are compared by their [`id`](crate::ContentItem::id) if they have one, and otherwise by their position among the `on_click` items built at the same source location in the same frame.
+    pub fn skip_unchanged(mut self) -> Self {
+        self.skip_unchanged = true;
+        self
     }
 }
 
-#[cfg(feature = "tokio")]
-#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+/// Writes a frame to the output of a [`Stream`]. If `last_frame` is given, the frame is skipped if it's identical to the previous one.
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+async fn write_async_frame<'a>(output: &mut (dyn AsyncWrite + Unpin + '_), frame: StreamFrame, elt: impl AsyncMainOutput<'a> + 'a, error_template_image: Option<Image>, last_frame: Option<&mut Option<Vec<u8>>>) -> io::Result<()> {
+    if let Some(last_frame) = last_frame {
+        let mut buf = Vec::default();
+        elt.write_main_output(&mut buf, error_template_image).await?;
+        if last_frame.as_ref() == Some(&buf) { return Ok(()) }
+        output.write_all(frame.prefix().as_bytes()).await?;
+        output.write_all(&buf).await?;
+        *last_frame = Some(buf);
+    } else {
+        output.write_all(frame.prefix().as_bytes()).await?;
+        elt.write_main_output(output, error_template_image).await?;
+    }
+    output.write_all(frame.suffix().as_bytes()).await?;
+    output.flush().await
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
 impl<'a, I: AsyncMainOutput<'a> + 'a> AsyncMainOutput<'a> for Stream<'a, I> {
+    fn write_main_output<'w>(mut self, output: &'w mut (dyn AsyncWrite + Unpin + 'w), error_template_image: Option<Image>) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'w>>
+    where 'a: 'w {
+        let frame = StreamFrame::new(self.swiftbar);
+        Box::pin(async move {
+            let mut last_frame = None;
+            let mut last_frame = self.skip_unchanged.then_some(&mut last_frame);
+            if let Some((delay, placeholder)) = self.loading.take() {
+                crate::reset_click_counts();
+                let mut next = self.inner.next();
+                let first = match crate::timeout(delay, &mut next).await {
+                    Some(first) => first,
+                    None => {
+                        write_async_frame(output, frame, placeholder, error_template_image.clone(), last_frame.as_deref_mut()).await?;
+                        next.await
+                    }
+                };
+                let Some(elt) = first else { return Ok(()) };
+                write_async_frame(output, frame, elt, error_template_image.clone(), last_frame.as_deref_mut()).await?;
+            }
+            loop {
+                // the next frame may be built when the stream is polled
+                crate::reset_click_counts();
+                let Some(elt) = self.inner.next().await else { break };
+                write_async_frame(output, frame, elt, error_template_image.clone(), last_frame.as_deref_mut()).await?;
+            }
+            Ok(())
+        })
+    }
+
     fn main_output(mut self, error_template_image: Option<Image>) -> Pin<Box<dyn Future<Output = ()> + 'a>> {
-        if build_ge!(self.swiftbar, 399) {
-            Box::pin(async move {
-                while let Some(elt) = self.inner.next().await {
-                    elt.main_output(error_template_image.clone()).await;
-                    println!("~~~");
-                }
-            })
-        } else {
-            Box::pin(async move {
-                while let Some(elt) = self.inner.next().await {
-                    println!("~~~");
-                    elt.main_output(error_template_image.clone()).await;
-                }
-            })
-        }
+        Box::pin(async move {
+            if let Some(mut output) = self.output.take() {
+                self.write_main_output(&mut output, error_template_image).await.expect("failed to write menu to stream output");
+            } else {
+                let mut stdout = AllowStdIo::new(io::BufWriter::new(io::stdout()));
+                self.write_main_output(&mut stdout, error_template_image).await.expect("failed to write menu to stdout");
+            }
+        })
     }
 }
@@ -0,0 +1,268 @@
+//! Compact, human-readable formatting of numbers, byte sizes, rates, and percentages, suited to the limited width of the menu bar.
+//!
+//! The free functions use [`NumberFormat::default`], which shows at most 1 decimal place using the [locale from the environment](Locale::from_env). Use a [`NumberFormat`] to configure this.
+//!
+//! To keep the text short, trailing zeros are omitted and decimals are only shown for small values: below 100 for byte sizes, rates, and percentages (`12.3 GB`, `123 GB`), and below 10 for abbreviated numbers (`1.2k`, `15k`).
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::format::{Locale, NumberFormat};
+//!
+//! let format = NumberFormat::new().locale(Locale::EN);
+//! assert_eq!(format.bytes(1_234_567), "1.2 MB");
+//! assert_eq!(format.rate(2_500.0), "2.5 KB/s");
+//! assert_eq!(format.compact(15_300.0), "15k");
+//! assert_eq!(format.percent(0.4567), "45.7%");
+//! assert_eq!(format.precision(0).percent(0.4567), "46%");
+//! assert_eq!(format.locale(Locale::DE).number(1234.5), "1.234,5");
+//! ```
+
+use std::env;
+
+/// The separators used to format numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    /// The character separating the integer part from the decimals.
+    pub decimal_separator: char,
+    /// The character separating groups of 3 digits in the integer part, if any.
+    pub thousands_separator: Option<char>,
+}
+
+impl Locale {
+    /// `1,234.5`
+    pub const EN: Self = Self { decimal_separator: '.', thousands_separator: Some(',') };
+    /// `1.234,5`
+    pub const DE: Self = Self { decimal_separator: ',', thousands_separator: Some('.') };
+    /// `1 234,5`
+    pub const FR: Self = Self { decimal_separator: ',', thousands_separator: Some('\u{202f}') };
+    /// `1234.5`
+    pub const PLAIN: Self = Self { decimal_separator: '.', thousands_separator: None };
+
+    /// Guesses the locale from the `LC_ALL`, `LC_NUMERIC`, and `LANG` environment variables (in that order of priority), defaulting to [`Locale::EN`].
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"].into_iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map_or(Self::EN, |value| Self::from_language(&value))
+    }
+
+    /// Returns the locale for a language tag like `de_DE.UTF-8` or `fr-CH`.
+    pub fn from_language(tag: &str) -> Self {
+        let language = tag.split(['_', '-', '.']).next().unwrap_or_default().to_ascii_lowercase();
+        match &*language {
+            "fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "fi" | "sv" | "nb" | "no" => Self::FR,
+            "de" | "nl" | "da" | "es" | "it" | "pt" | "tr" | "id" | "el" | "ro" => Self::DE,
+            _ => Self::EN,
+        }
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// Configuration for formatting numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    precision: usize,
+    locale: Locale,
+}
+
+impl NumberFormat {
+    /// Returns the default format: at most 1 decimal place, using the [locale from the environment](Locale::from_env).
+    pub fn new() -> Self {
+        Self {
+            precision: 1,
+            locale: Locale::from_env(),
+        }
+    }
+
+    /// Sets the maximum number of decimal places.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the locale.
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Formats a number with at most the configured number of decimal places and with thousands separators, e.g. `1,234.5`.
+    pub fn number(&self, n: f64) -> String {
+        self.with_decimals(n, self.precision)
+    }
+
+    /// Formats a number, with decimals only if its absolute value is less than `limit` to keep the text short.
+    fn short(&self, n: f64, limit: f64) -> String {
+        self.with_decimals(n, self.short_decimals(n, limit))
+    }
+
+    /// The number of decimal places shown by [`NumberFormat::short`].
+    fn short_decimals(&self, n: f64, limit: f64) -> usize {
+        if n.abs() < limit { self.precision } else { 0 }
+    }
+
+    fn with_decimals(&self, n: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, n.abs());
+        let (int_part, frac_part) = formatted.split_once('.').unwrap_or((&formatted, ""));
+        let frac_part = frac_part.trim_end_matches('0');
+        let mut buf = String::with_capacity(formatted.len() + int_part.len() / 3 + 1);
+        if n.is_sign_negative() && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') { buf.push('-') }
+        for (idx, digit) in int_part.chars().enumerate() {
+            if idx > 0 && (int_part.len() - idx) % 3 == 0 {
+                if let Some(sep) = self.locale.thousands_separator { buf.push(sep) }
+            }
+            buf.push(digit);
+        }
+        if !frac_part.is_empty() {
+            buf.push(self.locale.decimal_separator);
+            buf.push_str(frac_part);
+        }
+        buf
+    }
+
+    /// Scales a number down by `base` until it is less than `base` after rounding to the given number of decimal places, returning the scaled number and the index of the unit.
+    ///
+    /// Rounding before checking makes sure that e.g. 999,950 is shown as `1M` rather than `1000k`.
+    fn scale(n: f64, base: f64, num_units: usize, decimals: impl Fn(f64, usize) -> usize) -> (f64, usize) {
+        let mut value = n;
+        let mut unit = 0;
+        // round the same way as the formatting in with_decimals
+        while format!("{:.*}", decimals(value, unit), value.abs()).parse::<f64>().is_ok_and(|rounded| rounded >= base) && unit < num_units - 1 {
+            value /= base;
+            unit += 1;
+        }
+        (value, unit)
+    }
+
+    /// Formats a byte size using SI prefixes, e.g. `1.2 MB`.
+    pub fn bytes(&self, n: u64) -> String {
+        const UNITS: [&str; 7] = ["B", "KB", "MB", "GB", "TB", "PB", "EB"];
+        let (value, unit) = Self::scale(n as f64, 1000.0, UNITS.len(), |value, unit| if unit == 0 { 0 } else { self.short_decimals(value, 100.0) });
+        format!("{} {}", if unit == 0 { self.with_decimals(value, 0) } else { self.short(value, 100.0) }, UNITS[unit])
+    }
+
+    /// Formats a byte size using binary prefixes, e.g. `1.2 MiB`.
+    pub fn bytes_binary(&self, n: u64) -> String {
+        const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        let (value, unit) = Self::scale(n as f64, 1024.0, UNITS.len(), |value, unit| if unit == 0 { 0 } else { self.short_decimals(value, 100.0) });
+        format!("{} {}", if unit == 0 { self.with_decimals(value, 0) } else { self.short(value, 100.0) }, UNITS[unit])
+    }
+
+    /// Formats a transfer rate given in bytes per second, e.g. `2.5 MB/s`.
+    pub fn rate(&self, bytes_per_second: f64) -> String {
+        format!("{}/s", self.bytes(bytes_per_second.max(0.0).round() as u64))
+    }
+
+    /// Abbreviates a large number, e.g. `1.2k`, `3.4M`, or `5B`.
+    pub fn compact(&self, n: f64) -> String {
+        const SUFFIXES: [&str; 5] = ["", "k", "M", "B", "T"];
+        let (value, unit) = Self::scale(n, 1000.0, SUFFIXES.len(), |value, _| self.short_decimals(value, 10.0));
+        format!("{}{}", self.short(value, 10.0), SUFFIXES[unit])
+    }
+
+    /// Formats a fraction (where 1.0 is 100%) as a percentage, e.g. `45.7%`.
+    pub fn percent(&self, fraction: f64) -> String {
+        format!("{}%", self.short(fraction * 100.0, 100.0))
+    }
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats a number using the [default format](NumberFormat::new). See [`NumberFormat::number`].
+pub fn number(n: f64) -> String {
+    NumberFormat::new().number(n)
+}
+
+/// Formats a byte size using the [default format](NumberFormat::new). See [`NumberFormat::bytes`].
+pub fn bytes(n: u64) -> String {
+    NumberFormat::new().bytes(n)
+}
+
+/// Formats a byte size using binary prefixes and the [default format](NumberFormat::new). See [`NumberFormat::bytes_binary`].
+pub fn bytes_binary(n: u64) -> String {
+    NumberFormat::new().bytes_binary(n)
+}
+
+/// Formats a transfer rate using the [default format](NumberFormat::new). See [`NumberFormat::rate`].
+pub fn rate(bytes_per_second: f64) -> String {
+    NumberFormat::new().rate(bytes_per_second)
+}
+
+/// Abbreviates a large number using the [default format](NumberFormat::new). See [`NumberFormat::compact`].
+pub fn compact(n: f64) -> String {
+    NumberFormat::new().compact(n)
+}
+
+/// Formats a fraction as a percentage using the [default format](NumberFormat::new). See [`NumberFormat::percent`].
+pub fn percent(fraction: f64) -> String {
+    NumberFormat::new().percent(fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Locale,
+        NumberFormat,
+    };
+
+    fn format() -> NumberFormat {
+        NumberFormat::new().locale(Locale::EN)
+    }
+
+    #[test]
+    fn compact_unit_boundaries() {
+        assert_eq!(format().compact(999.0), "999");
+        assert_eq!(format().compact(999.4), "999");
+        assert_eq!(format().compact(999.5), "1k");
+        assert_eq!(format().compact(9_960.0), "10k");
+        assert_eq!(format().compact(999_499.0), "999k");
+        assert_eq!(format().compact(999_950.0), "1M");
+        assert_eq!(format().compact(-999_950.0), "-1M");
+        assert_eq!(format().compact(999_950_000_000_000.0), "1,000T");
+        assert_eq!(format().precision(2).compact(9_996.0), "10k");
+    }
+
+    #[test]
+    fn bytes_unit_boundaries() {
+        assert_eq!(format().bytes(999), "999 B");
+        assert_eq!(format().bytes(1_000), "1 KB");
+        assert_eq!(format().bytes(999_499), "999 KB");
+        assert_eq!(format().bytes(999_500), "1 MB");
+        assert_eq!(format().bytes(999_999), "1 MB");
+        assert_eq!(format().bytes(99_950), "100 KB");
+        assert_eq!(format().bytes(u64::MAX), "18.4 EB");
+    }
+
+    #[test]
+    fn bytes_binary_unit_boundaries() {
+        assert_eq!(format().bytes_binary(1023), "1,023 B");
+        assert_eq!(format().bytes_binary(1024), "1 KiB");
+        assert_eq!(format().bytes_binary(1024 * 1024 - 1), "1 MiB");
+        assert_eq!(format().bytes_binary(1023 * 1024), "1,023 KiB");
+    }
+
+    #[test]
+    fn rate_unit_boundaries() {
+        assert_eq!(format().rate(999_999.0), "1 MB/s");
+        assert_eq!(format().rate(-5.0), "0 B/s");
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(format().locale(Locale::FR).number(1234.5), "1\u{202f}234,5");
+        assert_eq!(format().locale(Locale::PLAIN).number(1234567.0), "1234567");
+        assert_eq!(Locale::from_language("de_CH.UTF-8"), Locale::DE);
+        assert_eq!(Locale::from_language("C"), Locale::EN);
+        assert_eq!(format().number(-0.04), "0");
+    }
+}
@@ -0,0 +1,183 @@
+//! A small HTTP client for fetching JSON from APIs, using conditional requests to avoid downloading unchanged data.
+//!
+//! The `ETag` and `Last-Modified` headers of each response are saved along with the response body in the [plugin cache directory](crate::cache_dir), in a subdirectory named `http`. The next request to the same URL sends them back as `If-None-Match` and `If-Modified-Since`, and if the server responds with `304 Not Modified`, the cached body is used. Many APIs don't count such requests against their rate limits.
+//!
+//! This requires a `tokio` runtime, so the `reqwest` feature also enables the `tokio` feature.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use {
+//!     serde::Deserialize,
+//!     bitbar::{
+//!         Menu,
+//!         MenuItem,
+//!         http,
+//!     },
+//! };
+//!
+//! #[derive(Deserialize)]
+//! struct Repo {
+//!     stargazers_count: u64,
+//! }
+//!
+//! #[bitbar::main]
+//! async fn main() -> Result<Menu, http::Error> {
+//!     let repo = http::Client::new()?.get_json::<Repo>("https://api.github.com/repos/fenhl/rust-bitbar").await?;
+//!     Ok(Menu(vec![MenuItem::new(format!("★ {}", repo.stargazers_count))]))
+//! }
+//! ```
+
+use {
+    std::{
+        fs,
+        io,
+        path::PathBuf,
+        sync::Arc,
+    },
+    reqwest::{
+        IntoUrl,
+        StatusCode,
+        header::{
+            ETAG,
+            HeaderValue,
+            IF_MODIFIED_SINCE,
+            IF_NONE_MATCH,
+            LAST_MODIFIED,
+        },
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+        de::DeserializeOwned,
+    },
+    thiserror::Error,
+    url::Url,
+    crate::{
+        Menu,
+        MenuItem,
+    },
+};
+
+/// An error that can occur in [`Client::get_json`].
+#[derive(Debug, Error, Clone)]
+pub enum Error {
+    /// An I/O error occurred while reading or writing the cached response
+    #[error(transparent)] Io(Arc<io::Error>),
+    /// The response body was not valid JSON for the requested type
+    #[error(transparent)] Json(Arc<serde_json::Error>),
+    /// The request failed or the server responded with an error status
+    #[error(transparent)] Reqwest(Arc<reqwest::Error>),
+    /// The server responded with `304 Not Modified`, but there was no cached response
+    #[error("{0} was not modified, but no cached response is available")]
+    NotCached(Url),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(Arc::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(Arc::new(e))
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Reqwest(Arc::new(e))
+    }
+}
+
+impl From<Error> for Menu {
+    fn from(e: Error) -> Menu {
+        let mut menu = vec![MenuItem::new("Error fetching data")];
+        match e {
+            Error::Io(e) => {
+                menu.push(MenuItem::new(format!("I/O error: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Json(e) => {
+                menu.push(MenuItem::new(format!("error decoding response: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Reqwest(e) => {
+                menu.push(MenuItem::new(format!("HTTP error: {}", e)));
+                if let Some(url) = e.url() {
+                    menu.push(MenuItem::new(format!("URL: {url}")));
+                }
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::NotCached(_) => menu.push(MenuItem::new(e)),
+        }
+        Menu(menu)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+/// Returns the path to the file in which the response for the given URL is cached.
+///
+/// The file name is a hash of the URL, so it stays within file name length limits and doesn't leak query parameters like API keys into directory listings.
+pub fn cache_path(url: &Url) -> io::Result<PathBuf> {
+    Ok(crate::cache_dir()?.join("http").join(format!("{:016x}.json", crate::stable_hash(url.as_str().as_bytes()))))
+}
+
+/// A wrapper around a [`reqwest::Client`] which caches responses for conditional requests.
+#[derive(Debug, Clone)]
+pub struct Client(reqwest::Client);
+
+impl Client {
+    /// Creates a client with a `User-Agent` header identifying this crate.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self(reqwest::Client::builder()
+            .user_agent(concat!("bitbar/", env!("CARGO_PKG_VERSION")))
+            .build()?))
+    }
+
+    /// Sends a `GET` request to the given URL and decodes the response as JSON, using the cached response if the server responds with `304 Not Modified`.
+    ///
+    /// Responses with an error status are returned as [`Error::Reqwest`] and not cached.
+    pub async fn get_json<T: DeserializeOwned>(&self, url: impl IntoUrl) -> Result<T, Error> {
+        let url = url.into_url()?;
+        let path = cache_path(&url)?;
+        let cached = fs::read(&path).ok().and_then(|buf| serde_json::from_slice::<CachedResponse>(&buf).ok());
+        let mut request = self.0.get(url.clone());
+        if let Some(ref cached) = cached {
+            if let Some(ref etag) = cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(ref last_modified) = cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let cached = cached.ok_or(Error::NotCached(url))?;
+            return Ok(serde_json::from_str(&cached.body)?)
+        }
+        let response = response.error_for_status()?;
+        let header = |name| response.headers().get(name).and_then(|value: &HeaderValue| value.to_str().ok()).map(str::to_owned);
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = response.text().await?;
+        let value = serde_json::from_str(&body)?;
+        if etag.is_some() || last_modified.is_some() {
+            crate::write_atomic(&path, &serde_json::to_vec(&CachedResponse { etag, last_modified, body })?)?;
+        }
+        Ok(value)
+    }
+}
+
+impl From<reqwest::Client> for Client {
+    fn from(client: reqwest::Client) -> Self {
+        Self(client)
+    }
+}
@@ -47,31 +47,139 @@ use {
     std::{
         borrow::Cow,
         collections::BTreeMap,
-        convert::TryInto,
+        convert::{
+            Infallible,
+            TryInto,
+        },
         fmt,
-        iter::FromIterator,
-        process,
+        io,
+        fs,
+        hash::Hasher as _,
+        io::{
+            BufWriter,
+            IsTerminal as _,
+            StdoutLock,
+            Write as _,
+        },
+        iter::{
+            self,
+            FromIterator,
+        },
+        mem,
+        panic::Location,
+        path::{
+            Path,
+            PathBuf,
+        },
+        process::{
+            self,
+            ExitCode,
+        },
+        sync::{
+            Mutex,
+            OnceLock,
+            atomic::{
+                AtomicBool,
+                Ordering,
+            },
+            mpsc,
+        },
+        thread,
+        time::{
+            Duration,
+            SystemTime,
+            UNIX_EPOCH,
+        },
         vec,
     },
     if_chain::if_chain,
+    siphasher::sip::SipHasher13,
+    thiserror::Error,
     url::Url,
 };
-#[cfg(feature = "tokio")] use std::{
-    future::Future,
-    pin::Pin,
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use {
+    std::{
+        future::Future,
+        pin::{
+            Pin,
+            pin,
+        },
+    },
+    futures::io::{
+        AllowStdIo,
+        AsyncWrite,
+        AsyncWriteExt as _,
+    },
 };
 pub use {
     bitbar_derive::{
         command,
+        MenuOptions,
+        PluginConfig,
+        Subcommands,
+        ToMenuItem,
+        color,
         fallback_command,
+        include_image,
         main,
     },
     crate::flavor::Flavor,
 };
+#[cfg(feature = "async-std")] #[doc(hidden)] pub use async_std;
+#[cfg(feature = "clap")] #[doc(hidden)] pub use clap;
+#[cfg(feature = "futures-executor")] #[doc(hidden)] pub use futures;
 #[cfg(feature = "tokio")] #[doc(hidden)] pub use tokio;
+#[cfg(feature = "inventory")] #[doc(hidden)] pub use inventory; // used in proc macro
+#[cfg(test)] use criterion as _; // used in benchmarks
+#[cfg(test)] extern crate self as bitbar; // allows testing derive macros, which generate absolute paths
+
+#[doc(hidden)] pub const VERSION: &str = env!("CARGO_PKG_VERSION"); // used in cargo-bitbar
 
 pub mod attr;
+#[cfg(feature = "inventory")] mod builtin;
+#[cfg(feature = "cache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
+pub mod cache;
+#[cfg(feature = "config")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+pub mod config;
+pub mod discord;
+#[cfg(feature = "emojis")]
+#[cfg_attr(docsrs, doc(cfg(feature = "emojis")))]
+pub mod emoji;
 pub mod flavor;
+pub mod format;
+#[cfg(feature = "reqwest")]
+#[cfg_attr(docsrs, doc(cfg(feature = "reqwest")))]
+pub mod http;
+#[cfg(unix)]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+pub mod lock;
+#[cfg(feature = "log")]
+#[cfg_attr(docsrs, doc(cfg(feature = "log")))]
+pub mod logging;
+pub mod metadata;
+pub mod plugin;
+pub mod preview;
+pub mod retry;
+pub mod schedule;
+pub mod section;
+#[cfg(feature = "state")]
+#[cfg_attr(docsrs, doc(cfg(feature = "state")))]
+pub mod state;
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+pub mod time;
+#[cfg(feature = "test")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test")))]
+pub mod test;
+#[cfg(feature = "timing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "timing")))]
+pub mod timing;
+pub mod validate;
+#[cfg(feature = "widgets")]
+#[cfg_attr(docsrs, doc(cfg(feature = "widgets")))]
+pub mod widgets;
 
 /// A menu item that's not a separator.
 #[derive(Debug, Default)]
@@ -79,7 +187,7 @@ pub struct ContentItem {
     /// This menu item's main content text.
     ///
     /// Any `|` in the text will be displayed as `¦`, and any newlines will be displayed as spaces.
-    pub text: String,
+    pub text: Cow<'static, str>,
     /// This menu item's alternate-mode menu item or submenu.
     pub extra: Option<attr::Extra>,
     /// Corresponds to BitBar's `href=` parameter.
@@ -87,7 +195,7 @@ pub struct ContentItem {
     /// Corresponds to BitBar's `color=` parameter.
     pub color: Option<attr::Color>,
     /// Corresponds to BitBar's `font=` parameter.
-    pub font: Option<String>,
+    pub font: Option<Cow<'static, str>>,
     /// Corresponds to BitBar's `size=` parameter.
     pub size: Option<usize>,
     /// Corresponds to BitBar's `bash=`, `terminal=`, `param1=`, etc. parameters.
@@ -98,20 +206,70 @@ pub struct ContentItem {
     pub image: Option<attr::Image>,
     /// Parameters for flavor-specific features.
     pub flavor_attrs: Option<flavor::Attrs>,
+    /// An identifier for tracking this item across refreshes even if its text changes, e.g. for a download whose text includes its progress. This is not rendered.
+    pub id: Option<Cow<'static, str>>,
+}
+
+/// The maximum nesting depth of submenus and alternate items generated by the [`Arbitrary`](arbitrary::Arbitrary) implementations, so that large inputs don't overflow the stack while generating a menu.
+#[cfg(feature = "arbitrary")] const ARBITRARY_MAX_DEPTH: usize = 16;
+
+#[cfg(feature = "arbitrary")] thread_local! {
+    static ARBITRARY_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Generates the submenu or alternate item of an arbitrary item, or `None` once [`ARBITRARY_MAX_DEPTH`] is reached.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_extra(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Option<attr::Extra>> {
+    let depth = ARBITRARY_DEPTH.get();
+    if depth >= ARBITRARY_MAX_DEPTH { return Ok(None) }
+    ARBITRARY_DEPTH.set(depth + 1);
+    let extra = u.arbitrary();
+    ARBITRARY_DEPTH.set(depth);
+    extra
+}
+
+/// Generates items with arbitrary text and parameters. Links are generated as `https://example.com/` with an arbitrary path.
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for ContentItem {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<ContentItem> {
+        Ok(ContentItem {
+            text: u.arbitrary::<String>()?.into(),
+            extra: arbitrary_extra(u)?,
+            href: if u.arbitrary()? {
+                let mut url = Url::parse("https://example.com/").expect("hardcoded URL is valid");
+                url.set_path(u.arbitrary()?);
+                Some(url)
+            } else {
+                None
+            },
+            color: u.arbitrary()?,
+            font: u.arbitrary::<Option<String>>()?.map(Cow::Owned),
+            size: u.arbitrary()?,
+            command: u.arbitrary()?,
+            refresh: u.arbitrary()?,
+            image: u.arbitrary()?,
+            flavor_attrs: u.arbitrary()?,
+            id: u.arbitrary::<Option<String>>()?.map(Cow::Owned),
+        })
+    }
 }
 
 impl ContentItem {
     /// Returns a new menu item with the given text.
     ///
     /// Any `|` in the text will be displayed as `¦`, and any newlines will be displayed as spaces.
+    ///
+    /// This always allocates the text. For string literals, `ContentItem::from("...")` can be used instead, which doesn't.
     pub fn new(text: impl ToString) -> ContentItem {
         ContentItem {
-            text: text.to_string(),
+            text: Cow::Owned(text.to_string()),
             ..ContentItem::default()
         }
     }
 
     /// Adds a submenu to this menu item.
+    #[allow(clippy::should_implement_trait)]
     pub fn sub(mut self, items: impl IntoIterator<Item = MenuItem>) -> Self {
         self.extra = Some(attr::Extra::Submenu(Menu::from_iter(items)));
         self
@@ -131,7 +289,7 @@ impl ContentItem {
 
     /// Sets this menu item's text font.
     pub fn font(mut self, font: impl ToString) -> Self {
-        self.font = Some(font.to_string());
+        self.font = Some(Cow::Owned(font.to_string()));
         self
     }
 
@@ -141,12 +299,122 @@ impl ContentItem {
         self
     }
 
+    /// Sets this menu item's [identifier](ContentItem::id).
+    pub fn id(mut self, id: impl ToString) -> Self {
+        self.id = Some(Cow::Owned(id.to_string()));
+        self
+    }
+
     /// Make this menu item run the given command when clicked.
     pub fn command<C: TryInto<attr::Command>>(mut self, cmd: C) -> Result<Self, C::Error> {
         self.command = Some(cmd.try_into()?);
         Ok(self)
     }
 
+    /// Runs the given closure when this menu item is clicked, reporting its output like that of a [`command`](macro@command).
+    ///
+    /// This is an alternative to defining a named command for small actions. Clicking the item runs the plugin binary with the reserved `bitbar-click` subcommand, which calls the `main` function generated by [`main`] again to build the menu, and runs the closure when `on_click` is called for the clicked item.
+    /// Items are identified by their [`id`](ContentItem::id) if they have one. Otherwise, they are identified by the source location of the `on_click` call and the number of previous calls from that location, so the menu must be built the same way each time. The output of `main` is discarded when handling a click, but any other side effects of building the menu happen again.
+    ///
+    /// Handlers registered while a [streamable](https://github.com/swiftbar/SwiftBar#streamable) plugin is streaming frames, rather than before `main` returns, are not found.
+    ///
+    /// # Errors
+    ///
+    /// If the path to the plugin binary can't be determined or is not valid UTF-8.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bitbar::{ContentItem, Menu, MenuItem};
+    ///
+    /// #[bitbar::main]
+    /// fn main() -> Menu {
+    ///     match ContentItem::new("Clear cache").on_click(|| std::fs::remove_dir_all(bitbar::cache_dir()?)) {
+    ///         Ok(item) => Menu(vec![item.into()]),
+    ///         Err(e) => Menu(vec![MenuItem::new(format!("error generating menu: {e}"))]),
+    ///     }
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn on_click<O: CommandOutput>(self, handler: impl FnOnce() -> O) -> io::Result<Self> {
+        let id = if let Some(ref id) = self.id {
+            format!("id:{id}")
+        } else {
+            let location = Location::caller();
+            let location = format!("{}:{}:{}", location.file(), location.line(), location.column());
+            let idx = {
+                let mut counts = CLICK_COUNTS.lock().expect("click handler registry poisoned");
+                let count = counts.entry(location.clone()).or_default();
+                *count += 1;
+                *count - 1
+            };
+            format!("{location}#{idx}")
+        };
+        if CLICK_TARGET.get().is_some_and(|target| *target == id) {
+            handler().report(CLICK_COMMAND);
+            process::exit(if exit_code() == ExitCode::SUCCESS { 0 } else { 1 })
+        }
+        match self.command(attr::Params::new(current_exe()?, vec![CLICK_COMMAND.to_owned(), id])) {
+            Ok(item) => Ok(item),
+            Err(never) => match never {},
+        }
+    }
+
+    /// Replaces `:shortcode:`-style emoji names in this menu item's text with the corresponding Unicode emoji, see [`emoji::emojize`].
+    #[cfg(feature = "emojis")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "emojis")))]
+    pub fn emojize(mut self) -> Self {
+        if let Cow::Owned(text) = emoji::emojize(&self.text) {
+            self.text = Cow::Owned(text);
+        }
+        self
+    }
+
+    /// Appends a count bubble like `❸` to this menu item's text, e.g. for the number of unread messages.
+    ///
+    /// Counts from 1 to 20 are shown as circled numbers, larger counts in parentheses, and counts above 999 as `(999+)`. A count of 0 doesn't add anything. The badge is always Unicode text, regardless of the flavor; there is no image-based rendering.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bitbar::ContentItem;
+    ///
+    /// assert_eq!(ContentItem::new("Inbox").badge(3).text, "Inbox ❸");
+    /// assert_eq!(ContentItem::new("Inbox").badge(42).text, "Inbox (42)");
+    /// assert!(matches!(ContentItem::from("Inbox").badge(0).text, std::borrow::Cow::Borrowed("Inbox")));
+    /// ```
+    pub fn badge(mut self, count: u64) -> Self {
+        if count == 0 { return self }
+        let text = self.text.to_mut();
+        match count {
+            1..=10 => { text.push(' '); text.push(char::from_u32(0x2775 + count as u32).expect("valid dingbat")) }
+            11..=20 => { text.push(' '); text.push(char::from_u32(0x24e0 + count as u32).expect("valid circled number")) }
+            21..=999 => text.push_str(&format!(" ({count})")),
+            _ => text.push_str(" (999+)"),
+        }
+        self
+    }
+
+    /// Appends a colored dot like `🟢` to this menu item's text, e.g. to indicate a status.
+    ///
+    /// Since the [color](Self::color) of a menu item applies to all of its text, the dot is an emoji, which is displayed in color by all BitBar implementations, so no image is generated regardless of the flavor. The emoji closest to the given color is used. For colors with a [dark theme variant](attr::Color::for_theme), the variant for the current [system theme](flavor::dark_mode) is used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bitbar::ContentItem;
+    ///
+    /// assert_eq!(ContentItem::new("CI").dot("green").unwrap().text, "CI 🟢");
+    /// assert_eq!(ContentItem::new("CI").dot("#e03030").unwrap().text, "CI 🔴");
+    /// ```
+    pub fn dot<C: TryInto<attr::Color>>(mut self, color: C) -> Result<Self, C::Error> {
+        let color = color.try_into()?.for_theme(flavor::dark_mode());
+        let text = self.text.to_mut();
+        text.push(' ');
+        text.push_str(color.circle_emoji());
+        Ok(self)
+    }
+
     /// Causes the BitBar plugin to be refreshed when this menu item is clicked.
     pub fn refresh(mut self) -> Self {
         self.refresh = true;
@@ -154,9 +422,11 @@ impl ContentItem {
     }
 
     /// Adds an alternate menu item, which is shown instead of this one as long as the option key ⌥ is held.
-    pub fn alt(mut self, alt: impl Into<ContentItem>) -> Self {
-        self.extra = Some(attr::Extra::Alternate(Box::new(alt.into())));
-        self
+    ///
+    /// This fails if `alt` is a [`ContentItem`] with a submenu or its own alternate item, since BitBar doesn't display these.
+    pub fn alt<A: TryInto<attr::AltItem>>(mut self, alt: A) -> Result<Self, A::Error> {
+        self.extra = Some(attr::Extra::Alternate(Box::new(alt.try_into()?)));
+        Ok(self)
     }
 
     /// Adds a template image to this menu item.
@@ -171,80 +441,132 @@ impl ContentItem {
         Ok(self)
     }
 
-    fn render(&self, f: &mut fmt::Formatter<'_>, is_alt: bool) -> fmt::Result {
-        // main text
-        write!(f, "{}", self.text.replace('|', "¦").replace('\n', " "))?;
-        // parameters
-        let mut rendered_params = BTreeMap::default();
+    /// The parameters of this menu item, omitting any that aren't supported by `flavor`.
+    pub(crate) fn params(&self, is_alt: bool, flavor: Option<Flavor>) -> RenderedParams<'_> {
+        let mut rendered_params = RenderedParams::default();
+        self.write_params(&mut rendered_params, is_alt, flavor);
+        rendered_params
+    }
+
+    /// Like [`ContentItem::params`], but reuses the given buffer, which is cleared first. This avoids allocating for each item when rendering a menu.
+    fn write_params<'a>(&'a self, rendered_params: &mut RenderedParams<'a>, is_alt: bool, flavor: Option<Flavor>) {
+        rendered_params.clear();
         if let Some(ref href) = self.href {
-            rendered_params.insert(Cow::Borrowed("href"), Cow::Borrowed(href.as_ref()));
+            rendered_params.push((Cow::Borrowed("href"), Cow::Borrowed(href.as_ref())));
         }
         if let Some(ref color) = self.color {
-            rendered_params.insert(Cow::Borrowed("color"), Cow::Owned(color.to_string()));
+            let color = match flavor {
+                Some(flavor) if color.dark.is_some() && !flavor.supports_dark_colors() => color.for_theme(flavor::dark_mode()),
+                _ => *color,
+            };
+            rendered_params.push((Cow::Borrowed("color"), Cow::Owned(color.to_string())));
         }
         if let Some(ref font) = self.font {
-            rendered_params.insert(Cow::Borrowed("font"), Cow::Borrowed(font));
+            rendered_params.push((Cow::Borrowed("font"), Cow::Borrowed(font)));
         }
         if let Some(size) = self.size {
-            rendered_params.insert(Cow::Borrowed("size"), Cow::Owned(size.to_string()));
+            rendered_params.push((Cow::Borrowed("size"), Cow::Owned(size.to_string())));
         }
         if let Some(ref cmd) = self.command {
             //TODO (xbar) prefer “shell” over “bash”
-            rendered_params.insert(Cow::Borrowed("bash"), Cow::Borrowed(&cmd.params.cmd));
+            rendered_params.push((Cow::Borrowed("bash"), Cow::Borrowed(&cmd.params.cmd)));
             for (i, param) in cmd.params.params.iter().enumerate() {
-                rendered_params.insert(Cow::Owned(format!("param{}", i + 1)), Cow::Borrowed(param));
+                let name = PARAM_NAMES.get(i).map_or_else(|| Cow::Owned(format!("param{}", i + 1)), |&name| Cow::Borrowed(name));
+                rendered_params.push((name, Cow::Borrowed(param)));
             }
             if !cmd.terminal {
-                rendered_params.insert(Cow::Borrowed("terminal"), Cow::Borrowed("false"));
+                rendered_params.push((Cow::Borrowed("terminal"), Cow::Borrowed("false")));
             }
         }
         if self.refresh {
-            rendered_params.insert(Cow::Borrowed("refresh"), Cow::Borrowed("true"));
+            rendered_params.push((Cow::Borrowed("refresh"), Cow::Borrowed("true")));
         }
         if is_alt {
-            rendered_params.insert(Cow::Borrowed("alternate"), Cow::Borrowed("true"));
+            rendered_params.push((Cow::Borrowed("alternate"), Cow::Borrowed("true")));
         }
         if let Some(ref img) = self.image {
-            rendered_params.insert(Cow::Borrowed(if img.is_template { "templateImage" } else { "image" }), Cow::Borrowed(&img.base64_data));
+            rendered_params.push((Cow::Borrowed(if img.is_template { "templateImage" } else { "image" }), Cow::Borrowed(&img.base64_data)));
         }
         if let Some(ref flavor_attrs) = self.flavor_attrs {
-            flavor_attrs.render(&mut rendered_params);
+            flavor_attrs.render(rendered_params);
+        }
+        if let Some(flavor) = flavor {
+            rendered_params.retain(|(name, _)| flavor.supports_param(name));
         }
-        if !rendered_params.is_empty() {
-            write!(f, " |")?;
-            for (name, value) in rendered_params {
-                let quoted_value = if value.contains(' ') {
-                    Cow::Owned(format!("\"{}\"", value))
+        rendered_params.sort_unstable_by(|(name1, _), (name2, _)| name1.cmp(name2));
+    }
+
+    fn render<'a>(&'a self, f: &mut fmt::Formatter<'_>, is_alt: bool, flavor: Option<Flavor>, depth: usize, scratch: &mut RenderedParams<'a>) -> fmt::Result {
+        for _ in 0..depth {
+            f.write_str("--")?;
+        }
+        // main text
+        let mut text = &*self.text;
+        while let Some(idx) = text.find(['|', '\n']) {
+            f.write_str(&text[..idx])?;
+            f.write_str(if text[idx..].starts_with('|') { "¦" } else { " " })?;
+            text = &text[idx + 1..];
+        }
+        f.write_str(text)?;
+        // parameters
+        self.write_params(scratch, is_alt, flavor);
+        if !scratch.is_empty() {
+            f.write_str(" |")?;
+            //TODO percent-encode parameter values other than `href` and images for SwiftBar builds which decode them, so they can contain quotes, pipes, and line breaks (blocked on a SwiftBar build that does)
+            for (name, value) in scratch.iter() {
+                if value.contains(' ') {
+                    //TODO values containing both spaces and double quotes are misparsed (reported by Menu::validate), fall back to single quotes? (test if BitBar supports these first)
+                    write!(f, " {name}=\"{value}\"")?;
                 } else {
-                    value
-                }; //TODO check for double quotes in value, fall back to single quotes? (test if BitBar supports these first)
-                write!(f, " {}={}", name, quoted_value)?;
+                    write!(f, " {name}={value}")?;
+                }
             }
         }
         writeln!(f)?;
         // additional items
         match &self.extra {
-            Some(attr::Extra::Alternate(ref alt)) => { alt.render(f, true)?; }
-            Some(attr::Extra::Submenu(ref sub)) => {
-                let sub_fmt = format!("{}", sub);
-                for line in sub_fmt.lines() {
-                    writeln!(f, "--{}", line)?;
-                }
-            }
+            Some(attr::Extra::Alternate(ref alt)) => { alt.render(f, true, flavor, depth, scratch)?; }
+            Some(attr::Extra::Submenu(ref sub)) => for item in &sub.0 {
+                item.render(f, flavor, depth + 1, scratch)?;
+            },
             None => {}
         }
         Ok(())
     }
 }
 
+/// The parameters of a menu item as pairs of name and value, sorted by name.
+pub(crate) type RenderedParams<'a> = Vec<(Cow<'a, str>, Cow<'a, str>)>;
+
+/// The names of the first few command parameters, so they don't have to be allocated for each item.
+const PARAM_NAMES: [&str; 9] = ["param1", "param2", "param3", "param4", "param5", "param6", "param7", "param8", "param9"];
+
+/// Creates a menu item with the given text without copying it.
+impl From<&'static str> for ContentItem {
+    fn from(text: &'static str) -> ContentItem {
+        ContentItem::from(Cow::Borrowed(text))
+    }
+}
+
+/// Creates a menu item with the given text without copying it.
+impl From<Cow<'static, str>> for ContentItem {
+    fn from(text: Cow<'static, str>) -> ContentItem {
+        ContentItem {
+            text,
+            ..ContentItem::default()
+        }
+    }
+}
+
 impl fmt::Display for ContentItem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.render(f, false)
+        self.render(f, false, None, 0, &mut RenderedParams::default())
     }
 }
 
 /// A menu item can either be a separator or a content item.
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum MenuItem {
     /// A content item, i.e. any menu item that's not a separator.
     Content(ContentItem),
@@ -265,18 +587,100 @@ impl Default for MenuItem {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for MenuItem {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<MenuItem> {
+        Ok(if u.ratio(1, 5)? { MenuItem::Sep } else { MenuItem::Content(u.arbitrary()?) })
+    }
+}
+
 impl From<ContentItem> for MenuItem {
     fn from(i: ContentItem) -> MenuItem {
         MenuItem::Content(i)
     }
 }
 
+impl MenuItem {
+    fn render<'a>(&'a self, f: &mut fmt::Formatter<'_>, flavor: Option<Flavor>, depth: usize, scratch: &mut RenderedParams<'a>) -> fmt::Result {
+        match self {
+            MenuItem::Content(content) => content.render(f, false, flavor, depth, scratch),
+            MenuItem::Sep => {
+                for _ in 0..depth {
+                    f.write_str("--")?;
+                }
+                writeln!(f, "---")
+            }
+        }
+    }
+}
+
 impl fmt::Display for MenuItem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            MenuItem::Content(content) => write!(f, "{}", content),
-            MenuItem::Sep => writeln!(f, "---")
+        self.render(f, None, 0, &mut RenderedParams::default())
+    }
+}
+
+/// How [`Menu::group_by`] displays groups of items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupStyle {
+    /// Each group is a submenu of an item displaying its key.
+    Submenus,
+    /// Each group is a [`Section`](section::Section) with its key as the title, i.e. the groups are separated by separators.
+    Sections,
+}
+
+fn find_in_item<'a>(item: &'a ContentItem, id: &str) -> Option<&'a ContentItem> {
+    if item.id.as_deref() == Some(id) { return Some(item) }
+    match &item.extra {
+        Some(attr::Extra::Alternate(alt)) => find_in_item(alt, id),
+        Some(attr::Extra::Submenu(sub)) => sub.find(id),
+        None => None,
+    }
+}
+
+fn find_in_item_mut<'a>(item: &'a mut ContentItem, id: &str) -> Option<&'a mut ContentItem> {
+    if item.id.as_deref() == Some(id) { return Some(item) }
+    match &mut item.extra {
+        Some(attr::Extra::Alternate(_)) => None, // modifying an alternate item could give it a submenu
+        Some(attr::Extra::Submenu(sub)) => sub.find_mut(id),
+        None => None,
+    }
+}
+
+/// Appends the given item to `items`, replacing its submenu with its items, recursively. The text of each item is prefixed with the text of the items containing it.
+fn flatten_item(items: &mut Vec<MenuItem>, prefix: Option<&str>, mut item: ContentItem) {
+    if let Some(prefix) = prefix {
+        item.text = Cow::Owned(format!("{prefix} ▸ {}", item.text));
+        if let Some(attr::Extra::Alternate(ref mut alt)) = item.extra {
+            alt.0.text = Cow::Owned(format!("{prefix} ▸ {}", alt.0.text));
+        }
+    }
+    match item.extra.take() {
+        Some(attr::Extra::Submenu(sub)) => {
+            let prefix = item.text.clone();
+            if item.href.is_some() || item.command.is_some() {
+                items.push(item.into());
+            }
+            for sub_item in sub.0 {
+                match sub_item {
+                    MenuItem::Content(sub_item) => flatten_item(items, Some(&prefix), sub_item),
+                    MenuItem::Sep => items.push(MenuItem::Sep),
+                }
+            }
         }
+        extra => {
+            item.extra = extra;
+            items.push(item.into());
+        }
+    }
+}
+
+/// The content item in a run of items between separators.
+fn content(item: &MenuItem) -> &ContentItem {
+    match item {
+        MenuItem::Content(item) => item,
+        MenuItem::Sep => unreachable!("separators are not part of runs of items"),
     }
 }
 
@@ -291,6 +695,187 @@ impl Menu {
     pub fn push(&mut self, item: impl Into<MenuItem>) {
         self.0.push(item.into());
     }
+
+    /// Returns the item with the given [`id`](ContentItem::id), searching submenus and alternate items.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bitbar::{ContentItem, Menu};
+    ///
+    /// let menu = Menu(vec![ContentItem::new("Downloading: 42%").id("download").into()]);
+    /// assert_eq!(menu.find("download").unwrap().text, "Downloading: 42%");
+    /// ```
+    pub fn find(&self, id: &str) -> Option<&ContentItem> {
+        self.0.iter().find_map(|item| match item {
+            MenuItem::Content(item) => find_in_item(item, id),
+            MenuItem::Sep => None,
+        })
+    }
+
+    /// Returns a mutable reference to the item with the given [`id`](ContentItem::id), searching submenus. Unlike [`Menu::find`], this doesn't search alternate items, since a mutable reference could be used to give them a submenu.
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut ContentItem> {
+        self.0.iter_mut().find_map(|item| match item {
+            MenuItem::Content(item) => find_in_item_mut(item, id),
+            MenuItem::Sep => None,
+        })
+    }
+
+    /// The runs of items between separators, excluding the items before the first separator, which are displayed in the menu bar.
+    fn sections_mut(&mut self) -> impl Iterator<Item = &mut [MenuItem]> {
+        self.0.split_mut(|item| matches!(item, MenuItem::Sep)).skip(1)
+    }
+
+    /// Sorts the items between each pair of separators by their text, case-insensitively. Separators, as well as the items before the first separator (which are displayed in the menu bar), stay in place.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bitbar::{Menu, MenuItem};
+    ///
+    /// let mut menu = Menu(vec![MenuItem::new("Title"), MenuItem::Sep, MenuItem::new("b"), MenuItem::new("A"), MenuItem::Sep, MenuItem::new("d"), MenuItem::new("c")]);
+    /// menu.sort_by_text();
+    /// assert_eq!(menu.to_string(), "Title\n---\nA\nb\n---\nc\nd\n");
+    /// ```
+    pub fn sort_by_text(&mut self) {
+        for section in self.sections_mut() {
+            section.sort_by(|item1, item2| {
+                let text1 = content(item1).text.chars().flat_map(char::to_lowercase);
+                let text2 = content(item2).text.chars().flat_map(char::to_lowercase);
+                text1.cmp(text2)
+            });
+        }
+    }
+
+    /// Sorts the items between each pair of separators by the given key, keeping the order of items with equal keys. Separators, as well as the items before the first separator (which are displayed in the menu bar), stay in place.
+    pub fn sort_by_key<K: Ord>(&mut self, mut key: impl FnMut(&ContentItem) -> K) {
+        for section in self.sections_mut() {
+            section.sort_by_key(|item| key(content(item)));
+        }
+    }
+
+    /// Groups the items between each pair of separators by the given key, with the groups ordered by key and titled with the key's [`Display`](fmt::Display) representation. Within each group, items keep their order. Separators, as well as the items before the first separator (which are displayed in the menu bar), stay in place.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bitbar::{GroupStyle, Menu, MenuItem};
+    ///
+    /// let mut menu = Menu(vec![MenuItem::new("Fruits"), MenuItem::Sep, MenuItem::new("banana"), MenuItem::new("avocado"), MenuItem::new("blueberry")]);
+    /// menu.group_by(|item| item.text.chars().next().unwrap_or_default(), GroupStyle::Submenus);
+    /// assert_eq!(menu.to_string(), "Fruits\n---\na\n--avocado\nb\n--banana\n--blueberry\n");
+    /// ```
+    pub fn group_by<K: Ord + fmt::Display>(&mut self, mut key: impl FnMut(&ContentItem) -> K, style: GroupStyle) {
+        let mut runs = mem::take(&mut self.0).into_iter().fold(vec![Vec::default()], |mut runs, item| {
+            if let MenuItem::Sep = item { runs.push(Vec::default()) } else { runs.last_mut().expect("runs is never empty").push(item) }
+            runs
+        }).into_iter();
+        self.0.extend(runs.next().expect("runs is never empty"));
+        for run in runs {
+            let mut groups = BTreeMap::<K, Vec<MenuItem>>::default();
+            for item in run {
+                groups.entry(key(content(&item))).or_default().push(item);
+            }
+            self.0.push(MenuItem::Sep);
+            for (idx, (key, group)) in groups.into_iter().enumerate() {
+                match style {
+                    GroupStyle::Submenus => self.0.push(ContentItem::new(key).sub(group).into()),
+                    // the first section follows the existing separator
+                    GroupStyle::Sections => self.0.extend(section::Section::new(key, group).into_iter().skip(if idx == 0 { 1 } else { 0 })),
+                }
+            }
+        }
+    }
+
+    /// Returns how deeply submenus are nested in this menu, i.e. 0 if it has no submenus, 1 if none of its submenus have submenus, and so on.
+    pub fn max_depth(&self) -> usize {
+        self.0.iter()
+            .filter_map(|item| if let MenuItem::Content(ContentItem { extra: Some(attr::Extra::Submenu(sub)), .. }) = item { Some(sub.max_depth() + 1) } else { None })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Replaces submenus nested more deeply than `depth` with their items, prefixed with the path to them, so that [`max_depth`](Self::max_depth) is at most `depth`.
+    ///
+    /// An item with a submenu which is flattened is only kept if it has a link or command, since it would otherwise do nothing when clicked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bitbar::{
+    ///     ContentItem,
+    ///     Menu,
+    ///     MenuItem,
+    /// };
+    ///
+    /// let mut menu = Menu(vec![
+    ///     ContentItem::new("Title").sub([
+    ///         ContentItem::new("Sub").sub([
+    ///             MenuItem::new("Leaf 1"),
+    ///             MenuItem::new("Leaf 2"),
+    ///         ]).into(),
+    ///     ]).into(),
+    /// ]);
+    /// assert_eq!(menu.max_depth(), 2);
+    /// menu.flatten_beyond(1);
+    /// assert_eq!(menu.max_depth(), 1);
+    /// assert_eq!(menu.to_string(), "Title\n--Sub ▸ Leaf 1\n--Sub ▸ Leaf 2\n");
+    /// ```
+    pub fn flatten_beyond(&mut self, depth: usize) {
+        if let Some(depth) = depth.checked_sub(1) {
+            for item in &mut self.0 {
+                if let MenuItem::Content(ContentItem { extra: Some(attr::Extra::Submenu(sub)), .. }) = item {
+                    sub.flatten_beyond(depth);
+                }
+            }
+        } else {
+            for item in mem::take(&mut self.0) {
+                match item {
+                    MenuItem::Content(item) => flatten_item(&mut self.0, None, item),
+                    MenuItem::Sep => self.0.push(MenuItem::Sep),
+                }
+            }
+        }
+    }
+
+    /// Renders this menu for the given BitBar implementation, omitting any parameters it doesn't [support](Flavor::supports_param).
+    ///
+    /// Like the [`Display`](fmt::Display) implementation of `Menu`, the output includes a trailing newline.
+    pub fn display_for(&self, flavor: Flavor) -> DisplayFor<'_> {
+        DisplayFor { menu: self, flavor: Some(flavor) }
+    }
+
+    /// Checks this menu for mistakes which the given BitBar implementation would silently ignore or display incorrectly. See the [`validate`] module for details.
+    pub fn validate(&self, flavor: Flavor) -> Vec<validate::Issue> {
+        validate::menu(self, flavor, &validate::Limits::default())
+    }
+
+    /// Like [`Menu::validate`], but reports items exceeding the given limits instead of the default ones.
+    pub fn validate_with(&self, flavor: Flavor, limits: &validate::Limits) -> Vec<validate::Issue> {
+        validate::menu(self, flavor, limits)
+    }
+
+    /// Moves parameters of commands running the plugin's own subcommands which are longer than `max_len` bytes to files in a subdirectory named `spill` of the [plugin cache directory](cache_dir), and replaces them with a reference to the file.
+    ///
+    /// When the command is run, the parameters are read back from the files before being passed to the subcommand, so this is transparent to [`command`] functions. If a file can't be read, the error is reported like other errors in subcommands and the subcommand isn't run. Commands running other programs are left unchanged. [`validate::MAX_PARAM_LEN`] is a reasonable default limit.
+    ///
+    /// Spill files are reused as long as a menu containing the same parameter is rendered, and removed once they haven't been used for a week.
+    pub fn spill_long_params(&mut self, max_len: usize) -> io::Result<()> {
+        validate::spill_menu(self, max_len, &current_exe()?)
+    }
+
+    /// Renders this menu as a human-readable tree for inspecting plugin output in a terminal.
+    pub fn preview(&self) -> preview::Preview<'_> {
+        preview::Preview::new(self)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+impl<'a> arbitrary::Arbitrary<'a> for Menu {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Menu> {
+        Ok(Menu(u.arbitrary()?))
+    }
 }
 
 impl<A: Into<MenuItem>> FromIterator<A> for Menu {
@@ -317,8 +902,22 @@ impl IntoIterator for Menu {
 /// Note that the output this generates already includes a trailing newline, so it should be used with `print!` instead of `println!`.
 impl fmt::Display for Menu {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for menu_item in &self.0 {
-            write!(f, "{}", menu_item)?;
+        DisplayFor { menu: self, flavor: None }.fmt(f)
+    }
+}
+
+/// A [`Menu`] rendered for a specific BitBar implementation, returned by [`Menu::display_for`].
+pub struct DisplayFor<'a> {
+    menu: &'a Menu,
+    flavor: Option<Flavor>,
+}
+
+impl fmt::Display for DisplayFor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // reused for the parameters of each item
+        let mut scratch = RenderedParams::default();
+        for menu_item in &self.menu.0 {
+            menu_item.render(f, self.flavor, 0, &mut scratch)?;
         }
         Ok(())
     }
@@ -326,124 +925,1506 @@ impl fmt::Display for Menu {
 
 /// Members of this trait can be returned from a main function annotated with [`main`].
 pub trait MainOutput {
+    /// Writes this value as a menu to the given output, using the given template image in case of an error.
+    fn write_main_output(self, output: &mut dyn io::Write, error_template_image: Option<attr::Image>) -> io::Result<()>;
+
     /// Displays this value as a menu, using the given template image in case of an error.
-    fn main_output(self, error_template_image: Option<attr::Image>);
+    ///
+    /// # Panics
+    ///
+    /// If writing to stdout fails.
+    fn main_output(self, error_template_image: Option<attr::Image>)
+    where Self: Sized {
+        let mut stdout = buffered_stdout();
+        self.write_main_output(&mut stdout, error_template_image).expect("failed to write menu to stdout");
+        stdout.flush().expect("failed to write menu to stdout");
+    }
+
+    /// Converts this value into a menu if it renders as a single menu, so that it can be passed to an [`ErrorFormatter`]. Implementations which wrap another `MainOutput` should forward to it.
+    #[doc(hidden)] fn into_error_menu(self) -> Result<Menu, Self>
+    where Self: Sized {
+        Err(self)
+    }
+}
+
+/// Stdout is line-buffered, which makes printing large menus slow. This buffers the entire output instead, so [`io::Write::flush`] must be called to display it.
+pub(crate) fn buffered_stdout() -> BufWriter<StdoutLock<'static>> {
+    BufWriter::new(io::stdout().lock())
 }
 
 impl<T: Into<Menu>> MainOutput for T {
-    fn main_output(self, _: Option<attr::Image>) {
-        print!("{}", self.into());
+    fn write_main_output(self, output: &mut dyn io::Write, _: Option<attr::Image>) -> io::Result<()> {
+        write_menu(output, &self.into())
+    }
+
+    fn into_error_menu(self) -> Result<Menu, Self> {
+        Ok(self.into())
+    }
+}
+
+static PREVIEW_MODE: AtomicBool = AtomicBool::new(false);
+
+#[doc(hidden)] pub fn set_preview_mode() { // used in proc macro
+    PREVIEW_MODE.store(true, Ordering::SeqCst);
+}
+
+static VALIDATE_MODE: AtomicBool = AtomicBool::new(false);
+
+#[doc(hidden)] pub fn set_validate_mode() { // used in proc macro
+    VALIDATE_MODE.store(true, Ordering::SeqCst);
+}
+
+static TERMINAL_MODE: AtomicBool = AtomicBool::new(false);
+
+#[doc(hidden)] pub fn detect_terminal_mode() { // used in proc macro
+    if io::stdout().is_terminal() && flavor::standalone() {
+        TERMINAL_MODE.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Whether the menu is written as a colored [preview](Menu::preview) because the plugin was run directly in a terminal.
+pub(crate) fn terminal_mode() -> bool {
+    TERMINAL_MODE.load(Ordering::SeqCst)
+}
+
+/// Writes a menu for the detected flavor, as a [preview](Menu::preview) if the plugin was run with `--bitbar-preview` or directly in a terminal, or as a list of [validation issues](Menu::validate) if it was run with `--bitbar-validate`.
+fn write_menu(output: &mut dyn io::Write, menu: &Menu) -> io::Result<()> {
+    if VALIDATE_MODE.load(Ordering::SeqCst) {
+        for issue in menu.validate(Flavor::check()) {
+            writeln!(output, "{issue}")?;
+        }
+        Ok(())
+    } else if PREVIEW_MODE.load(Ordering::SeqCst) {
+        write!(output, "{}", menu.preview())
+    } else if terminal_mode() {
+        write!(output, "{}", menu.preview().ansi_colors())
+    } else {
+        write!(output, "{}", menu.display_for(Flavor::check()))
     }
 }
 
-/// In the `Err` case, the menu will be prefixed with a menu item displaying the `error_template_image` and the text `?`.
+/// In the `Err` case, the menu is formatted using the [`ErrorFormatter`] set using [`set_error_formatter`]. By default, it will be prefixed with a menu item displaying the `error_template_image` and the text `?`.
 impl<T: MainOutput, E: MainOutput> MainOutput for Result<T, E> {
-    fn main_output(self, error_template_image: Option<attr::Image>) {
+    fn write_main_output(self, output: &mut dyn io::Write, error_template_image: Option<attr::Image>) -> io::Result<()> {
         match self {
-            Ok(x) => x.main_output(error_template_image),
+            Ok(x) => x.write_main_output(output, error_template_image),
             Err(e) => {
-                let mut header = ContentItem::new("?");
-                if let Some(error_template_image) = error_template_image {
-                    header = match header.template_image(error_template_image) {
-                        Ok(header) => header,
-                        Err(never) => match never {},
-                    };
+                let formatter = error_formatter();
+                match e.into_error_menu() {
+                    Ok(menu) => write_menu(output, &formatter(menu, error_template_image)),
+                    Err(e) => {
+                        // not a single menu (e.g. a stream), so the formatter only generates the header
+                        write_menu(output, &formatter(Menu::default(), error_template_image))?;
+                        e.write_main_output(output, None)
+                    }
                 }
-                print!("{}", Menu(vec![header.into(), MenuItem::Sep]));
-                e.main_output(None);
             }
         }
     }
+
+    fn into_error_menu(self) -> Result<Menu, Self> {
+        match self {
+            Ok(x) => x.into_error_menu().map_err(Ok),
+            Err(e) => e.into_error_menu().map_err(Err),
+        }
+    }
+}
+
+/// A function which formats the menu displayed when the plugin's `main` function returns an error. It takes the menu generated from the error and the template image set using `#[bitbar::main(error_template_image = "...")]`, if any.
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::{ContentItem, Menu, MenuItem, attr::Image};
+///
+/// fn branded_error(error: Menu, _: Option<Image>) -> Menu {
+///     let mut menu = Menu(vec![ContentItem::new("My Plugin ⚠").color("orange").unwrap().into(), MenuItem::Sep]);
+///     let report_url = format!("https://example.com/issues/new?body={}", url::form_urlencoded::byte_serialize(error.to_string().as_bytes()).collect::<String>());
+///     menu.extend(error);
+///     menu.push(ContentItem::new("Report issue").href(report_url).unwrap());
+///     menu
+/// }
+///
+/// #[bitbar::main(error_formatter = "branded_error")]
+/// fn main() -> Result<Menu, Menu> {
+///     Err(Menu(vec![MenuItem::new("something went wrong")]))
+/// }
+/// ```
+pub type ErrorFormatter = fn(Menu, Option<attr::Image>) -> Menu;
+
+static ERROR_FORMATTER: OnceLock<ErrorFormatter> = OnceLock::new();
+
+fn error_formatter() -> ErrorFormatter {
+    *ERROR_FORMATTER.get_or_init(|| default_error_formatter)
+}
+
+/// Sets the function used to format the menu displayed when `main` returns an error. Only the first call has an effect, and it must happen before an error menu is displayed.
+///
+/// This can also be set using `#[bitbar::main(error_formatter = "...")]`.
+pub fn set_error_formatter(formatter: ErrorFormatter) {
+    let _ = ERROR_FORMATTER.set(formatter);
+}
+
+/// The default [`ErrorFormatter`], which prefixes the error menu with a menu item displaying the template image (if any) and the text `?`, followed by a separator.
+pub fn default_error_formatter(error: Menu, error_template_image: Option<attr::Image>) -> Menu {
+    let mut header = ContentItem::new("?");
+    if let Some(error_template_image) = error_template_image {
+        header = match header.template_image(error_template_image) {
+            Ok(header) => header,
+            Err(never) => match never {},
+        };
+    }
+    let mut menu = Menu(vec![header.into(), MenuItem::Sep]);
+    menu.extend(error);
+    menu
 }
 
-#[cfg(feature = "tokio")]
-#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
 /// Members of this trait can be returned from a main function annotated with [`main`].
 pub trait AsyncMainOutput<'a> {
+    /// Writes this value as a menu to the given output, using the given template image in case of an error.
+    fn write_main_output<'w>(self, output: &'w mut (dyn AsyncWrite + Unpin + 'w), error_template_image: Option<attr::Image>) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'w>>
+    where 'a: 'w;
+
     /// Displays this value as a menu, using the given template image in case of an error.
-    fn main_output(self, error_template_image: Option<attr::Image>) -> Pin<Box<dyn Future<Output = ()> + 'a>>;
+    ///
+    /// # Panics
+    ///
+    /// If writing to stdout fails.
+    fn main_output(self, error_template_image: Option<attr::Image>) -> Pin<Box<dyn Future<Output = ()> + 'a>>
+    where Self: Sized + 'a {
+        Box::pin(async move {
+            let mut stdout = AllowStdIo::new(BufWriter::new(io::stdout()));
+            self.write_main_output(&mut stdout, error_template_image).await.expect("failed to write menu to stdout");
+            stdout.into_inner().flush().expect("failed to write menu to stdout");
+        })
+    }
 }
 
-#[cfg(feature = "tokio")]
-#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
 impl<'a, T: MainOutput + 'a> AsyncMainOutput<'a> for T {
-    fn main_output(self, error_template_image: Option<attr::Image>) -> Pin<Box<dyn Future<Output = ()> + 'a>> {
+    fn write_main_output<'w>(self, output: &'w mut (dyn AsyncWrite + Unpin + 'w), error_template_image: Option<attr::Image>) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'w>>
+    where 'a: 'w {
         Box::pin(async move {
-            MainOutput::main_output(self, error_template_image);
+            let mut buf = Vec::default();
+            MainOutput::write_main_output(self, &mut buf, error_template_image)?;
+            output.write_all(&buf).await
         })
     }
 }
 
-/// Members of this trait can be returned from a subcommand function annotated with [`command`] or [`fallback_command`].
-pub trait CommandOutput {
-    /// Reports any errors in this command output as macOS notifications.
-    fn report(self, cmd_name: &str);
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[doc(hidden)] pub type CommandFn = fn(Vec<String>) -> Pin<Box<dyn Future<Output = ()>>>; // used in proc macro
+#[cfg(not(any(feature = "async-std", feature = "futures-executor", feature = "tokio")))]
+#[doc(hidden)] pub type CommandFn = fn(Vec<String>); // used in proc macro
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[doc(hidden)] pub type FallbackFn = fn(String, Vec<String>) -> Pin<Box<dyn Future<Output = ()>>>; // used in proc macro
+#[cfg(not(any(feature = "async-std", feature = "futures-executor", feature = "tokio")))]
+#[doc(hidden)] pub type FallbackFn = fn(String, Vec<String>); // used in proc macro
+
+#[doc(hidden)] pub struct CommandRegistration { // used in proc macro
+    pub name: &'static str,
+    pub params: &'static [CommandParam],
+    pub run: CommandFn,
 }
 
-impl CommandOutput for () {
-    fn report(self, _: &str) {}
+/// A parameter of a registered command, as printed by the `bitbar-commands` subcommand.
+#[doc(hidden)] pub struct CommandParam { // used in proc macro
+    pub name: &'static str,
+    pub ty: &'static str,
+    /// One of `required`, `optional`, `flag`, `varargs`, or `clap`.
+    pub kind: &'static str,
+    pub default: Option<&'static str>,
 }
 
-impl<T: CommandOutput, E: fmt::Debug + fmt::Display> CommandOutput for Result<T, E> {
-    fn report(self, cmd_name: &str) {
-        match self {
-            Ok(x) => x.report(cmd_name),
-            Err(e) => {
-                notify_error(&format!("{}: {}", cmd_name, e), &format!("{e:?}"));
-                process::exit(1);
-            }
-        }
+#[cfg(feature = "inventory")] inventory::collect!(CommandRegistration);
+
+#[doc(hidden)] pub fn registered_command(name: &str) -> Option<CommandFn> { // used in proc macro
+    #[cfg(feature = "inventory")] { inventory::iter::<CommandRegistration>.into_iter().find(|registration| registration.name == name).map(|registration| registration.run) }
+    #[cfg(not(feature = "inventory"))] { let _ = name; None }
+}
+
+#[doc(hidden)] pub struct SubcommandsRegistration { // used in proc macro
+    pub names: &'static [&'static str],
+    pub run: FallbackFn,
+}
+
+#[cfg(feature = "inventory")] inventory::collect!(SubcommandsRegistration);
+
+#[doc(hidden)] pub fn registered_subcommands(name: &str) -> Option<FallbackFn> { // used in proc macro
+    #[cfg(feature = "inventory")] { inventory::iter::<SubcommandsRegistration>.into_iter().find(|registration| registration.names.contains(&name)).map(|registration| registration.run) }
+    #[cfg(not(feature = "inventory"))] { let _ = name; None }
+}
+
+/// The names of all registered commands and subcommands, excluding those built into this crate.
+///
+/// Without the `inventory` feature, commands aren't registered, so this is always empty.
+fn command_names() -> Vec<&'static str> {
+    #[cfg(feature = "inventory")] {
+        let mut names = inventory::iter::<CommandRegistration>.into_iter().map(|registration| registration.name)
+            .chain(inventory::iter::<SubcommandsRegistration>.into_iter().flat_map(|registration| registration.names.iter().copied()))
+            .filter(|name| !name.starts_with("bitbar-"))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
     }
+    #[cfg(not(feature = "inventory"))] { Vec::default() }
 }
 
-#[doc(hidden)] pub fn notify(body: impl fmt::Display) { // used in proc macro
-    if_chain! {
-        if let Flavor::SwiftBar(swiftbar) = Flavor::check();
-        if let Ok(notification) = flavor::swiftbar::Notification::new(swiftbar);
-        then {
-            let _ = notification
-                .title(env!("CARGO_PKG_NAME"))
-                .body(body.to_string())
-                .send();
-        } else {
-            #[cfg(target_os = "macos")] {
-                let _ = notify_rust::set_application(&notify_rust::get_bundle_identifier_or_default("BitBar"));
-                let _ = notify_rust::Notification::default()
-                    .summary(&env!("CARGO_PKG_NAME"))
-                    .sound_name("Funky")
-                    .body(&body.to_string())
-                    .show();
-            }
-            #[cfg(not(target_os = "macos"))] {
-                eprintln!("{body}");
-            }
+#[doc(hidden)] pub fn print_help(name: &str, version: &str, description: &str) { // used in proc macro
+    println!("{name} {version}");
+    if !description.is_empty() { println!("{description}") }
+    println!();
+    println!("Run without arguments to print the menu.");
+    let commands = command_names();
+    if !commands.is_empty() {
+        println!();
+        println!("Commands:");
+        for command in commands {
+            println!("  {command}");
         }
     }
 }
 
-#[doc(hidden)] pub fn notify_error(display: &str, debug: &str) { // used in proc macro
-    if_chain! {
-        if let Flavor::SwiftBar(swiftbar) = Flavor::check();
-        if let Ok(notification) = flavor::swiftbar::Notification::new(swiftbar);
-        then {
-            let _ = notification
-                .title(env!("CARGO_PKG_NAME"))
-                .subtitle(display)
-                .body(format!("debug: {debug}"))
-                .send();
-        } else {
-            #[cfg(target_os = "macos")] {
-                let _ = notify_rust::set_application(&notify_rust::get_bundle_identifier_or_default("BitBar"));
-                let _ = notify_rust::Notification::default()
-                    .summary(display)
-                    .sound_name("Funky")
-                    .body(&format!("debug: {debug}"))
-                    .show();
-            }
-            #[cfg(not(target_os = "macos"))] {
-                eprintln!("{display}");
-                eprintln!("debug: {debug}");
-            }
+#[doc(hidden)] pub const fn const_str_eq(a: &str, b: &str) -> bool { // used in proc macro
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() { return false }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] { return false }
+        i += 1;
+    }
+    true
+}
+
+#[doc(hidden)] pub fn unknown_subcommand(subcommand: &str) -> ! { // used in proc macro
+    notify_error(&format!("no such subcommand: {subcommand}"), "");
+    let commands = command_names();
+    if commands.is_empty() {
+        eprintln!("this plugin has no commands");
+    } else {
+        eprintln!("available commands: {}", commands.join(", "));
+    }
+    process::exit(1)
+}
+
+const CLICK_COMMAND: &str = "bitbar-click";
+
+static CLICK_TARGET: OnceLock<String> = OnceLock::new();
+static CLICK_COUNTS: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+/// Restarts the numbering of [`ContentItem::on_click`] items without an [`id`](ContentItem::id), so that each frame of a stream numbers them the same way and identical frames render identically.
+pub(crate) fn reset_click_counts() {
+    CLICK_COUNTS.lock().expect("click handler registry poisoned").clear();
+}
+
+#[doc(hidden)] pub fn set_click_target(id: Option<String>) { // used in proc macro
+    let Some(id) = id else {
+        notify_error(&format!("{CLICK_COMMAND}: missing menu item ID"), "");
+        process::exit(1)
+    };
+    let _ = CLICK_TARGET.set(id);
+}
+
+#[doc(hidden)] pub fn click_target_not_found() -> ! { // used in proc macro
+    let id = CLICK_TARGET.get().map_or("", String::as_str);
+    notify_error(&format!("{CLICK_COMMAND}: the clicked menu item no longer exists"), &format!("no on_click handler with ID {id:?}"));
+    process::exit(1)
+}
+
+#[doc(hidden)] pub struct PluginVar { // used in proc macro
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub default: &'static str,
+}
+
+#[doc(hidden)] pub struct VarsRegistration { // used in proc macro
+    pub vars: &'static [PluginVar],
+}
+
+#[cfg(feature = "inventory")] inventory::collect!(VarsRegistration);
+
+/// A set of subcommands, usually implemented for an enum using [`derive(Subcommands)`](macro@Subcommands).
+///
+/// Use a function annotated with [`#[command(subcommands)]`](macro@command) to handle the subcommands.
+pub trait Subcommands: Sized {
+    /// The names of all subcommands.
+    const NAMES: &'static [&'static str];
+
+    /// Parses a subcommand from its name and arguments.
+    fn parse(name: &str, args: Vec<String>) -> Result<Self, SubcommandError>;
+
+    /// Returns the subcommand name followed by the arguments, i.e. the inverse of [`parse`](Self::parse).
+    fn to_args(&self) -> Vec<String>;
+
+    /// Returns the parameters for running this subcommand from a menu item's `command`.
+    ///
+    /// If there are more than 5 arguments, the resulting `Params` will only work on SwiftBar.
+    fn to_params(&self) -> io::Result<attr::Params> {
+        Ok(attr::Params::new(current_exe()?, self.to_args()))
+    }
+}
+
+/// An error that can occur in [`Subcommands::parse`].
+#[derive(Debug, Error, Clone)]
+pub enum SubcommandError {
+    /// The subcommand name did not match any of the subcommands
+    #[error("no such subcommand: {0}")]
+    Unknown(String),
+    /// The subcommand was called with too many or too few arguments
+    #[error("{subcommand}: expected {expected} arguments, found {found}")]
+    WrongNumberOfArguments {
+        /// The name of the subcommand
+        subcommand: String,
+        /// The number of arguments the subcommand takes
+        expected: usize,
+        /// The number of arguments the subcommand was called with
+        found: usize,
+    },
+    /// An argument could not be parsed
+    #[error("{subcommand}: error parsing parameter {position}: {display}")]
+    Parse {
+        /// The name of the subcommand
+        subcommand: String,
+        /// The 0-based position of the argument that could not be parsed
+        position: usize,
+        /// The `Display` representation of the parse error
+        display: String,
+        /// The `Debug` representation of the parse error
+        debug: String,
+    },
+}
+
+impl SubcommandError {
+    #[doc(hidden)] // used in proc macro
+    pub fn parse(subcommand: &str, position: usize, e: &(impl fmt::Debug + fmt::Display)) -> Self {
+        Self::Parse {
+            subcommand: subcommand.to_owned(),
+            position,
+            display: e.to_string(),
+            debug: format!("{e:?}"),
+        }
+    }
+}
+
+/// Plugin configuration read from environment variables, usually implemented for a struct using [`derive(PluginConfig)`](macro@PluginConfig).
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::{Menu, MenuItem, PluginConfig};
+///
+/// #[derive(PluginConfig)]
+/// struct Config {
+///     api_token: String,
+///     #[config(default = "60")]
+///     interval: u64,
+///     #[config(rename = "LABEL")]
+///     title: Option<String>,
+/// }
+///
+/// #[bitbar::main]
+/// fn main() -> Result<Menu, bitbar::ConfigError> {
+///     let config = Config::from_env()?;
+///     Ok(Menu(vec![MenuItem::new(config.title.unwrap_or_else(|| format!("every {}s", config.interval)))]))
+/// }
+/// ```
+pub trait PluginConfig: Sized {
+    /// Reads the configuration from the given environment.
+    fn from_env_source(env: &dyn flavor::EnvSource) -> Result<Self, ConfigError>;
+
+    /// Reads the configuration from the environment variables of the current process.
+    fn from_env() -> Result<Self, ConfigError> {
+        Self::from_env_source(&flavor::ProcessEnv)
+    }
+}
+
+/// An error that can occur in [`PluginConfig::from_env`].
+#[derive(Debug, Error, Clone)]
+pub enum ConfigError {
+    /// A required variable was not set
+    #[error("missing plugin variable {0}")]
+    Missing(String),
+    /// A variable could not be parsed
+    #[error("error parsing plugin variable {var}: {display}")]
+    Parse {
+        /// The name of the variable
+        var: String,
+        /// The value that could not be parsed
+        value: String,
+        /// The `Display` representation of the parse error
+        display: String,
+        /// The `Debug` representation of the parse error
+        debug: String,
+    },
+}
+
+impl ConfigError {
+    #[doc(hidden)] // used in proc macro
+    pub fn parse(var: &str, value: &str, e: &(impl fmt::Debug + fmt::Display)) -> Self {
+        Self::Parse {
+            var: var.to_owned(),
+            value: value.to_owned(),
+            display: e.to_string(),
+            debug: format!("{e:?}"),
+        }
+    }
+}
+
+impl From<ConfigError> for Menu {
+    fn from(e: ConfigError) -> Menu {
+        let mut menu = vec![MenuItem::new("Error in plugin configuration"), MenuItem::new(&e)];
+        if let ConfigError::Parse { value, debug, .. } = e {
+            menu.push(MenuItem::new(format!("value: {value:?}")));
+            menu.push(MenuItem::new(debug));
+        }
+        Menu(menu)
+    }
+}
+
+/// A fieldless enum whose variants can be selected from a submenu, usually implemented using [`derive(MenuOptions)`](macro@MenuOptions).
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::{ContentItem, Flavor, Menu, MenuItem, MenuOptions};
+///
+/// #[derive(Clone, Copy, MenuOptions)]
+/// enum Theme {
+///     Light,
+///     Dark,
+///     #[menu_option(label = "Match System")]
+///     Auto,
+/// }
+///
+/// #[bitbar::command]
+/// fn set_theme(theme: Theme) -> Result<(), std::convert::Infallible> {
+///     // save the setting
+///     Ok(())
+/// }
+///
+/// #[bitbar::main]
+/// fn main(flavor: Flavor) -> Menu {
+///     let current = Theme::Auto;
+///     match current.options_menu(flavor, set_theme) {
+///         Ok(options) => Menu(vec![ContentItem::new("Theme").sub(options).into()]),
+///         Err(e) => Menu(vec![MenuItem::new(format!("error generating theme menu: {e}"))]),
+///     }
+/// }
+/// ```
+pub trait MenuOptions: Sized + 'static {
+    /// All variants, in the order they should be displayed.
+    const OPTIONS: &'static [Self];
+
+    /// The name used to represent this variant as a command argument.
+    fn name(&self) -> &'static str;
+
+    /// The menu item text for this variant.
+    fn label(&self) -> &'static str;
+
+    /// Generates a submenu with an item for each variant, with the current value (`self`) marked as checked.
+    ///
+    /// Clicking an item runs the command returned by the `command` function for that variant and refreshes the plugin.
+    /// On SwiftBar, the current value is marked using a native checkmark, otherwise its text is prefixed with `✓`.
+    fn options_menu<E>(&self, flavor: Flavor, mut command: impl FnMut(&Self) -> Result<attr::Params, E>) -> Result<Menu, E> {
+        Self::OPTIONS.iter().map(|option| {
+            Ok(checked_item(option.label(), option.name() == self.name(), flavor)
+                .command(command(option)?).unwrap_or_else(|never| match never {})
+                .refresh())
+        }).collect()
+    }
+}
+
+/// Creates a menu item which is marked as checked if `checked` is true, using a native checkmark on SwiftBar and a `✓` prefix otherwise.
+pub(crate) fn checked_item(text: impl fmt::Display, checked: bool, flavor: Flavor) -> ContentItem {
+    match (checked, flavor) {
+        (true, Flavor::SwiftBar(swiftbar)) => {
+            let mut item = ContentItem::new(text);
+            swiftbar.checked(&mut item);
+            item
+        }
+        (true, _) => ContentItem::new(format!("✓ {text}")),
+        (false, _) => ContentItem::new(text),
+    }
+}
+
+/// A value which can be displayed as a menu item, e.g. a domain object listed in a plugin's menu. Usually implemented using [`derive(ToMenuItem)`](macro@ToMenuItem).
+///
+/// Collections of such values can be converted into a [`Menu`] using [`IntoMenu`].
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::{IntoMenu as _, Menu, ToMenuItem, attr::Color, color};
+///
+/// #[derive(ToMenuItem)]
+/// struct PullRequest {
+///     #[menu_item(text)]
+///     title: String,
+///     #[menu_item(href)]
+///     url: url::Url,
+///     #[menu_item(color)]
+///     status: Option<Color>,
+/// }
+///
+/// let prs = vec![
+///     PullRequest { title: "Fix typo".to_owned(), url: "https://example.com/pull/1".parse().unwrap(), status: None },
+///     PullRequest { title: "Add feature".to_owned(), url: "https://example.com/pull/2".parse().unwrap(), status: Some(color!("red")) },
+/// ];
+/// let menu: Menu = prs.iter().into_menu();
+/// assert_eq!(menu.to_string(), "Fix typo | href=https://example.com/pull/1\nAdd feature | color=#ff0000 href=https://example.com/pull/2\n");
+/// ```
+pub trait ToMenuItem {
+    /// The menu item representing this value.
+    fn to_menu_item(&self) -> ContentItem;
+}
+
+impl<T: ToMenuItem + ?Sized> ToMenuItem for &T {
+    fn to_menu_item(&self) -> ContentItem {
+        (**self).to_menu_item()
+    }
+}
+
+/// Converts a collection of [`ToMenuItem`] values into a [`Menu`] with one item per value.
+pub trait IntoMenu {
+    /// Returns a menu with the item for each value, in iteration order.
+    fn into_menu(self) -> Menu;
+}
+
+impl<I: IntoIterator> IntoMenu for I
+where I::Item: ToMenuItem {
+    fn into_menu(self) -> Menu {
+        self.into_iter().map(|value| value.to_menu_item()).collect()
+    }
+}
+
+#[doc(hidden)] pub fn args() -> impl Iterator<Item = String> { // used in proc macro
+    let mut args = std::env::args().collect::<Vec<_>>();
+    // the program name and the subcommand name are never spilled
+    for arg in args.iter_mut().skip(2) {
+        if let Err(e) = validate::unspill(arg) {
+            // the I/O error is the source rather than part of the message
+            let mut display = e.to_string();
+            let mut source = std::error::Error::source(&e);
+            while let Some(e) = source {
+                display.push_str(&format!(": {e}"));
+                source = e.source();
+            }
+            notify_error(&display, &format!("{e:?}"));
+            process::exit(1)
+        }
+    }
+    args.into_iter()
+}
+
+/// Like [`args`], but if the plugin was run without arguments because content was [dropped](flavor::swiftbar::Dropped) onto it, returns the program name, the given command name, and the dropped content as arguments.
+#[doc(hidden)] pub fn args_with_drop(on_drop: &str) -> Box<dyn Iterator<Item = String>> { // used in proc macro
+    let mut args = args().peekable();
+    let program = args.next().expect("missing program name");
+    match flavor::swiftbar::Dropped::check() {
+        Some(dropped) if args.peek().is_none() => Box::new([program, on_drop.to_owned()].into_iter().chain(dropped.into_args())),
+        _ => Box::new(iter::once(program).chain(args)),
+    }
+}
+
+/// The path to the plugin binary, for use in a command's `Params`. Returns an error of kind [`io::ErrorKind::InvalidData`] if the path is not valid UTF-8.
+#[doc(hidden)] pub fn current_exe() -> io::Result<String> { // used in proc macro
+    std::env::current_exe()?.into_os_string().into_string().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF-8 plugin path"))
+}
+
+/// The error returned by the `FromStr` implementation generated by [`derive(MenuOptions)`](macro@MenuOptions).
+#[derive(Debug, Error, Clone)]
+#[error("unknown option: {0}")]
+pub struct UnknownOptionError(pub String);
+
+/// Types which the arguments of a [`fallback_command`] can be parsed into.
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::FromArgs;
+///
+/// struct Ids(Vec<u64>);
+///
+/// impl FromArgs for Ids {
+///     type Err = std::num::ParseIntError;
+///
+///     fn from_args(_: &str, args: Vec<String>) -> Result<Self, Self::Err> {
+///         args.iter().map(|arg| arg.parse()).collect::<Result<_, _>>().map(Self)
+///     }
+/// }
+///
+/// #[bitbar::fallback_command]
+/// fn fallback(cmd: String, Ids(ids): Ids) {
+///     println!("{cmd}: {ids:?}");
+/// }
+/// #
+/// # #[bitbar::main(fallback_command = "fallback")]
+/// # fn main() -> bitbar::Menu { bitbar::Menu::default() }
+/// ```
+pub trait FromArgs: Sized {
+    /// The error returned if the arguments are invalid. It's reported like an error returned from the fallback command.
+    type Err: fmt::Debug + fmt::Display;
+
+    /// Parses the arguments following the subcommand name `cmd`.
+    fn from_args(cmd: &str, args: Vec<String>) -> Result<Self, Self::Err>;
+}
+
+/// Passes the arguments through unchanged.
+impl FromArgs for Vec<String> {
+    type Err = Infallible;
+
+    fn from_args(_: &str, args: Vec<String>) -> Result<Self, Infallible> {
+        Ok(args)
+    }
+}
+
+/// Members of this trait can be returned from a subcommand function annotated with [`command`] or [`fallback_command`].
+pub trait CommandOutput {
+    /// Reports any errors in this command output as macOS notifications.
+    ///
+    /// Outside of SwiftBar, notifications require the `notifications` feature (enabled by default). Without it, errors are printed to stderr instead.
+    fn report(self, cmd_name: &str);
+}
+
+impl CommandOutput for () {
+    fn report(self, _: &str) {}
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+/// Members of this trait can be returned from a subcommand function annotated with [`command`] or [`fallback_command`]. Unlike [`CommandOutput`], reporting the output can do async work, e.g. posting an error to a webhook before sending a notification.
+///
+/// This is implemented for all members of [`CommandOutput`].
+///
+/// # Example
+///
+/// ```rust
+/// use {
+///     std::{
+///         future::Future,
+///         pin::Pin,
+///     },
+///     bitbar::AsyncCommandOutput,
+/// };
+///
+/// struct Reported(Result<(), String>);
+///
+/// impl<'a> AsyncCommandOutput<'a> for Reported {
+///     fn report(self, cmd_name: &'a str) -> Pin<Box<dyn Future<Output = ()> + 'a>> {
+///         Box::pin(async move {
+///             if let Err(e) = self.0 {
+///                 // post the error to a webhook
+///                 bitbar::notify(format!("{cmd_name}: {e}"));
+///             }
+///         })
+///     }
+/// }
+///
+/// #[bitbar::command]
+/// async fn sync() -> Reported {
+///     Reported(Ok(()))
+/// }
+/// #
+/// # #[bitbar::main]
+/// # fn main() -> bitbar::Menu { bitbar::Menu::default() }
+/// ```
+pub trait AsyncCommandOutput<'a> {
+    /// Reports any errors in this command output, e.g. as macOS notifications.
+    fn report(self, cmd_name: &'a str) -> Pin<Box<dyn Future<Output = ()> + 'a>>;
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+impl<'a, T: CommandOutput + 'a> AsyncCommandOutput<'a> for T {
+    fn report(self, cmd_name: &'a str) -> Pin<Box<dyn Future<Output = ()> + 'a>> {
+        Box::pin(async move { CommandOutput::report(self, cmd_name) })
+    }
+}
+
+/// Returning a menu from a command triggers a refresh of the plugin using the URL scheme of the running BitBar implementation, so that clicking a menu item gives instant feedback without waiting for the next scheduled refresh.
+///
+/// Since SwiftBar and BitBar don't read the output of commands, the menu itself is only printed to stdout, which can be useful when running the command from a terminal. The refreshed menu is generated by `main` as usual. For [streamable](https://github.com/swiftbar/SwiftBar#streamable) plugins, the refresh restarts the stream.
+impl CommandOutput for Menu {
+    fn report(self, cmd_name: &str) {
+        let flavor = Flavor::check();
+        let mut stdout = buffered_stdout();
+        if let Err(e) = write!(stdout, "{}", self.display_for(flavor)).and_then(|()| stdout.flush()) {
+            notify_error(&format!("{cmd_name}: failed to print menu: {e}"), &format!("{e:?}"));
+            process::exit(1);
+        }
+        if let Err(e) = refresh_plugin(flavor) {
+            notify_error(&format!("{cmd_name}: failed to refresh plugin: {e}"), &format!("{e:?}"));
+            process::exit(1);
+        }
+    }
+}
+
+fn refresh_plugin(flavor: Flavor) -> io::Result<()> {
+    let url = match flavor {
+        Flavor::SwiftBar(swiftbar) => {
+            let plugin_name = swiftbar.plugin_name().map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
+            Url::parse_with_params("swiftbar://refreshplugin", [("name", plugin_name)])
+        }
+        Flavor::BitBar => {
+            let exe = std::env::current_exe()?;
+            let plugin_name = exe.file_name().and_then(|name| name.to_str()).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "non-UTF-8 plugin path"))?;
+            Url::parse_with_params("bitbar://refreshPlugin", [("name", plugin_name)])
+        }
+        Flavor::Custom(_) => return Ok(()),
+    }.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    open::that(url.as_str())
+}
+
+/// The error displayed when `main` doesn't return within the duration given by `#[bitbar::main(timeout = "...")]`.
+#[derive(Debug, Error, Clone, Copy)]
+#[error("the plugin did not respond within {0:?}")]
+pub struct TimeoutError(pub Duration);
+
+/// Includes the menu most recently published using [`partial`], if any.
+impl From<TimeoutError> for Menu {
+    fn from(e: TimeoutError) -> Menu {
+        let mut menu = vec![
+            MenuItem::new("Plugin timed out"),
+            MenuItem::new(e),
+        ];
+        if let Some(partial) = PARTIAL_MENU.lock().expect("partial menu poisoned").take() {
+            menu.push(MenuItem::Sep);
+            menu.extend(partial.0);
+        }
+        Menu(menu)
+    }
+}
+
+static PARTIAL_MENU: Mutex<Option<Menu>> = Mutex::new(None);
+
+/// Publishes the menu built so far by a `main` function with a [`timeout`](macro@main), replacing any previously published menu.
+///
+/// If `main` doesn't return in time, this menu is displayed below the “Plugin timed out” header instead of being discarded. It has no effect on the output if `main` returns in time.
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::{Menu, MenuItem, TimeoutError};
+///
+/// bitbar::partial(Menu(vec![MenuItem::new("CPU: 12%")]));
+/// assert_eq!(Menu::from(TimeoutError(std::time::Duration::from_secs(30))).to_string(), "Plugin timed out\nthe plugin did not respond within 30s\n---\nCPU: 12%\n");
+/// ```
+pub fn partial(menu: impl Into<Menu>) {
+    *PARTIAL_MENU.lock().expect("partial menu poisoned") = Some(menu.into());
+}
+
+#[doc(hidden)] pub fn timeout_blocking<T: Send + 'static>(duration: Duration, f: impl FnOnce() -> T + Send + 'static) -> Option<T> { // used in proc macro
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || { let _ = tx.send(f()); });
+    rx.recv_timeout(duration).ok()
+}
+
+/// Waits for the given duration without depending on a particular async runtime.
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+pub(crate) async fn sleep(duration: Duration) {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    thread::spawn(move || {
+        thread::sleep(duration);
+        let _ = tx.send(());
+    });
+    let _ = rx.await;
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[doc(hidden)] pub async fn timeout<F: Future>(duration: Duration, fut: F) -> Option<F::Output> { // used in proc macro
+    match futures::future::select(pin!(fut), pin!(sleep(duration))).await {
+        futures::future::Either::Left((output, _)) => Some(output),
+        futures::future::Either::Right((_, _)) => None,
+    }
+}
+
+static BACKGROUND_THREADS: Mutex<Vec<thread::JoinHandle<()>>> = Mutex::new(Vec::new());
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+static BACKGROUND_FUTURES: Mutex<Vec<Pin<Box<dyn Future<Output = ()> + Send>>>> = Mutex::new(Vec::new());
+
+/// Runs the given function on a separate thread which the plugin process waits for before exiting, after its output has been written.
+#[cfg_attr(not(feature = "cache"), allow(unused))]
+pub(crate) fn spawn_background(f: impl FnOnce() + Send + 'static) {
+    BACKGROUND_THREADS.lock().expect("background task registry poisoned").push(thread::spawn(f));
+}
+
+/// Registers the given future to be awaited by the generated `main` after the plugin's output has been written.
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(not(feature = "cache"), allow(unused))]
+pub(crate) fn spawn_background_async(fut: impl Future<Output = ()> + Send + 'static) {
+    BACKGROUND_FUTURES.lock().expect("background task registry poisoned").push(Box::pin(fut));
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[doc(hidden)] pub async fn join_background_tasks() { // used in proc macro
+    loop {
+        let futures = mem::take(&mut *BACKGROUND_FUTURES.lock().expect("background task registry poisoned"));
+        if futures.is_empty() { break }
+        futures::future::join_all(futures).await;
+    }
+    join_background_threads();
+}
+
+#[cfg(not(any(feature = "async-std", feature = "futures-executor", feature = "tokio")))]
+#[doc(hidden)] pub fn join_background_tasks() { // used in proc macro
+    join_background_threads();
+}
+
+fn join_background_threads() {
+    loop {
+        let threads = mem::take(&mut *BACKGROUND_THREADS.lock().expect("background task registry poisoned"));
+        if threads.is_empty() { break }
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Replaces the contents of the file at `path` by first writing them to a temporary file in the same directory, so a crash while writing doesn't leave behind a partially written file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir)?;
+    let file_name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut tmp_name = file_name.to_owned();
+    tmp_name.push(format!(".{}.tmp", process::id()));
+    let tmp_path = dir.join(tmp_name);
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
+/// A hash of the given data which, unlike [`DefaultHasher`](std::collections::hash_map::DefaultHasher), stays the same across Rust versions, for naming files which need to be found again by later runs of the plugin.
+pub(crate) fn stable_hash(data: &[u8]) -> u64 {
+    let mut hasher = SipHasher13::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Sets the modification time of the file at `path` to now, so it isn't removed by [`prune_dir`].
+pub(crate) fn touch(path: &Path) -> io::Result<()> {
+    fs::File::options().write(true).open(path)?.set_modified(SystemTime::now())
+}
+
+/// Removes the files in `dir` which haven't been modified for longer than `max_age`. A missing directory is treated as empty.
+pub(crate) fn prune_dir(dir: &Path, max_age: Duration) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let now = SystemTime::now();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() && now.duration_since(metadata.modified()?).is_ok_and(|age| age > max_age) {
+            match fs::remove_file(entry.path()) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+    }
+    Ok(())
+}
+
+static EXIT_CODE: Mutex<Option<ExitCode>> = Mutex::new(None);
+
+#[doc(hidden)] pub fn exit_code() -> ExitCode { // used in proc macro
+    EXIT_CODE.lock().expect("exit code mutex poisoned").unwrap_or(ExitCode::SUCCESS)
+}
+
+/// The plugin process exits with this exit code after the command returns.
+impl CommandOutput for ExitCode {
+    fn report(self, _: &str) {
+        *EXIT_CODE.lock().expect("exit code mutex poisoned") = Some(self);
+    }
+}
+
+/// Wraps a command output to send a notification if the command succeeds, i.e. if it doesn't error and doesn't set a failure exit code.
+///
+/// This is used by `#[command(notify_success = "...")]`.
+#[derive(Debug, Clone)]
+pub struct NotifySuccess<T> {
+    output: T,
+    message: String,
+}
+
+impl<T: CommandOutput> NotifySuccess<T> {
+    /// Wraps the given command output.
+    pub fn new(output: T, message: impl ToString) -> Self {
+        Self { output, message: message.to_string() }
+    }
+}
+
+impl<T: CommandOutput> CommandOutput for NotifySuccess<T> {
+    fn report(self, cmd_name: &str) {
+        let Self { output, message } = self;
+        output.report(cmd_name);
+        if exit_code() == ExitCode::SUCCESS {
+            notify(message);
+        }
+    }
+}
+
+/// A command output which can request a success notification, a plugin refresh, and a specific exit code.
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::CommandResult;
+///
+/// #[bitbar::command]
+/// fn clear_cache() -> CommandResult {
+///     // clear the cache
+///     CommandResult::new()
+///         .notify("Cache cleared")
+///         .refresh()
+/// }
+/// #
+/// # #[bitbar::main]
+/// # fn main() -> bitbar::Menu { bitbar::Menu::default() }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandResult {
+    notification: Option<String>,
+    refresh: bool,
+    exit_code: ExitCode,
+}
+
+impl CommandResult {
+    /// Creates a command result which does nothing and exits successfully.
+    pub fn new() -> Self {
+        Self {
+            notification: None,
+            refresh: false,
+            exit_code: ExitCode::SUCCESS,
+        }
+    }
+
+    /// Sends a notification with the given text after the command returns.
+    pub fn notify(mut self, body: impl ToString) -> Self {
+        self.notification = Some(body.to_string());
+        self
+    }
+
+    /// Refreshes the plugin using the URL scheme of the running BitBar implementation after the command returns.
+    ///
+    /// This is an alternative to [`ContentItem::refresh`] which can be decided by the command.
+    pub fn refresh(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+
+    /// Sets the exit code of the plugin process.
+    pub fn exit_code(mut self, exit_code: impl Into<ExitCode>) -> Self {
+        self.exit_code = exit_code.into();
+        self
+    }
+}
+
+impl Default for CommandResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandOutput for CommandResult {
+    fn report(self, cmd_name: &str) {
+        let Self { notification, refresh, exit_code } = self;
+        if let Some(body) = notification {
+            notify(body);
+        }
+        if refresh {
+            if let Err(e) = refresh_plugin(Flavor::check()) {
+                notify_error(&format!("{cmd_name}: failed to refresh plugin: {e}"), &format!("{e:?}"));
+                process::exit(1);
+            }
+        }
+        CommandOutput::report(exit_code, cmd_name);
+    }
+}
+
+impl<T: CommandOutput, E: fmt::Debug + fmt::Display> CommandOutput for Result<T, E> {
+    fn report(self, cmd_name: &str) {
+        match self {
+            Ok(x) => x.report(cmd_name),
+            Err(e) => {
+                notify_error(&format!("{}: {}", cmd_name, e), &format!("{e:?}"));
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// An error that can occur in [`Notifier::try_notify`].
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    /// Opening the SwiftBar notification URL failed
+    #[error(transparent)] Io(#[from] io::Error),
+    /// Showing the notification via the macOS notification center failed
+    #[cfg(all(target_os = "macos", feature = "notifications"))]
+    #[error(transparent)] Notification(#[from] notify_rust::error::Error),
+    /// The plugin is not running on SwiftBar, and notifications are not supported on this platform or the `notifications` feature is disabled
+    #[error("notifications are not supported on this platform")]
+    Unsupported,
+}
+
+/// Configures how notifications are sent, e.g. for errors from commands or by [`CommandResult::notify`].
+///
+/// On SwiftBar, notifications are sent via its URL scheme and appear as coming from SwiftBar. Otherwise, they're sent via the macOS notification center using the `notifications` feature, or printed to stderr if that's not available.
+///
+/// The notifier used by this crate can be set using [`set_notifier`] or `#[bitbar::main(notifier = "...")]`.
+///
+/// # Example
+///
+/// ```rust
+/// use bitbar::Notifier;
+///
+/// fn notifier() -> Notifier {
+///     Notifier::new()
+///         .summary("Weather")
+///         .sound("Glass")
+/// }
+///
+/// #[bitbar::main(notifier = "notifier")]
+/// fn main() -> bitbar::Menu {
+///     bitbar::Menu::default()
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notifier {
+    summary: Cow<'static, str>,
+    sound: flavor::swiftbar::Sound,
+    bundle_identifier: Option<Cow<'static, str>>,
+}
+
+impl Notifier {
+    /// Creates a notifier with the default options: the summary `bitbar`, the default sound (`Funky` when not running on SwiftBar), and the bundle identifier of BitBar.
+    pub fn new() -> Self {
+        Self {
+            summary: Cow::Borrowed(env!("CARGO_PKG_NAME")),
+            sound: flavor::swiftbar::Sound::Default,
+            bundle_identifier: None,
+        }
+    }
+
+    /// Sets the summary (title) of notifications. Error notifications use the error message instead when not running on SwiftBar.
+    pub fn summary(mut self, summary: impl ToString) -> Self {
+        self.summary = Cow::Owned(summary.to_string());
+        self
+    }
+
+    /// Sets the sound played for notifications, e.g. `Sound::Glass` or `"Glass"`. On SwiftBar, this uses [`Notification::sound`](flavor::swiftbar::Notification::sound), which currently plays the default sound for anything other than [`Sound::None`](flavor::swiftbar::Sound::None).
+    pub fn sound(mut self, sound: impl Into<flavor::swiftbar::Sound>) -> Self {
+        self.sound = sound.into();
+        self
+    }
+
+    /// Disables sound for notifications.
+    pub fn silent(mut self) -> Self {
+        self.sound = flavor::swiftbar::Sound::None;
+        self
+    }
+
+    /// Sets the bundle identifier of the application notifications appear to come from, e.g. `com.apple.Terminal`. Defaults to BitBar. This has no effect on SwiftBar.
+    pub fn bundle_identifier(mut self, bundle_identifier: impl ToString) -> Self {
+        self.bundle_identifier = Some(Cow::Owned(bundle_identifier.to_string()));
+        self
+    }
+
+    /// Sends a notification with the given text, printing it to stderr if this fails.
+    pub fn notify(&self, body: impl fmt::Display) {
+        let body = body.to_string();
+        if let Err(e) = self.send(None, &body) {
+            eprintln!("{body}");
+            if !matches!(e, NotifyError::Unsupported) { eprintln!("failed to send notification: {e}") }
+        }
+    }
+
+    /// Sends a notification with the given text, returning an error if this fails.
+    pub fn try_notify(&self, body: impl fmt::Display) -> Result<(), NotifyError> {
+        self.send(None, &body.to_string())
+    }
+
+    /// Sends a notification. If a subtitle is given, it's used instead of the summary when not running on SwiftBar.
+    fn send(&self, subtitle: Option<&str>, body: &str) -> Result<(), NotifyError> {
+        if_chain! {
+            if let Flavor::SwiftBar(swiftbar) = Flavor::check();
+            if let Ok(notification) = flavor::swiftbar::Notification::new(swiftbar);
+            then {
+                let mut notification = notification.title(&self.summary).body(body);
+                if let Some(subtitle) = subtitle { notification = notification.subtitle(subtitle) }
+                notification = notification.sound(self.sound.clone());
+                notification.send()?;
+                Ok(())
+            } else {
+                #[cfg(all(target_os = "macos", feature = "notifications"))] {
+                    let _ = notify_rust::set_application(&self.bundle_identifier.as_deref().map_or_else(|| notify_rust::get_bundle_identifier_or_default("BitBar"), str::to_owned));
+                    let mut notification = notify_rust::Notification::default();
+                    notification.summary(subtitle.unwrap_or(&self.summary)).body(body);
+                    match self.sound {
+                        flavor::swiftbar::Sound::Default => { notification.sound_name("Funky"); }
+                        flavor::swiftbar::Sound::None => {}
+                        ref sound => { notification.sound_name(sound.name()); }
+                    }
+                    notification.show()?;
+                    Ok(())
+                }
+                #[cfg(not(all(target_os = "macos", feature = "notifications")))] {
+                    let _ = (subtitle, body);
+                    Err(NotifyError::Unsupported)
+                }
+            }
+        }
+    }
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static NOTIFIER: OnceLock<Notifier> = OnceLock::new();
+
+/// Returns the notifier set using [`set_notifier`], or the [default](Notifier::new) one.
+pub fn notifier() -> &'static Notifier {
+    NOTIFIER.get_or_init(Notifier::new)
+}
+
+/// Sets the notifier used for notifications sent by this crate, e.g. for errors from commands. Only the first call has an effect, and it must happen before the first notification is sent.
+///
+/// This can also be set using `#[bitbar::main(notifier = "...")]`.
+pub fn set_notifier(notifier: Notifier) {
+    let _ = NOTIFIER.set(notifier);
+}
+
+/// Sends a notification with the given text using the [notifier](notifier()), printing it to stderr if this fails.
+pub fn notify(body: impl fmt::Display) {
+    notifier().notify(body);
+}
+
+#[doc(hidden)] pub fn command_param_error(command: &str, position: usize, param: &str, ty: &str, value: &str, e: &(impl fmt::Debug + fmt::Display)) -> ! { // used in proc macro
+    let display = format!("{command}: error parsing parameter {position} ({param}: {ty}) from {value:?}: {e}");
+    let debug = format!("{e:?}");
+    // also print to stderr for debugging, e.g. when running the plugin in a terminal
+    if !report_error(&display, &debug) {
+        eprintln!("{display}");
+        eprintln!("debug: {debug}");
+    }
+    process::exit(1)
+}
+
+#[doc(hidden)] #[derive(Debug, Clone, Copy)] pub struct ErrorSinks { // used in proc macro
+    pub notify: bool,
+    pub stderr: bool,
+    pub log: bool,
+}
+
+static ERROR_SINKS: OnceLock<ErrorSinks> = OnceLock::new();
+
+fn error_sinks() -> ErrorSinks {
+    *ERROR_SINKS.get_or_init(|| ErrorSinks { notify: true, stderr: false, log: false })
+}
+
+#[doc(hidden)] pub fn set_error_sinks(sinks: ErrorSinks) { // used in proc macro
+    let _ = ERROR_SINKS.set(sinks);
+}
+
+/// Looks up a plugin directory: the one given by the SwiftBar environment variable `swiftbar_var` if it's set, and otherwise a directory named after the plugin binary in `~/Library/<library_dir>/bitbar`. Returns `None` if neither `swiftbar_var` nor `HOME` is set.
+fn plugin_dir(swiftbar_var: &str, library_dir: &str) -> Option<io::Result<PathBuf>> {
+    if let Some(path) = std::env::var_os(swiftbar_var) {
+        return Some(Ok(PathBuf::from(path)))
+    }
+    let home = std::env::var_os("HOME")?;
+    Some(std::env::current_exe().and_then(|exe| {
+        let plugin_name = exe.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "plugin path has no file name"))?;
+        Ok(Path::new(&home).join("Library").join(library_dir).join("bitbar").join(plugin_name))
+    }))
+}
+
+fn missing_home() -> io::Result<PathBuf> {
+    Err(io::Error::new(io::ErrorKind::NotFound, "missing HOME environment variable"))
+}
+
+/// The directory for cached data of the plugin, which is also where errors are logged with `#[bitbar::main(on_error = "log")]`.
+///
+/// On SwiftBar, this is the directory given by the `SWIFTBAR_PLUGIN_CACHE_PATH` environment variable. Otherwise, it's a directory named after the plugin binary in `~/Library/Caches/bitbar`.
+pub fn cache_dir() -> io::Result<PathBuf> {
+    plugin_dir("SWIFTBAR_PLUGIN_CACHE_PATH", "Caches").unwrap_or_else(missing_home)
+}
+
+/// Like [`data_dir`], but returns `None` if the directory can't be determined from the environment.
+pub(crate) fn find_data_dir() -> Option<io::Result<PathBuf>> {
+    plugin_dir("SWIFTBAR_PLUGIN_DATA_PATH", "Application Support")
+}
+
+/// The directory for persistent data of the plugin, which is where the `config` and `state` modules store their files.
+///
+/// On SwiftBar, this is the directory given by the `SWIFTBAR_PLUGIN_DATA_PATH` environment variable. Otherwise, it's a directory named after the plugin binary in `~/Library/Application Support/bitbar`.
+pub fn data_dir() -> io::Result<PathBuf> {
+    find_data_dir().unwrap_or_else(missing_home)
+}
+
+/// Log files are rotated when they exceed this size, keeping one old log file.
+pub(crate) const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Opens the log file with the given name in the [cache directory](cache_dir) for appending, rotating it first if necessary.
+pub(crate) fn open_log(name: &str) -> io::Result<fs::File> {
+    let dir = cache_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(name);
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.len() > MAX_LOG_SIZE) {
+        fs::rename(&path, dir.join(format!("{name}.1")))?;
+    }
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// The current time in seconds since the Unix epoch, used to timestamp log lines.
+pub(crate) fn log_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+fn log_error(display: &str, debug: &str) -> io::Result<()> {
+    let timestamp = log_timestamp();
+    let mut file = open_log("errors.log")?;
+    writeln!(file, "[{timestamp}] {display}")?;
+    if !debug.is_empty() { writeln!(file, "[{timestamp}] debug: {debug}")?; }
+    Ok(())
+}
+
+#[doc(hidden)] pub fn notify_error(display: &str, debug: &str) { // used in proc macro
+    report_error(display, debug);
+}
+
+/// Reports an error to the configured [`ErrorSinks`], returning whether it was printed to stderr (either as a sink or because sending the notification failed).
+fn report_error(display: &str, debug: &str) -> bool {
+    let sinks = error_sinks();
+    if sinks.stderr {
+        eprintln!("{display}");
+        if !debug.is_empty() { eprintln!("debug: {debug}") }
+    }
+    if sinks.log {
+        if let Err(e) = log_error(display, debug) {
+            eprintln!("failed to log error: {e}");
+        }
+    }
+    if !sinks.notify { return sinks.stderr }
+    let body = if debug.is_empty() { String::default() } else { format!("debug: {debug}") };
+    if let Err(e) = notifier().send(Some(display), &body) {
+        if !sinks.stderr {
+            eprintln!("{display}");
+            if !body.is_empty() { eprintln!("{body}") }
+        }
+        if !matches!(e, NotifyError::Unsupported) { eprintln!("failed to send notification: {e}") }
+        return true
+    }
+    sinks.stderr
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::collections::BTreeMap,
+        super::{
+            ContentItem,
+            MainOutput as _,
+            Menu,
+            MenuItem,
+            SubcommandError,
+            Subcommands,
+            attr::AltItem,
+            flavor::swiftbar::{
+                BlockingStream,
+                SwiftBar,
+            },
+        },
+    };
+    #[cfg(feature = "arbitrary")] use super::{
+        Flavor,
+        attr::Extra,
+    };
+
+    fn flattened(items: Vec<MenuItem>) -> String {
+        let mut menu = Menu(items);
+        menu.flatten_beyond(0);
+        menu.to_string()
+    }
+
+    #[test]
+    fn flatten_drops_inert_parents() {
+        assert_eq!(flattened(vec![
+            ContentItem::new("A").sub([MenuItem::new("1"), MenuItem::Sep, MenuItem::new("2")]).into(),
+            MenuItem::Sep,
+            MenuItem::new("B"),
+        ]), "A ▸ 1\n---\nA ▸ 2\n---\nB\n");
+    }
+
+    #[test]
+    fn flatten_keeps_clickable_parents() {
+        assert_eq!(flattened(vec![
+            ContentItem::new("A").href("https://example.com/").unwrap().sub([MenuItem::new("1")]).into(),
+        ]), "A | href=https://example.com/\nA ▸ 1\n");
+    }
+
+    #[test]
+    fn flatten_nested() {
+        assert_eq!(flattened(vec![
+            ContentItem::new("A").sub([
+                ContentItem::new("B").sub([MenuItem::new("1")]).into(),
+                MenuItem::new("2"),
+            ]).into(),
+        ]), "A ▸ B ▸ 1\nA ▸ 2\n");
+    }
+
+    #[test]
+    fn flatten_prefixes_alternates() {
+        assert_eq!(flattened(vec![
+            ContentItem::new("A").sub([
+                ContentItem::new("1").alt(AltItem::new("1 (alt)")).unwrap().into(),
+            ]).into(),
+        ]), "A ▸ 1\nA ▸ 1 (alt) | alternate=true\n");
+    }
+
+    #[test]
+    fn flatten_beyond_depth() {
+        let mut menu = Menu(vec![
+            ContentItem::new("A").sub([
+                ContentItem::new("B").sub([
+                    ContentItem::new("C").sub([MenuItem::new("1")]).into(),
+                ]).into(),
+            ]).into(),
+        ]);
+        menu.flatten_beyond(2);
+        assert_eq!(menu.max_depth(), 2);
+        assert_eq!(menu.to_string(), "A\n--B\n----C ▸ 1\n");
+        menu.flatten_beyond(5);
+        assert_eq!(menu.max_depth(), 2);
+    }
+
+    #[test]
+    fn nested_error_menu_is_formatted() {
+        let output = Err::<Menu, Result<Menu, Menu>>(Err(Menu(vec![MenuItem::new("inner error")])));
+        let mut buf = Vec::default();
+        output.write_main_output(&mut buf, None).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "?\n---\ninner error\n");
+    }
+
+    #[derive(Debug, PartialEq, Subcommands)]
+    enum FieldNames {
+        Rename {
+            name: String,
+            args: String,
+        },
+        Count(u8),
+    }
+
+    #[test]
+    fn subcommand_fields_dont_shadow_parameters() {
+        assert_eq!(FieldNames::parse("rename", vec!["a".to_owned(), "b".to_owned()]).unwrap(), FieldNames::Rename { name: "a".to_owned(), args: "b".to_owned() });
+        assert_eq!(FieldNames::Rename { name: "a".to_owned(), args: "b".to_owned() }.to_args(), ["rename", "a", "b"]);
+        match FieldNames::parse("rename", vec!["a".to_owned()]) {
+            Err(SubcommandError::WrongNumberOfArguments { subcommand, expected: 2, found: 1 }) => assert_eq!(subcommand, "rename"),
+            result => panic!("unexpected result: {result:?}"),
         }
+        match FieldNames::parse("count", vec!["x".to_owned()]) {
+            Err(SubcommandError::Parse { subcommand, position: 0, .. }) => assert_eq!(subcommand, "count"),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[super::command]
+    fn r#type(text: String) -> Result<(), String> {
+        drop(text);
+        Ok(())
+    }
+
+    #[test]
+    fn raw_identifier_command() {
+        assert_eq!(BITBAR_TYPE_NAME, "type");
+        assert_eq!(r#type(&"x".to_owned()).unwrap().params, ["type", "x"]);
+    }
+
+    /// Deterministic pseudo-random input for the `Arbitrary` implementations (xorshift64), so failures are reproducible without a fuzzer.
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_menus(count: usize) -> impl Iterator<Item = Menu> {
+        use arbitrary::{
+            Arbitrary as _,
+            Unstructured,
+        };
+
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..count).filter_map(move |i| {
+            let bytes = (0..64 + i % 1024).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            }).collect::<Vec<_>>();
+            Menu::arbitrary(&mut Unstructured::new(&bytes)).ok()
+        })
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_menus_render() {
+        for menu in arbitrary_menus(1000) {
+            let rendered = menu.to_string();
+            assert_eq!(menu.display_for(Flavor::BitBar).to_string().is_empty(), rendered.is_empty());
+            let _ = menu.preview().to_string();
+            if menu.validate(Flavor::BitBar).is_empty() {
+                // each item (including alternates) is rendered as a single line
+                fn lines(items: &[MenuItem]) -> usize {
+                    items.iter().map(|item| match item {
+                        MenuItem::Content(ContentItem { extra: Some(Extra::Alternate(_)), .. }) => 2,
+                        MenuItem::Content(ContentItem { extra: Some(Extra::Submenu(sub)), .. }) => 1 + lines(&sub.0),
+                        MenuItem::Content(ContentItem { extra: None, .. }) => 1,
+                        MenuItem::Sep => 1,
+                    }).sum()
+                }
+                assert_eq!(rendered.lines().count(), lines(&menu.0), "{menu:?}");
+            }
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_menus_flatten() {
+        for mut menu in arbitrary_menus(1000) {
+            let depth = menu.max_depth();
+            menu.flatten_beyond(1);
+            assert!(menu.max_depth() <= 1.min(depth));
+        }
+    }
+
+    #[test]
+    fn skip_unchanged_on_click_frames() {
+        let swiftbar = SwiftBar::check_from(&BTreeMap::from([("SWIFTBAR_BUILD", "402")])).unwrap();
+        let frames = (0..3).map(|_| Menu(vec![
+            ContentItem::new("a").on_click(|| ()).unwrap().into(),
+            ContentItem::new("b").id("b").on_click(|| ()).unwrap().into(),
+        ]));
+        let mut buf = Vec::default();
+        BlockingStream::new(swiftbar, frames).skip_unchanged().write_main_output(&mut buf, None).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(output.matches("~~~").count(), 1, "{output}");
+        assert!(output.contains("param2=id:b"), "{output}");
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_menu_depth_is_bounded() {
+        use arbitrary::{
+            Arbitrary as _,
+            Unstructured,
+        };
+
+        // without a depth limit, this input nests submenus deeply enough to overflow the stack
+        let menu = Menu::arbitrary(&mut Unstructured::new(&[1, 8].repeat(1 << 16))).unwrap();
+        assert!(menu.max_depth() <= super::ARBITRARY_MAX_DEPTH);
     }
 }
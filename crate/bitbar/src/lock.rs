@@ -0,0 +1,128 @@
+//! Preventing overlapping refreshes of the same plugin.
+//!
+//! If generating the menu takes longer than the plugin's refresh interval, BitBar implementations start another instance of the plugin while the previous one is still running. With an [`InstanceLock`], the second instance can exit early instead of doing the same expensive work concurrently.
+//!
+//! This is usually done using `#[bitbar::main(single_instance)]` or `#[bitbar::main(single_instance = "cached")]`, see [`main`](macro@crate::main).
+
+use {
+    std::{
+        fs::{
+            self,
+            File,
+        },
+        io::{
+            self,
+            Write as _,
+        },
+        path::PathBuf,
+        process,
+        sync::atomic::{
+            AtomicBool,
+            Ordering,
+        },
+    },
+    rustix::{
+        fs::FlockOperation,
+        io::Errno,
+    },
+    crate::{
+        attr,
+        MainOutput,
+    },
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use {
+    futures::io::AllowStdIo,
+    crate::AsyncMainOutput,
+};
+
+/// Proof that no other instance of this plugin holds the lock. The lock is released when this is dropped or the process exits.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Returns the path to the lock file, `instance.lock` in the [plugin cache directory](crate::cache_dir).
+pub fn path() -> io::Result<PathBuf> {
+    Ok(crate::cache_dir()?.join("instance.lock"))
+}
+
+/// Returns the path to the file where the last menu is saved by `#[bitbar::main(single_instance = "cached")]`, `last-menu.txt` in the [plugin cache directory](crate::cache_dir).
+pub fn cached_menu_path() -> io::Result<PathBuf> {
+    Ok(crate::cache_dir()?.join("last-menu.txt"))
+}
+
+/// Tries to acquire the instance lock without blocking. Returns `Ok(None)` if another instance of this plugin is holding it.
+pub fn single_instance() -> io::Result<Option<InstanceLock>> {
+    let path = path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = File::create(path)?;
+    // not using File::try_lock, which would require Rust 1.89
+    match rustix::fs::flock(&file, FlockOperation::NonBlockingLockExclusive) {
+        Ok(()) => Ok(Some(InstanceLock { _file: file })),
+        Err(Errno::WOULDBLOCK) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the menu saved by the last instance using `#[bitbar::main(single_instance = "cached")]` to stdout. Does nothing if no menu has been saved.
+pub fn print_cached_menu() -> io::Result<()> {
+    let buf = match fs::read(cached_menu_path()?) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut stdout = crate::buffered_stdout();
+    stdout.write_all(&buf)?;
+    stdout.flush()
+}
+
+static SAVE_MENU: AtomicBool = AtomicBool::new(false);
+
+#[doc(hidden)] pub fn main_lock(cached: bool) -> Option<InstanceLock> { // used in proc macro
+    match single_instance() {
+        Ok(Some(lock)) => {
+            SAVE_MENU.store(cached, Ordering::SeqCst);
+            Some(lock)
+        }
+        Ok(None) => {
+            if cached {
+                if let Err(e) = print_cached_menu() {
+                    crate::notify_error(&format!("failed to print cached menu: {e}"), &format!("{e:?}"));
+                }
+            }
+            process::exit(0)
+        }
+        // run without the lock rather than not at all
+        Err(_) => None,
+    }
+}
+
+fn save_menu(buf: &[u8]) {
+    if let Err(e) = cached_menu_path().and_then(|path| crate::write_atomic(&path, buf)) {
+        crate::notify_error(&format!("failed to save menu: {e}"), &format!("{e:?}"));
+    }
+}
+
+#[doc(hidden)] pub fn main_output(output: impl MainOutput, error_template_image: Option<attr::Image>) { // used in proc macro
+    if !SAVE_MENU.load(Ordering::SeqCst) || crate::terminal_mode() { return output.main_output(error_template_image) }
+    let mut buf = Vec::default();
+    output.write_main_output(&mut buf, error_template_image).expect("failed to write menu to buffer");
+    save_menu(&buf);
+    let mut stdout = crate::buffered_stdout();
+    stdout.write_all(&buf).expect("failed to write menu to stdout");
+    stdout.flush().expect("failed to write menu to stdout");
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[doc(hidden)] pub async fn main_output_async<'a>(output: impl AsyncMainOutput<'a> + 'a, error_template_image: Option<attr::Image>) { // used in proc macro
+    if !SAVE_MENU.load(Ordering::SeqCst) || crate::terminal_mode() { return output.main_output(error_template_image).await }
+    let mut buf = AllowStdIo::new(Vec::default());
+    output.write_main_output(&mut buf, error_template_image).await.expect("failed to write menu to buffer");
+    let buf = buf.into_inner();
+    save_menu(&buf);
+    let mut stdout = crate::buffered_stdout();
+    stdout.write_all(&buf).expect("failed to write menu to stdout");
+    stdout.flush().expect("failed to write menu to stdout");
+}
@@ -0,0 +1,156 @@
+//! A [`log`] implementation for plugins, writing to a file in the plugin cache directory and optionally showing recent log lines in the menu.
+//!
+//! Log lines are appended to `plugin.log` in the [plugin cache directory](crate::cache_dir), which is rotated like the error log (see `on_error` in [`main`](macro@crate::main)). If the `BITBAR_DEBUG` environment variable is set, messages at the `debug` level are also logged, and [`debug_items`] returns a submenu showing the log lines from the current run.
+//!
+//! Events from the `tracing` crate can be logged by enabling its `log` feature.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{Menu, MenuItem, logging};
+//!
+//! #[bitbar::main]
+//! fn main() -> Menu {
+//!     let _ = logging::init();
+//!     log::info!("generating menu");
+//!     let mut menu = Menu(vec![MenuItem::new("Title")]);
+//!     menu.extend(logging::debug_items());
+//!     menu
+//! }
+//! ```
+
+use {
+    std::{
+        collections::VecDeque,
+        env,
+        fs::File,
+        io::{
+            self,
+            BufWriter,
+            Write as _,
+        },
+        sync::Mutex,
+    },
+    log::{
+        LevelFilter,
+        Log,
+        Metadata,
+        Record,
+        SetLoggerError,
+    },
+    crate::{
+        ContentItem,
+        MenuItem,
+    },
+};
+
+/// The number of recent log lines kept for [`debug_items`] by [`init`].
+pub const DEFAULT_MAX_LINES: usize = 50;
+
+/// The environment variable which enables debug logging and the debug submenu.
+pub const DEBUG_VAR: &str = "BITBAR_DEBUG";
+
+struct Logger {
+    file: Mutex<Option<LogFile>>,
+    recent: Mutex<VecDeque<String>>,
+    max_lines: usize,
+}
+
+/// The open log file along with its size, so that it only has to be reopened once it needs to be rotated.
+struct LogFile {
+    writer: BufWriter<File>,
+    len: u64,
+}
+
+impl LogFile {
+    fn open() -> io::Result<Self> {
+        let file = crate::open_log("plugin.log")?;
+        let len = file.metadata()?.len();
+        Ok(Self { writer: BufWriter::new(file), len })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let entry = format!("[{}] {line}\n", crate::log_timestamp());
+        self.writer.write_all(entry.as_bytes())?;
+        // the logger is never dropped, so lines must be flushed as they're written
+        self.writer.flush()?;
+        self.len += entry.len() as u64;
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) { return }
+        let line = format!("{} {}: {}", record.level(), record.target(), record.args());
+        let mut file = self.file.lock().expect("log file poisoned");
+        if file.is_none() { *file = LogFile::open().ok() }
+        if let Some(ref mut log_file) = *file {
+            // reopen the file for the next line to rotate it
+            if log_file.write_line(&line).is_err() || log_file.len > crate::MAX_LOG_SIZE { *file = None }
+        }
+        drop(file);
+        if self.max_lines > 0 {
+            let mut recent = self.recent.lock().expect("log buffer poisoned");
+            if recent.len() >= self.max_lines {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Mutex<Option<&'static Logger>> = Mutex::new(None);
+
+/// Returns whether the `BITBAR_DEBUG` environment variable is set to a non-empty value.
+pub fn debug_enabled() -> bool {
+    env::var_os(DEBUG_VAR).is_some_and(|value| !value.is_empty())
+}
+
+/// Installs the plugin logger as the global logger, keeping the last [`DEFAULT_MAX_LINES`] lines for [`debug_items`].
+///
+/// # Errors
+///
+/// If another global logger has already been installed.
+pub fn init() -> Result<(), SetLoggerError> {
+    init_with_max_lines(DEFAULT_MAX_LINES)
+}
+
+/// Installs the plugin logger as the global logger, keeping the given number of recent lines for [`debug_items`].
+///
+/// # Errors
+///
+/// If another global logger has already been installed.
+pub fn init_with_max_lines(max_lines: usize) -> Result<(), SetLoggerError> {
+    let logger = Box::leak(Box::new(Logger {
+        file: Mutex::default(),
+        recent: Mutex::default(),
+        max_lines,
+    }));
+    log::set_logger(logger)?;
+    log::set_max_level(if debug_enabled() { LevelFilter::Debug } else { LevelFilter::Info });
+    *LOGGER.lock().expect("logger registry poisoned") = Some(logger);
+    Ok(())
+}
+
+/// If the `BITBAR_DEBUG` environment variable is set and the logger has been [installed](init), returns a separator followed by a “Debug” item whose submenu shows the recent log lines. Otherwise, returns nothing.
+pub fn debug_items() -> Vec<MenuItem> {
+    if !debug_enabled() { return Vec::default() }
+    let Some(logger) = *LOGGER.lock().expect("logger registry poisoned") else { return Vec::default() };
+    let recent = logger.recent.lock().expect("log buffer poisoned");
+    let lines = if recent.is_empty() {
+        vec![MenuItem::new("(no log messages)")]
+    } else {
+        recent.iter().map(MenuItem::new).collect()
+    };
+    vec![
+        MenuItem::Sep,
+        ContentItem::new("Debug").sub(lines).into(),
+    ]
+}
@@ -0,0 +1,254 @@
+//! Reading back the [plugin metadata](https://github.com/swiftbar/SwiftBar#script-metadata) written by [`cargo-bitbar`](https://crates.io/crates/cargo-bitbar).
+
+use {
+    std::collections::BTreeMap,
+    crate::{
+        ContentItem,
+        Menu,
+        MenuItem,
+    },
+};
+#[cfg(feature = "metadata")] use {
+    std::{
+        env,
+        io,
+        path::Path,
+        sync::Arc,
+    },
+    base64::{
+        Engine as _,
+        engine::general_purpose::STANDARD as BASE64,
+    },
+    thiserror::Error,
+};
+
+/// The name of the extended attribute in which SwiftBar looks for the metadata of binary plugins.
+pub const XATTR_NAME: &str = "com.ameba.SwiftBar";
+
+/// The value of the `<swiftbar.type>` metadata item.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PluginKind {
+    /// A plugin that is run once per refresh.
+    #[default]
+    Default,
+    /// A plugin that [streams](https://github.com/swiftbar/SwiftBar#streamable) menus.
+    Streamable,
+}
+
+/// Plugin metadata, as written by `cargo bitbar meta`.
+///
+/// Metadata items are parsed from lines of the form `# <bitbar.title>My Plugin</bitbar.title>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// `<bitbar.title>`
+    pub title: Option<String>,
+    /// `<bitbar.version>`
+    pub version: Option<String>,
+    /// `<bitbar.author>`
+    pub author: Option<String>,
+    /// `<bitbar.author.github>`
+    pub author_github: Option<String>,
+    /// `<bitbar.desc>`
+    pub desc: Option<String>,
+    /// `<bitbar.image>`
+    pub image: Option<String>,
+    /// `<bitbar.dependencies>`
+    pub dependencies: Option<String>,
+    /// `<bitbar.abouturl>`
+    pub abouturl: Option<String>,
+    /// `<swiftbar.hideAbout>`
+    pub hide_about: bool,
+    /// `<swiftbar.hideRunInTerminal>`
+    pub hide_run_in_terminal: bool,
+    /// `<swiftbar.hideLastUpdated>`
+    pub hide_last_updated: bool,
+    /// `<swiftbar.hideDisablePlugin>`
+    pub hide_disable_plugin: bool,
+    /// `<swiftbar.hideSwiftBar>`
+    pub hide_swiftbar: bool,
+    /// `<swiftbar.schedule>`
+    pub schedule: Option<String>,
+    /// `<swiftbar.droptypes>`, the types of content which can be [dropped](crate::flavor::swiftbar::Dropped) onto the plugin.
+    pub droptypes: Vec<String>,
+    /// `<swiftbar.refreshOnOpen>`
+    pub refresh_on_open: bool,
+    /// `<swiftbar.runInBash>`, which defaults to `true`.
+    pub run_in_bash: bool,
+    /// `<swiftbar.type>`
+    pub kind: PluginKind,
+    /// `<swiftbar.useTrailingStreamSeparator>`
+    pub use_trailing_stream_separator: bool,
+    /// `<swiftbar.environment>`, mapping variable names to default values.
+    pub environment: BTreeMap<String, String>,
+    /// Any other metadata items, keyed by tag name.
+    pub other: BTreeMap<String, String>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            title: None,
+            version: None,
+            author: None,
+            author_github: None,
+            desc: None,
+            image: None,
+            dependencies: None,
+            abouturl: None,
+            hide_about: false,
+            hide_run_in_terminal: false,
+            hide_last_updated: false,
+            hide_disable_plugin: false,
+            hide_swiftbar: false,
+            schedule: None,
+            droptypes: Vec::default(),
+            refresh_on_open: false,
+            run_in_bash: true,
+            kind: PluginKind::Default,
+            use_trailing_stream_separator: false,
+            environment: BTreeMap::default(),
+            other: BTreeMap::default(),
+        }
+    }
+}
+
+impl Metadata {
+    /// Parses metadata from the decoded contents of the metadata attribute (or the comment header of a script plugin).
+    ///
+    /// Lines which are not metadata items are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut metadata = Self::default();
+        for line in text.lines() {
+            let Some((tag, value)) = parse_line(line) else { continue };
+            let value = value.to_owned();
+            match tag {
+                "bitbar.title" | "xbar.title" => metadata.title = Some(value),
+                "bitbar.version" | "xbar.version" => metadata.version = Some(value),
+                "bitbar.author" | "xbar.author" => metadata.author = Some(value),
+                "bitbar.author.github" | "xbar.author.github" => metadata.author_github = Some(value),
+                "bitbar.desc" | "xbar.desc" => metadata.desc = Some(value),
+                "bitbar.image" | "xbar.image" => metadata.image = Some(value),
+                "bitbar.dependencies" | "xbar.dependencies" => metadata.dependencies = Some(value),
+                "bitbar.abouturl" | "xbar.abouturl" => metadata.abouturl = Some(value),
+                "swiftbar.hideAbout" => metadata.hide_about = value == "true",
+                "swiftbar.hideRunInTerminal" => metadata.hide_run_in_terminal = value == "true",
+                "swiftbar.hideLastUpdated" => metadata.hide_last_updated = value == "true",
+                "swiftbar.hideDisablePlugin" => metadata.hide_disable_plugin = value == "true",
+                "swiftbar.hideSwiftBar" => metadata.hide_swiftbar = value == "true",
+                "swiftbar.schedule" => metadata.schedule = Some(value),
+                "swiftbar.droptypes" => metadata.droptypes = value.split(',').map(str::trim).filter(|droptype| !droptype.is_empty()).map(str::to_owned).collect(),
+                "swiftbar.refreshOnOpen" => metadata.refresh_on_open = value == "true",
+                "swiftbar.runInBash" => metadata.run_in_bash = value != "false",
+                "swiftbar.type" if value == "streamable" => metadata.kind = PluginKind::Streamable,
+                "swiftbar.useTrailingStreamSeparator" => metadata.use_trailing_stream_separator = value == "true",
+                "swiftbar.environment" => metadata.environment = value.trim_start_matches('[').trim_end_matches(']')
+                    .split(',')
+                    .filter_map(|var| {
+                        let (name, default_value) = var.split_once(':').unwrap_or((var, ""));
+                        let name = name.trim();
+                        (!name.is_empty()).then(|| (name.to_owned(), default_value.trim().to_owned()))
+                    })
+                    .collect(),
+                _ => { metadata.other.insert(tag.to_owned(), value); }
+            }
+        }
+        metadata
+    }
+
+    /// Decodes and parses the raw value of the metadata attribute, which is base64-encoded.
+    #[cfg(feature = "metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metadata")))]
+    pub fn from_xattr_value(value: &[u8]) -> Result<Self, MetadataError> {
+        let decoded = BASE64.decode(value.trim_ascii()).map_err(|e| MetadataError::Base64(Arc::new(e)))?;
+        let text = String::from_utf8(decoded).map_err(|_| MetadataError::NonUtf8)?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Reads the metadata attribute of the given binary.
+    #[cfg(feature = "metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metadata")))]
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, MetadataError> {
+        let value = xattr::get(path, XATTR_NAME).map_err(|e| MetadataError::Io(Arc::new(e)))?.ok_or(MetadataError::Missing)?;
+        Self::from_xattr_value(&value)
+    }
+
+    /// Reads the metadata attribute of the currently running plugin binary, as determined by [`std::env::current_exe`].
+    ///
+    /// This can be used to display the plugin's own version or about info, or to verify that metadata has been added at startup.
+    #[cfg(feature = "metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metadata")))]
+    pub fn current() -> Result<Self, MetadataError> {
+        Self::read(env::current_exe().map_err(|e| MetadataError::Io(Arc::new(e)))?)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start_matches(|c: char| c == '#' || c == '/' || c.is_whitespace());
+    let (tag, rest) = line.strip_prefix('<')?.split_once('>')?;
+    let value = rest.rfind("</").map_or(rest, |end| &rest[..end]);
+    Some((tag, value.trim()))
+}
+
+/// An error that can occur when reading the plugin's metadata.
+#[cfg(feature = "metadata")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metadata")))]
+#[derive(Debug, Error, Clone)]
+pub enum MetadataError {
+    /// The metadata attribute was not valid base64
+    #[error(transparent)] Base64(Arc<base64::DecodeError>),
+    /// An I/O error occurred while reading the metadata attribute
+    #[error(transparent)] Io(Arc<io::Error>),
+    /// The binary has no metadata attribute
+    #[error("the plugin binary has no metadata")]
+    Missing,
+    /// The decoded metadata was not valid UTF-8
+    #[error("plugin metadata is not valid UTF-8")]
+    NonUtf8,
+}
+
+#[cfg(feature = "metadata")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metadata")))]
+impl From<MetadataError> for Menu {
+    fn from(e: MetadataError) -> Menu {
+        let mut menu = vec![MenuItem::new("Error reading plugin metadata")];
+        match e {
+            MetadataError::Base64(e) => {
+                menu.push(MenuItem::new(format!("error decoding metadata: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            MetadataError::Io(e) => {
+                menu.push(MenuItem::new(format!("I/O error: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            MetadataError::Missing | MetadataError::NonUtf8 => menu.push(MenuItem::new(e)),
+        }
+        Menu(menu)
+    }
+}
+
+/// Renders the metadata as a menu, e.g. for an “About” submenu.
+impl From<Metadata> for Menu {
+    fn from(metadata: Metadata) -> Menu {
+        let Metadata { title, version, author, author_github, desc, abouturl, .. } = metadata;
+        let mut menu = Vec::default();
+        match (title, version) {
+            (Some(title), Some(version)) => menu.push(MenuItem::new(format!("{title} {version}"))),
+            (Some(text), None) | (None, Some(text)) => menu.push(MenuItem::new(text)),
+            (None, None) => {}
+        }
+        match (author, author_github) {
+            (Some(author), Some(github)) => menu.push(MenuItem::new(format!("by {author} (@{github})"))),
+            (Some(author), None) => menu.push(MenuItem::new(format!("by {author}"))),
+            (None, Some(github)) => menu.push(MenuItem::new(format!("by @{github}"))),
+            (None, None) => {}
+        }
+        if let Some(desc) = desc { menu.push(MenuItem::new(desc)) }
+        if let Some(abouturl) = abouturl {
+            menu.push(match ContentItem::new(&abouturl).href(&*abouturl) {
+                Ok(item) => item.into(),
+                Err(_) => MenuItem::new(abouturl),
+            });
+        }
+        Menu(menu)
+    }
+}
@@ -0,0 +1,148 @@
+//! A trait-based alternative to the [`main`](crate::main) and [`command`](crate::command) attributes.
+//!
+//! Implement [`Plugin`] for a type and call [`run`] from the `main` function. Since each part of the plugin is a regular method, they can be unit tested without running the plugin.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use {
+//!     std::{
+//!         fs,
+//!         io,
+//!         process::ExitCode,
+//!     },
+//!     bitbar::{
+//!         ContentItem,
+//!         Flavor,
+//!         MenuItem,
+//!         plugin::{
+//!             self,
+//!             Plugin,
+//!         },
+//!     },
+//! };
+//!
+//! struct Todo {
+//!     items: Vec<String>,
+//! }
+//!
+//! impl Plugin for Todo {
+//!     type Error = io::Error;
+//!
+//!     fn new(_: Flavor) -> io::Result<Self> {
+//!         Ok(Self { items: fs::read_to_string("todo.txt")?.lines().map(str::to_owned).collect() })
+//!     }
+//!
+//!     fn title(&self) -> MenuItem {
+//!         MenuItem::new(format!("{} to do", self.items.len()))
+//!     }
+//!
+//!     fn body(&self) -> io::Result<Vec<MenuItem>> {
+//!         self.items.iter().enumerate().map(|(idx, item)| Ok(ContentItem::new(item)
+//!             .command(plugin::command("done", [idx.to_string()])?).unwrap_or_else(|never| match never {})
+//!             .refresh()
+//!             .into()
+//!         )).collect()
+//!     }
+//!
+//!     fn on_command(&self, name: &str, args: Vec<String>) -> io::Result<()> {
+//!         match name {
+//!             "done" => {
+//!                 let idx = args[0].parse::<usize>().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+//!                 let remaining = self.items.iter().enumerate().filter(|&(i, _)| i != idx).map(|(_, item)| format!("{item}\n")).collect::<String>();
+//!                 fs::write("todo.txt", remaining)
+//!             }
+//!             _ => bitbar::plugin::unknown_command(name),
+//!         }
+//!     }
+//! }
+//!
+//! fn main() -> ExitCode {
+//!     plugin::run::<Todo>()
+//! }
+//! ```
+
+use {
+    std::{
+        fmt,
+        io,
+        process::ExitCode,
+    },
+    crate::{
+        CommandOutput as _,
+        Flavor,
+        MainOutput as _,
+        Menu,
+        MenuItem,
+        attr::Params,
+    },
+};
+
+/// A BitBar plugin, run using [`run`]. See the [module-level documentation](self) for an example.
+pub trait Plugin: Sized {
+    /// The error type of the hooks. Errors are displayed in the menu or, for commands, reported as notifications.
+    type Error: fmt::Debug + fmt::Display;
+
+    /// Initializes the plugin, e.g. by reading its configuration. This is called once on each run, both for generating the menu and for running a command.
+    fn new(flavor: Flavor) -> Result<Self, Self::Error>;
+
+    /// The item displayed in the menu bar. This is displayed even if [`body`](Self::body) returns an error.
+    fn title(&self) -> MenuItem;
+
+    /// The items in the menu, below the title and a separator.
+    fn body(&self) -> Result<Vec<MenuItem>, Self::Error>;
+
+    /// Handles a command created using [`command`], with the name and arguments passed to it.
+    ///
+    /// The default implementation reports every command as unknown using [`unknown_command`].
+    fn on_command(&self, name: &str, args: Vec<String>) -> Result<(), Self::Error> {
+        let _ = args;
+        unknown_command(name)
+    }
+}
+
+/// Returns a command which runs the plugin's [`on_command`](Plugin::on_command) hook with the given name and arguments. Returns an error if the path to the plugin binary is not valid UTF-8.
+pub fn command(name: impl ToString, args: impl IntoIterator<Item = impl ToString>) -> io::Result<Params> {
+    Ok(Params::new(crate::current_exe()?, [name.to_string()].into_iter().chain(args.into_iter().map(|arg| arg.to_string())).collect()))
+}
+
+/// Reports that a command with the given name doesn't exist and exits the plugin process. Can be used for the fallback case in [`Plugin::on_command`].
+pub fn unknown_command(name: &str) -> ! {
+    crate::unknown_subcommand(name)
+}
+
+/// Runs the plugin: generates the menu using the [`title`](Plugin::title) and [`body`](Plugin::body) hooks, or if the plugin was run with arguments, passes them to the [`on_command`](Plugin::on_command) hook.
+///
+/// Like the [`main`](crate::main) attribute, this supports the `--bitbar-preview` and `--bitbar-validate` flags, and renders the menu as a colored preview if run directly in a terminal.
+pub fn run<P: Plugin>() -> ExitCode {
+    let mut args = crate::args();
+    let _ = args.next().expect("missing program name");
+    let mut command = args.next();
+    if command.as_deref() == Some("--bitbar-preview") {
+        crate::set_preview_mode();
+        command = None;
+    } else if command.as_deref() == Some("--bitbar-validate") {
+        crate::set_validate_mode();
+        command = None;
+    } else if command.is_none() {
+        crate::detect_terminal_mode();
+    }
+    let flavor = Flavor::check();
+    if let Some(command) = command {
+        P::new(flavor).and_then(|plugin| plugin.on_command(&command, args.collect())).report(&command);
+    } else {
+        match P::new(flavor) {
+            Ok(plugin) => {
+                let mut items = vec![plugin.title(), MenuItem::Sep];
+                match plugin.body() {
+                    Ok(body) => items.extend(body),
+                    Err(e) => items.push(MenuItem::new(e)),
+                }
+                Menu(items).main_output(None);
+            }
+            Err(e) => Err::<Menu, _>(Menu(vec![MenuItem::new(e)])).main_output(None),
+        }
+    }
+    crate::join_background_threads();
+    crate::exit_code()
+}
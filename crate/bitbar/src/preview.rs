@@ -0,0 +1,98 @@
+//! A human-readable rendering of menus for inspecting plugin output in a terminal, e.g. in CI or during development.
+//!
+//! The generated `main` function prints this rendering instead of the normal plugin output when the plugin is run with `--bitbar-preview`, or with [colors](Preview::ansi_colors) when it's run directly in a terminal rather than by a BitBar implementation.
+
+use {
+    std::fmt,
+    crate::{
+        ContentItem,
+        Menu,
+        MenuItem,
+        attr::Extra,
+        flavor,
+    },
+};
+
+/// Parameter values longer than this many characters are truncated.
+const MAX_PARAM_LEN: usize = 40;
+
+/// Returned by [`Menu::preview`]. Renders the menu as a tree, with the parameters of each item annotated in brackets.
+///
+/// All parameters are shown, regardless of whether they are supported by the current [`Flavor`](crate::Flavor).
+pub struct Preview<'a> {
+    menu: &'a Menu,
+    ansi_colors: bool,
+}
+
+impl<'a> Preview<'a> {
+    pub(crate) fn new(menu: &'a Menu) -> Self {
+        Self { menu, ansi_colors: false }
+    }
+
+    /// Renders the text of each item in its [color](ContentItem::color) and the parameters dimmed, using ANSI escape codes. Only useful if the output is displayed in a terminal.
+    pub fn ansi_colors(mut self) -> Self {
+        self.ansi_colors = true;
+        self
+    }
+}
+
+impl fmt::Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render_menu(f, self.menu, "", true, self.ansi_colors)
+    }
+}
+
+fn render_menu(f: &mut fmt::Formatter<'_>, menu: &Menu, indent: &str, top_level: bool, ansi_colors: bool) -> fmt::Result {
+    for (idx, item) in menu.0.iter().enumerate() {
+        let is_last = idx == menu.0.len() - 1;
+        let (branch, continuation) = if top_level {
+            ("", "")
+        } else if is_last {
+            ("└─ ", "   ")
+        } else {
+            ("├─ ", "│  ")
+        };
+        match item {
+            MenuItem::Content(content) => {
+                write!(f, "{indent}{branch}")?;
+                render_item(f, content, false, ansi_colors)?;
+                let child_indent = format!("{indent}{continuation}");
+                match &content.extra {
+                    Some(Extra::Alternate(alt)) => {
+                        write!(f, "{child_indent}⌥ ")?;
+                        render_item(f, alt, true, ansi_colors)?;
+                    }
+                    Some(Extra::Submenu(sub)) => render_menu(f, sub, &child_indent, false, ansi_colors)?,
+                    None => {}
+                }
+            }
+            MenuItem::Sep => writeln!(f, "{indent}{branch}───")?,
+        }
+    }
+    Ok(())
+}
+
+fn render_item(f: &mut fmt::Formatter<'_>, item: &ContentItem, is_alt: bool, ansi_colors: bool) -> fmt::Result {
+    match item.color.as_ref().filter(|_| ansi_colors) {
+        Some(color) => {
+            let css_color_parser::Color { r, g, b, .. } = color.for_theme(flavor::dark_mode()).light;
+            write!(f, "\x1b[38;2;{r};{g};{b}m{}\x1b[0m", item.text.replace('\n', " "))?;
+        }
+        None => write!(f, "{}", item.text.replace('\n', " "))?,
+    }
+    let params = item.params(is_alt, None);
+    let mut params = params.iter().filter(|(name, _)| *name != "alternate").peekable();
+    if params.peek().is_some() {
+        write!(f, "  {}[", if ansi_colors { "\x1b[2m" } else { "" })?;
+        for (idx, (name, value)) in params.enumerate() {
+            if idx > 0 { write!(f, " ")?; }
+            if value.chars().count() > MAX_PARAM_LEN {
+                write!(f, "{name}={}…", value.chars().take(MAX_PARAM_LEN - 1).collect::<String>())?;
+            } else {
+                write!(f, "{name}={value}")?;
+            }
+        }
+        write!(f, "]{}", if ansi_colors { "\x1b[0m" } else { "" })?;
+    }
+    writeln!(f)
+}
@@ -0,0 +1,203 @@
+//! Retrying fallible operations like network requests with exponential backoff.
+//!
+//! A data source which occasionally fails, e.g. because of a brief network outage, can make the menu alternate between the data and an error. Retrying a few times before giving up avoids this without making the plugin take longer than its refresh interval.
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     std::time::Duration,
+//!     bitbar::retry::{
+//!         self,
+//!         Policy,
+//!     },
+//! };
+//!
+//! let mut remaining_failures = 2;
+//! let mut fetch = || if remaining_failures > 0 {
+//!     remaining_failures -= 1;
+//!     Err("connection reset")
+//! } else {
+//!     Ok(42)
+//! };
+//! let policy = Policy::default().initial_delay(Duration::from_millis(1));
+//! assert_eq!(retry::retry(&policy, &mut fetch), Ok(42));
+//!
+//! let error = retry::retry(&policy.attempts(1), || Err::<(), _>("connection reset")).unwrap_err();
+//! assert_eq!(error.to_string(), "connection reset (after 1 attempt)");
+//! ```
+
+use {
+    std::{
+        fmt,
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    crate::schedule::Interval,
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use std::future::Future;
+
+/// How often and how long to retry an operation.
+///
+/// After each failed attempt, [`retry`] waits before trying again, starting with the [initial delay](Self::initial_delay) and doubling it each time up to the [maximum delay](Self::max_delay). It gives up after the [maximum number of attempts](Self::attempts), or once waiting for the next attempt would exceed the [time budget](Self::budget).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    attempts: usize,
+    initial_delay: Duration,
+    max_delay: Duration,
+    budget: Option<Duration>,
+}
+
+impl Policy {
+    /// Sets the maximum number of attempts, including the first one. Defaults to 3. Values less than 1 are treated as 1.
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Sets how long to wait after the first failed attempt. Defaults to 500 milliseconds.
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Sets the longest time to wait between two attempts. Defaults to 10 seconds.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the total time after which no more attempts are started, measured from the start of the first attempt, or `None` for no limit.
+    ///
+    /// Defaults to half of the [refresh interval of the running plugin](Interval::current), so that retries don't delay the next refresh, or no limit if the interval is unknown.
+    pub fn budget(mut self, budget: impl Into<Option<Duration>>) -> Self {
+        self.budget = budget.into();
+        self
+    }
+
+    /// The delay before the attempt after the given number of failed attempts, if another attempt should be made given the time elapsed since the first one.
+    fn delay(&self, failed_attempts: usize, elapsed: Duration) -> Option<Duration> {
+        if failed_attempts >= self.attempts { return None }
+        let delay = self.initial_delay.saturating_mul(2u32.saturating_pow(u32::try_from(failed_attempts - 1).unwrap_or(u32::MAX))).min(self.max_delay);
+        if self.budget.is_some_and(|budget| elapsed.checked_add(delay).is_none_or(|end| end > budget)) { return None }
+        Some(delay)
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            budget: Interval::current().map(|Interval(interval)| interval / 2),
+        }
+    }
+}
+
+/// The error returned by the last attempt of a retried operation, along with the number of attempts made.
+///
+/// Its `Display` implementation includes the number of attempts, so it can be shown in the menu as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryError<E> {
+    /// The error returned by the last attempt.
+    pub error: E,
+    /// How many times the operation was attempted.
+    pub attempts: usize,
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (after {} attempt{})", self.error, self.attempts, if self.attempts == 1 { "" } else { "s" })
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RetryError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Calls `f` until it succeeds or the policy says to give up, waiting between attempts.
+pub fn retry<T, E>(policy: &Policy, mut f: impl FnMut() -> Result<T, E>) -> Result<T, RetryError<E>> {
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(error) => match policy.delay(attempts, start.elapsed()) {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(RetryError { error, attempts }),
+            },
+        }
+    }
+}
+
+/// Like [`retry`], but for an operation returning a future. Waiting between attempts doesn't block the async runtime.
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+pub async fn retry_async<T, E, Fut: Future<Output = Result<T, E>>>(policy: &Policy, mut f: impl FnMut() -> Fut) -> Result<T, RetryError<E>> {
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(error) => match policy.delay(attempts, start.elapsed()) {
+                Some(delay) => crate::sleep(delay).await,
+                None => return Err(RetryError { error, attempts }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::time::Duration,
+        super::Policy,
+    };
+
+    fn policy() -> Policy {
+        Policy::default().attempts(5).initial_delay(Duration::from_secs(1)).max_delay(Duration::from_secs(5)).budget(None)
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = policy();
+        assert_eq!(policy.delay(1, Duration::ZERO), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(2, Duration::ZERO), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(3, Duration::ZERO), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(4, Duration::ZERO), Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(5, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn no_overflow() {
+        let policy = policy().attempts(usize::MAX);
+        assert_eq!(policy.delay(100, Duration::ZERO), Some(Duration::from_secs(5)));
+        let policy = policy.max_delay(Duration::MAX);
+        assert!(policy.delay(usize::MAX - 1, Duration::ZERO).is_some());
+        let policy = policy.budget(Duration::from_secs(10));
+        assert_eq!(policy.delay(usize::MAX - 1, Duration::from_secs(1)), None);
+        assert_eq!(policy.delay(1, Duration::MAX), None);
+    }
+
+    #[test]
+    fn budget() {
+        let policy = policy().budget(Duration::from_secs(10));
+        assert_eq!(policy.delay(1, Duration::from_secs(9)), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(1, Duration::from_secs(10)), None);
+        assert_eq!(policy.delay(3, Duration::from_secs(6)), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(3, Duration::from_secs(7)), None);
+    }
+
+    #[test]
+    fn at_least_one_attempt() {
+        assert_eq!(policy().attempts(0).delay(1, Duration::ZERO), None);
+    }
+}
@@ -0,0 +1,258 @@
+//! Deciding when a plugin needs to be refreshed, for time-sensitive plugins like calendars or timers.
+//!
+//! A [`Schedule`] compares the plugin's refresh interval with the times at which the displayed data goes out of date (e.g. from `time::Relative::stale_in` with the `chrono` feature). It can then be used to make items refresh the plugin when clicked if they may be out of date, and to recommend a refresh interval which keeps the menu accurate without refreshing more often than necessary.
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     std::time::Duration,
+//!     bitbar::{
+//!         ContentItem,
+//!         schedule::{
+//!             Interval,
+//!             Schedule,
+//!         },
+//!     },
+//! };
+//!
+//! let schedule = Schedule::new(Interval::from_file_name("calendar.5m"))
+//!     .deadline(Duration::from_secs(90))
+//!     .tolerance(Duration::from_secs(30));
+//! assert!(schedule.is_late());
+//! assert!(schedule.apply(ContentItem::new("Meeting in 1 min")).refresh);
+//! assert_eq!(schedule.recommended_interval().to_string(), "2m");
+//! ```
+
+use {
+    std::{
+        env,
+        fmt,
+        path::Path,
+        time::Duration,
+    },
+    crate::ContentItem,
+};
+
+const UNITS: [(u64, char); 4] = [
+    (24 * 60 * 60, 'd'),
+    (60 * 60, 'h'),
+    (60, 'm'),
+    (1, 's'),
+];
+
+/// A refresh interval as specified in a plugin's file name, e.g. `5m` in `my-plugin.5m`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval(pub Duration);
+
+impl Interval {
+    /// Parses an interval like `30s`, `5m`, `1h`, or `2d`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let unit = s.chars().last()?;
+        let (secs, _) = UNITS.into_iter().find(|&(_, name)| name == unit)?;
+        let n = s[..s.len() - 1].parse::<u64>().ok().filter(|&n| n > 0)?;
+        Some(Self(Duration::from_secs(n.checked_mul(secs)?)))
+    }
+
+    /// Parses the interval from a plugin file name like `my-plugin.5m` or `my-plugin.5m.sh`.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        name.split('.').skip(1).filter_map(Self::parse).last()
+    }
+
+    /// Parses the interval from a [`<swiftbar.schedule>`](https://github.com/swiftbar/SwiftBar#script-metadata) cron expression, if it describes a regular interval.
+    ///
+    /// Only expressions of the form `* * * * *`, `*/N * * * *` (every `N` minutes), and `0 */N * * *` (every `N` hours) are recognized.
+    pub fn from_cron(schedule: &str) -> Option<Self> {
+        let fields = schedule.split_whitespace().collect::<Vec<_>>();
+        let step = |field: &str| if field == "*" { Some(1) } else { field.strip_prefix("*/")?.parse::<u64>().ok().filter(|&n| n > 0) };
+        match fields[..] {
+            [minute, "*", "*", "*", "*"] => step(minute).and_then(|n| n.checked_mul(60)).map(|secs| Self(Duration::from_secs(secs))),
+            ["0", hour, "*", "*", "*"] => step(hour).and_then(|n| n.checked_mul(60 * 60)).map(|secs| Self(Duration::from_secs(secs))),
+            _ => None,
+        }
+    }
+
+    /// Reads the interval from the file name of the running plugin, using the `SWIFTBAR_PLUGIN_PATH` environment variable if set and the path of the executable otherwise.
+    ///
+    /// Returns `None` if the file name doesn't include an interval, e.g. because the plugin uses a `<swiftbar.schedule>` instead. In that case, [`Interval::from_cron`] can be used with the schedule from the plugin's metadata.
+    pub fn current() -> Option<Self> {
+        let path = env::var_os("SWIFTBAR_PLUGIN_PATH").map(Into::into).or_else(|| env::current_exe().ok())?;
+        Self::from_file_name(Path::new(&path).file_name()?.to_str()?)
+    }
+
+    /// Returns the largest interval of at most `max` which can be written in a plugin file name, i.e. a whole number of seconds, minutes, hours, or days. Intervals are at least 1 second.
+    pub fn at_most(max: Duration) -> Self {
+        let secs = max.as_secs().max(1);
+        let (unit, _) = UNITS.into_iter().find(|&(unit, _)| secs >= unit).unwrap_or((1, 's'));
+        Self(Duration::from_secs(secs / unit * unit))
+    }
+
+    /// A `<swiftbar.schedule>` cron expression for this interval, if there is one. Cron expressions can't describe intervals of less than a minute, or intervals which don't evenly divide an hour or a day.
+    pub fn cron(&self) -> Option<String> {
+        let secs = self.0.as_secs();
+        if self.0.subsec_nanos() != 0 || secs == 0 || !secs.is_multiple_of(60) { return None }
+        let minutes = secs / 60;
+        if minutes == 1 {
+            Some("* * * * *".to_owned())
+        } else if minutes < 60 && 60 % minutes == 0 {
+            Some(format!("*/{minutes} * * * *"))
+        } else if minutes.is_multiple_of(60) && 24 % (minutes / 60) == 0 {
+            Some(format!("0 */{} * * *", minutes / 60))
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats the interval as it would appear in a plugin file name, e.g. `5m`, using the largest unit which divides it evenly.
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs().max(1);
+        let (unit, name) = UNITS.into_iter().find(|&(unit, _)| secs.is_multiple_of(unit)).unwrap_or((1, 's'));
+        write!(f, "{}{name}", secs / unit)
+    }
+}
+
+/// The refresh interval of a plugin along with the times at which its displayed data goes out of date.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    interval: Option<Interval>,
+    deadlines: Vec<Duration>,
+    tolerance: Duration,
+}
+
+impl Schedule {
+    /// Creates a schedule for a plugin with the given refresh interval, or no regular refreshes if `None`.
+    pub fn new(interval: impl Into<Option<Interval>>) -> Self {
+        Self {
+            interval: interval.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a schedule using the [interval of the running plugin](Interval::current).
+    pub fn current() -> Self {
+        Self::new(Interval::current())
+    }
+
+    /// Adds a time, relative to now, at which some of the displayed data goes out of date.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadlines.push(deadline);
+        self
+    }
+
+    /// Adds times, relative to now, at which some of the displayed data goes out of date.
+    pub fn deadlines(mut self, deadlines: impl IntoIterator<Item = Duration>) -> Self {
+        self.deadlines.extend(deadlines);
+        self
+    }
+
+    /// Sets how long data may be displayed after it went out of date. Defaults to zero.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// The earliest deadline, if any.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.deadlines.iter().copied().min()
+    }
+
+    /// Whether some data will be out of date for longer than the tolerance before the next scheduled refresh.
+    pub fn is_late(&self) -> bool {
+        self.next_deadline().is_some_and(|deadline| self.interval.is_none_or(|Interval(interval)| deadline + self.tolerance < interval))
+    }
+
+    /// Makes the given item refresh the plugin when clicked if [some data will be out of date](Self::is_late) before the next scheduled refresh.
+    pub fn apply(&self, item: ContentItem) -> ContentItem {
+        if self.is_late() { item.refresh() } else { item }
+    }
+
+    /// The longest refresh interval which keeps every deadline within the tolerance, at most the plugin's current interval (or 1 day if there is neither an interval nor any deadline).
+    ///
+    /// This considers the gaps between consecutive deadlines, since the plugin has to be refreshed after each of them.
+    pub fn recommended_interval(&self) -> Interval {
+        let mut deadlines = self.deadlines.clone();
+        deadlines.sort();
+        let mut max = self.interval.map(|Interval(interval)| interval);
+        let mut prev = Duration::ZERO;
+        for deadline in deadlines {
+            let gap = deadline.saturating_sub(prev) + self.tolerance;
+            if gap > Duration::ZERO { max = Some(max.map_or(gap, |max| max.min(gap))) }
+            prev = deadline;
+        }
+        match max {
+            Some(max) => Interval::at_most(max),
+            None => Interval(Duration::from_secs(24 * 60 * 60)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::time::Duration,
+        super::{
+            Interval,
+            Schedule,
+        },
+    };
+
+    fn secs(n: u64) -> Interval { Interval(Duration::from_secs(n)) }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Interval::parse("30s"), Some(secs(30)));
+        assert_eq!(Interval::parse("5m"), Some(secs(300)));
+        assert_eq!(Interval::parse("2d"), Some(secs(2 * 24 * 60 * 60)));
+        assert_eq!(Interval::parse("0m"), None);
+        assert_eq!(Interval::parse("m"), None);
+        assert_eq!(Interval::parse("5x"), None);
+        assert_eq!(Interval::from_file_name("calendar.5m.sh"), Some(secs(300)));
+        assert_eq!(Interval::from_file_name("calendar.sh"), None);
+    }
+
+    #[test]
+    fn cron() {
+        assert_eq!(Interval::from_cron("* * * * *"), Some(secs(60)));
+        assert_eq!(Interval::from_cron("*/15 * * * *"), Some(secs(15 * 60)));
+        assert_eq!(Interval::from_cron("0 */2 * * *"), Some(secs(2 * 60 * 60)));
+        assert_eq!(Interval::from_cron("5 * * * *"), None);
+        assert_eq!(Interval::from_cron("*/0 * * * *"), None);
+        assert_eq!(Interval::from_cron("*/307445734561825861 * * * *"), None);
+        assert_eq!(Interval::from_cron("0 */5124095576030432 * * *"), None);
+        for interval in [secs(60), secs(15 * 60), secs(2 * 60 * 60)] {
+            assert_eq!(Interval::from_cron(&interval.cron().unwrap()), Some(interval));
+        }
+        assert_eq!(secs(30).cron(), None);
+        assert_eq!(secs(7 * 60).cron(), None);
+        assert_eq!(secs(5 * 60 * 60).cron(), None);
+    }
+
+    #[test]
+    fn at_most_and_display() {
+        assert_eq!(Interval::at_most(Duration::ZERO), secs(1));
+        assert_eq!(Interval::at_most(Duration::from_secs(90)), secs(60));
+        assert_eq!(Interval::at_most(Duration::from_secs(25 * 60 * 60)), secs(24 * 60 * 60));
+        assert_eq!(secs(120).to_string(), "2m");
+        assert_eq!(secs(90).to_string(), "90s");
+        assert_eq!(secs(24 * 60 * 60).to_string(), "1d");
+    }
+
+    #[test]
+    fn lateness() {
+        assert!(!Schedule::new(None).is_late());
+        assert!(Schedule::new(None).deadline(Duration::from_secs(60)).is_late());
+        let schedule = Schedule::new(secs(300)).deadline(Duration::from_secs(600));
+        assert!(!schedule.is_late());
+        assert_eq!(schedule.recommended_interval(), secs(300));
+        assert!(!Schedule::new(secs(300)).deadline(Duration::from_secs(240)).tolerance(Duration::from_secs(60)).is_late());
+    }
+
+    #[test]
+    fn recommended_interval() {
+        assert_eq!(Schedule::new(None).recommended_interval(), secs(24 * 60 * 60));
+        assert_eq!(Schedule::new(secs(300)).deadlines([Duration::from_secs(180), Duration::from_secs(60)]).recommended_interval(), secs(60));
+        assert_eq!(Schedule::new(None).deadline(Duration::from_secs(3 * 60 * 60)).recommended_interval(), secs(3 * 60 * 60));
+    }
+}
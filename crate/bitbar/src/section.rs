@@ -0,0 +1,148 @@
+//! Menus made up of independent sections, e.g. for dashboard-style plugins which display data from several sources.
+//!
+//! With the `tokio`, `async-std`, or `futures-executor` feature, [`Sources`] fetches the data for all sections concurrently, so that a slow or failing source only affects its own section.
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     std::time::Duration,
+//!     bitbar::{
+//!         Menu,
+//!         MenuItem,
+//!         section::Sources,
+//!     },
+//! };
+//!
+//! async fn fetch_weather() -> Result<Vec<MenuItem>, std::io::Error> {
+//!     Ok(vec![MenuItem::new("Sunny, 21 °C")])
+//! }
+//!
+//! async fn fetch_news() -> Result<Vec<MenuItem>, std::io::Error> {
+//!     Err(std::io::Error::other("server unavailable"))
+//! }
+//!
+//! #[bitbar::main]
+//! async fn main() -> Menu {
+//!     let mut menu = Menu(vec![MenuItem::new("Dashboard")]);
+//!     menu.extend(Sources::new()
+//!         .add("Weather", Duration::from_secs(5), fetch_weather())
+//!         .add("News", Duration::from_secs(5), fetch_news())
+//!         .fetch().await);
+//!     menu
+//! }
+//! ```
+
+use {
+    std::fmt,
+    crate::{
+        ContentItem,
+        MenuItem,
+        attr::Color,
+    },
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use {
+    std::{
+        future::Future,
+        pin::Pin,
+        time::Duration,
+    },
+    crate::Menu,
+};
+
+/// A part of a menu, rendered as a separator followed by an optional title and the section's items.
+#[derive(Debug, Default)]
+pub struct Section {
+    /// The title of this section, displayed as the first item.
+    pub title: Option<String>,
+    /// The items in this section.
+    pub items: Vec<MenuItem>,
+}
+
+impl Section {
+    /// Creates a section with the given title and items.
+    pub fn new<I: IntoIterator>(title: impl ToString, items: I) -> Self
+    where I::Item: Into<MenuItem> {
+        Self {
+            title: Some(title.to_string()),
+            items: items.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Creates a section with the given title which shows an error instead of its items.
+    pub fn error(title: impl ToString, e: &(impl fmt::Debug + fmt::Display)) -> Self {
+        let error = ContentItem::new(format!("error: {e}"))
+            .color(ERROR_COLOR).unwrap_or_else(|never| match never {})
+            .sub([MenuItem::new(format!("{e:?}"))]);
+        Self {
+            title: Some(title.to_string()),
+            items: vec![error.into()],
+        }
+    }
+}
+
+impl IntoIterator for Section {
+    type Item = MenuItem;
+    type IntoIter = std::iter::Chain<std::iter::Chain<std::iter::Once<MenuItem>, std::option::IntoIter<MenuItem>>, std::vec::IntoIter<MenuItem>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(MenuItem::Sep)
+            .chain(self.title.map(MenuItem::new))
+            .chain(self.items)
+    }
+}
+
+const ERROR_COLOR: Color = Color { light: css_color_parser::Color { r: 0xff, g: 0x33, b: 0x33, a: 1.0 }, dark: None };
+
+/// The error displayed in a section whose source took longer than its timeout.
+#[derive(Debug, Clone, Copy)]
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+pub struct SourceTimeout(pub Duration);
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+impl fmt::Display for SourceTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out after {:?}", self.0)
+    }
+}
+
+/// A set of data sources which are fetched concurrently, each producing one [`Section`].
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+#[derive(Default)]
+pub struct Sources<'a> {
+    sources: Vec<Pin<Box<dyn Future<Output = Section> + 'a>>>,
+}
+
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+impl<'a> Sources<'a> {
+    /// Creates an empty set of sources.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source. If `fetch` returns an error or doesn't finish within `timeout`, the section shows the error instead of its items.
+    pub fn add<T: IntoIterator, E: fmt::Debug + fmt::Display>(mut self, title: impl ToString, timeout: Duration, fetch: impl Future<Output = Result<T, E>> + 'a) -> Self
+    where T::Item: Into<MenuItem> {
+        let title = title.to_string();
+        self.sources.push(Box::pin(async move {
+            match crate::timeout(timeout, fetch).await {
+                Some(Ok(items)) => Section::new(title, items),
+                Some(Err(e)) => Section::error(title, &e),
+                None => Section::error(title, &SourceTimeout(timeout)),
+            }
+        }));
+        self
+    }
+
+    /// Fetches all sources concurrently and returns the sections in the order in which they were added.
+    pub async fn fetch_sections(self) -> Vec<Section> {
+        futures::future::join_all(self.sources).await
+    }
+
+    /// Fetches all sources concurrently and returns a menu with all sections in the order in which they were added.
+    pub async fn fetch(self) -> Menu {
+        self.fetch_sections().await.into_iter().flatten().collect()
+    }
+}
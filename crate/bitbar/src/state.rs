@@ -0,0 +1,222 @@
+//! Persistent plugin state, such as toggles, counters, and “last seen” markers.
+//!
+//! State is stored as JSON in the [plugin data directory](crate::data_dir), along with a version number so that older state files can be [migrated](State::migrate) when the state type changes.
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     serde::{
+//!         Deserialize,
+//!         Serialize,
+//!     },
+//!     bitbar::{
+//!         Menu,
+//!         MenuItem,
+//!         state::{
+//!             self,
+//!             State,
+//!             Store,
+//!         },
+//!     },
+//! };
+//!
+//! #[derive(Default, Serialize, Deserialize)]
+//! struct Counter {
+//!     runs: u64,
+//! }
+//!
+//! impl State for Counter {}
+//!
+//! #[bitbar::main]
+//! fn main() -> Result<Menu, state::Error> {
+//!     let mut counter = Store::<Counter>::load()?;
+//!     counter.runs += 1;
+//!     counter.save()?;
+//!     Ok(Menu(vec![MenuItem::new(format!("run {} times", counter.runs))]))
+//! }
+//! ```
+
+use {
+    std::{
+        fs,
+        io,
+        ops::{
+            Deref,
+            DerefMut,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+        sync::Arc,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+        de::DeserializeOwned,
+    },
+    serde_json::Value,
+    thiserror::Error,
+    crate::{
+        Menu,
+        MenuItem,
+    },
+};
+
+/// A type which can be persisted using a [`Store`].
+pub trait State: Default + Serialize + DeserializeOwned {
+    /// The version of the state format, written to the state file along with the state.
+    ///
+    /// Increase this when changing the type in a way that isn't backwards compatible, and handle older versions in [`migrate`](Self::migrate).
+    const VERSION: u32 = 0;
+
+    /// Converts state saved with an older [`VERSION`](Self::VERSION) to the current type.
+    ///
+    /// The default implementation deserializes the old state as if it had the current version.
+    fn migrate(version: u32, state: Value) -> Result<Self, Error> {
+        let _ = version;
+        Ok(serde_json::from_value(state)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateFile<T> {
+    version: u32,
+    state: T,
+}
+
+/// An error that can occur when loading or saving plugin state.
+#[derive(Debug, Error, Clone)]
+pub enum Error {
+    /// An I/O error occurred while reading or writing the state file
+    #[error(transparent)] Io(Arc<io::Error>),
+    /// The state file could not be parsed, or the state could not be serialized
+    #[error(transparent)] Json(Arc<serde_json::Error>),
+    /// The state file was written by a newer version of the plugin
+    #[error("state file has version {found}, but this plugin only supports up to version {supported}")]
+    NewerVersion {
+        /// The version found in the state file
+        found: u32,
+        /// The current [`State::VERSION`]
+        supported: u32,
+    },
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(Arc::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(Arc::new(e))
+    }
+}
+
+impl From<Error> for Menu {
+    fn from(e: Error) -> Menu {
+        let mut menu = vec![MenuItem::new("Error in plugin state")];
+        match e {
+            Error::Io(e) => {
+                menu.push(MenuItem::new(format!("I/O error: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::Json(e) => {
+                menu.push(MenuItem::new(format!("error in state file: {}", e)));
+                menu.push(MenuItem::new(format!("{:?}", e)));
+            }
+            Error::NewerVersion { .. } => menu.push(MenuItem::new(e)),
+        }
+        Menu(menu)
+    }
+}
+
+/// Returns the path to the default state file, `state.json` in the [plugin data directory](crate::data_dir).
+pub fn path() -> Result<PathBuf, Error> {
+    Ok(crate::data_dir()?.join("state.json"))
+}
+
+/// Plugin state loaded from a file, which can be modified via [`DerefMut`] and written back using [`save`](Self::save).
+#[derive(Debug)]
+pub struct Store<T: State> {
+    path: PathBuf,
+    state: T,
+}
+
+impl<T: State> Store<T> {
+    /// Loads the state from the [default state file](path), or uses the [`Default`] value if it doesn't exist.
+    pub fn load() -> Result<Self, Error> {
+        Self::load_from(path()?)
+    }
+
+    /// Loads the state from a file named `{name}.json` in the [plugin data directory](crate::data_dir), or uses the [`Default`] value if it doesn't exist.
+    ///
+    /// This can be used to keep multiple independent stores.
+    pub fn load_named(name: &str) -> Result<Self, Error> {
+        Self::load_from(crate::data_dir()?.join(format!("{name}.json")))
+    }
+
+    /// Loads the state from the given file, or uses the [`Default`] value if it doesn't exist.
+    pub fn load_from(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let state = match fs::read(&path) {
+            Ok(buf) => {
+                let StateFile { version, state } = serde_json::from_slice::<StateFile<Value>>(&buf)?;
+                if version > T::VERSION {
+                    return Err(Error::NewerVersion { found: version, supported: T::VERSION })
+                } else if version < T::VERSION {
+                    T::migrate(version, state)?
+                } else {
+                    serde_json::from_value(state)?
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => T::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, state })
+    }
+
+    /// The path to the state file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the state back to the file.
+    ///
+    /// The state is first written to a temporary file in the same directory, which then replaces the state file, so a crash while saving doesn't leave behind a partially written file.
+    pub fn save(&self) -> Result<(), Error> {
+        let mut buf = serde_json::to_vec_pretty(&StateFile { version: T::VERSION, state: &self.state })?;
+        buf.push(b'\n');
+        crate::write_atomic(&self.path, &buf)?;
+        Ok(())
+    }
+
+    /// Loads the state from the [default state file](path), modifies it using the given function, and saves it.
+    pub fn update<R>(f: impl FnOnce(&mut T) -> R) -> Result<R, Error> {
+        let mut store = Self::load()?;
+        let output = f(&mut store);
+        store.save()?;
+        Ok(output)
+    }
+
+    /// Returns the state, discarding the path to the state file.
+    pub fn into_inner(self) -> T {
+        self.state
+    }
+}
+
+impl<T: State> Deref for Store<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.state
+    }
+}
+
+impl<T: State> DerefMut for Store<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.state
+    }
+}
@@ -0,0 +1,271 @@
+//! Helpers for testing plugin code which depends on the BitBar implementation it's running on.
+//!
+//! [`Flavor::check`] and [`flavor::dark_mode`] normally cache their results for the lifetime of the process and read the real environment. The functions in this module instead run a closure in a fake environment, setting the environment variables used for detection and making [`Flavor::check`], [`SwiftBar::check`], and [`flavor::dark_mode`] return the corresponding values. The previous environment is restored afterwards, even if the closure panics.
+//!
+//! Since environment variables are shared by all threads of a process, fake environments are serialized: if another thread is running a closure in a fake environment, these functions wait until it's done. Code outside of a fake environment which checks the flavor or reads environment variables concurrently may observe the fake environment. Fake environments must not be nested.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{
+//!     Flavor,
+//!     flavor::SwiftBar,
+//!     test,
+//! };
+//!
+//! fn status() -> &'static str {
+//!     if SwiftBar::check().is_some() { "SwiftBar" } else { "other" }
+//! }
+//!
+//! assert_eq!(test::with_swiftbar(402, status), "SwiftBar");
+//! assert_eq!(test::with_xbar(status), "other");
+//! assert!(matches!(test::with_bitbar(Flavor::check), Flavor::BitBar));
+//! assert!(test::FakeEnv::xbar().dark_mode(true).run(bitbar::flavor::dark_mode));
+//! assert!(!test::with_swiftbar(402, bitbar::flavor::dark_mode));
+//! ```
+//!
+//! # Snapshots
+//!
+//! The [`assert_menu_snapshot`](crate::assert_menu_snapshot) macro compares the rendered output of a menu against a golden file checked into the plugin's repository:
+//!
+//! ```rust,no_run
+//! use bitbar::{
+//!     Flavor,
+//!     Menu,
+//!     MenuItem,
+//!     assert_menu_snapshot,
+//! };
+//!
+//! let menu = Menu(vec![MenuItem::new("Hello"), MenuItem::Sep, MenuItem::new("World")]);
+//! // compares against tests/snapshots/hello.txt in the package directory
+//! assert_menu_snapshot!("hello", menu);
+//! // renders the menu for a specific flavor first
+//! assert_menu_snapshot!("hello-bitbar", menu, Flavor::BitBar);
+//! ```
+//!
+//! To create or update golden files, run the tests with the `BITBAR_UPDATE_SNAPSHOTS` environment variable set to `1`.
+
+use {
+    std::{
+        collections::BTreeMap,
+        env,
+        ffi::OsString,
+        fmt::Write as _,
+        fs,
+        path::Path,
+        sync::{
+            Mutex,
+            PoisonError,
+        },
+    },
+    crate::flavor::{
+        self,
+        Flavor,
+        ProcessEnv,
+    },
+};
+#[cfg(doc)] use crate::flavor::SwiftBar;
+
+/// The environment variables set by BitBar implementations which affect the behavior of this crate.
+const DETECTION_VARS: [&str; 11] = [
+    "BitBar",
+    "BitBarDarkMode",
+    "XBARDarkMode",
+    "OS_APPEARANCE",
+    "SWIFTBAR",
+    "SWIFTBAR_BUILD",
+    "SWIFTBAR_VERSION",
+    "SWIFTBAR_PLUGIN_PATH",
+    "SWIFTBAR_PLUGIN_CACHE_PATH",
+    "SWIFTBAR_PLUGIN_DATA_PATH",
+    "SWIFTBAR_PLUGINS_PATH",
+];
+
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// A fake environment in which a closure can be [run](Self::run).
+#[derive(Debug, Clone, Default)]
+pub struct FakeEnv {
+    vars: BTreeMap<String, Option<String>>,
+}
+
+impl FakeEnv {
+    /// An environment without any of the variables set by BitBar implementations, as if the plugin was run on its own. This is detected as [`Flavor::BitBar`].
+    fn empty() -> Self {
+        Self {
+            vars: DETECTION_VARS.into_iter().map(|key| (key.to_owned(), None)).collect(),
+        }
+    }
+
+    /// An environment as set up by the original BitBar, which is detected as [`Flavor::BitBar`]. The system is in light mode unless changed using [`FakeEnv::dark_mode`].
+    pub fn bitbar() -> Self {
+        Self::empty().var("BitBar", "1")
+    }
+
+    /// An environment as set up by xbar. Since xbar is not yet detected separately, this is reported as [`Flavor::BitBar`]. The system is in light mode unless changed using [`FakeEnv::dark_mode`].
+    ///
+    /// xbar passes [plugin variables](https://github.com/matryer/xbar-plugins/blob/main/CONTRIBUTING.md#variables-in-xbar) as environment variables with a `VAR_` prefix. These can be added using [`FakeEnv::var`].
+    pub fn xbar() -> Self {
+        Self::empty().var("XBARDarkMode", "false")
+    }
+
+    /// An environment detected as SwiftBar with the given build number, e.g. `399` for SwiftBar 1.4.3 beta 1. The system is in light mode unless changed using [`FakeEnv::dark_mode`].
+    ///
+    /// Only the variables required for detection are set. Others, e.g. `SWIFTBAR_PLUGIN_PATH`, can be added using [`FakeEnv::var`].
+    pub fn swiftbar(build: usize) -> Self {
+        Self::empty()
+            .var("SWIFTBAR", "1")
+            .var("SWIFTBAR_BUILD", build.to_string())
+            .var("OS_APPEARANCE", "Light")
+    }
+
+    /// Sets whether the system is in dark mode, using the environment variable of the BitBar implementation this environment is set up as.
+    pub fn dark_mode(self, dark_mode: bool) -> Self {
+        if self.is_set("SWIFTBAR") {
+            self.var("OS_APPEARANCE", if dark_mode { "Dark" } else { "Light" })
+        } else if self.is_set("XBARDarkMode") {
+            self.var("XBARDarkMode", dark_mode)
+        } else if dark_mode {
+            self.var("BitBarDarkMode", "1")
+        } else {
+            self.remove_var("BitBarDarkMode")
+        }
+    }
+
+    fn is_set(&self, key: &str) -> bool {
+        self.vars.get(key).is_some_and(Option::is_some)
+    }
+
+    /// Sets an environment variable.
+    pub fn var(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.vars.insert(key.to_string(), Some(value.to_string()));
+        self
+    }
+
+    /// Unsets an environment variable.
+    pub fn remove_var(mut self, key: impl ToString) -> Self {
+        self.vars.insert(key.to_string(), None);
+        self
+    }
+
+    /// Runs the given closure in this environment, then restores the previous environment.
+    pub fn run<T>(self, f: impl FnOnce() -> T) -> T {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let _guard = Guard {
+            previous: self.vars.into_iter().map(|(key, value)| {
+                let previous = env::var_os(&key);
+                match value {
+                    Some(value) => env::set_var(&key, value),
+                    None => env::remove_var(&key),
+                }
+                (key, previous)
+            }).collect(),
+        };
+        flavor::set_override(Some((Flavor::check_from(&ProcessEnv), flavor::dark_mode_from(&ProcessEnv))));
+        f()
+    }
+}
+
+/// Restores the environment when dropped, including during unwinding.
+struct Guard {
+    previous: Vec<(String, Option<OsString>)>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        flavor::set_override(None);
+        for (key, previous) in self.previous.drain(..).rev() {
+            match previous {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+        }
+    }
+}
+
+/// Runs the given closure in an environment detected as [`Flavor::BitBar`]. See [`FakeEnv::bitbar`].
+pub fn with_bitbar<T>(f: impl FnOnce() -> T) -> T {
+    FakeEnv::bitbar().run(f)
+}
+
+/// Runs the given closure in an environment as set up by xbar. See [`FakeEnv::xbar`].
+pub fn with_xbar<T>(f: impl FnOnce() -> T) -> T {
+    FakeEnv::xbar().run(f)
+}
+
+/// Runs the given closure in an environment detected as SwiftBar with the given build number. See [`FakeEnv::swiftbar`].
+pub fn with_swiftbar<T>(build: usize, f: impl FnOnce() -> T) -> T {
+    FakeEnv::swiftbar(build).run(f)
+}
+
+//TODO `roundtrip(menu)` helper asserting that rendering and re-parsing a menu preserves its content for each flavor, blocked on a parser for plugin output
+
+/// The environment variable which, if set to `1`, makes snapshot assertions write the actual output to the golden file instead of comparing against it.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "BITBAR_UPDATE_SNAPSHOTS";
+
+/// Asserts that the rendered menu matches the contents of a golden file, panicking with a line diff if it doesn't.
+///
+/// If the [`UPDATE_SNAPSHOTS_VAR`] environment variable is set to `1`, the golden file is created or overwritten instead.
+///
+/// The [`assert_menu_snapshot`](crate::assert_menu_snapshot) macro calls this with a path relative to the calling package.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    if env::var_os(UPDATE_SNAPSHOTS_VAR).is_some_and(|value| value == "1") {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap_or_else(|e| panic!("failed to create snapshot directory {}: {e}", parent.display()));
+        }
+        fs::write(path, actual).unwrap_or_else(|e| panic!("failed to write snapshot {}: {e}", path.display()));
+        return
+    }
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) => panic!("failed to read snapshot {}: {e}\nrun with {UPDATE_SNAPSHOTS_VAR}=1 to create it\n\nactual output:\n{actual}", path.display()),
+    };
+    if expected != actual {
+        panic!("menu does not match snapshot {} (- expected, + actual):\n{}\nrun with {UPDATE_SNAPSHOTS_VAR}=1 to update it", path.display(), diff(&expected, actual));
+    }
+}
+
+/// Renders a line diff between two texts, based on their longest common subsequence of lines.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    // lcs[i][j] is the length of the longest common subsequence of expected[i..] and actual[j..]
+    let mut lcs = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut buf = String::default();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            let _ = writeln!(buf, "  {}", expected[i]);
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            let _ = writeln!(buf, "+ {}", actual[j]);
+            j += 1;
+        } else {
+            let _ = writeln!(buf, "- {}", expected[i]);
+            i += 1;
+        }
+    }
+    buf
+}
+
+/// Asserts that a [`Menu`](crate::Menu) matches the golden file `tests/snapshots/<name>.txt` in the calling package, panicking with a line diff if it doesn't.
+///
+/// The menu is rendered using its [`Display`](std::fmt::Display) implementation, or for the given [`Flavor`](crate::Flavor) using [`Menu::display_for`](crate::Menu::display_for) if one is passed as the third argument. See [`test::assert_snapshot`](crate::test::assert_snapshot) for how to update golden files.
+#[macro_export]
+#[cfg_attr(docsrs, doc(cfg(feature = "test")))]
+macro_rules! assert_menu_snapshot {
+    ($name:expr, $menu:expr $(,)?) => {
+        $crate::test::assert_snapshot(::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.txt", $name)), &$menu.to_string())
+    };
+    ($name:expr, $menu:expr, $flavor:expr $(,)?) => {
+        $crate::test::assert_snapshot(::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.txt", $name)), &$menu.display_for($flavor).to_string())
+    };
+}
@@ -0,0 +1,200 @@
+//! Formatting of points in time relative to the current time, for calendar or timer style plugins.
+//!
+//! Each function returns a [`Relative`], which includes the time at which the text goes out of date. This can be used to decide when the plugin needs to be refreshed, e.g. by passing the earliest of these times to [`refresh_in`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use {
+//!     chrono::{
+//!         TimeDelta,
+//!         Utc,
+//!     },
+//!     bitbar::{
+//!         Menu,
+//!         MenuItem,
+//!         time,
+//!     },
+//! };
+//!
+//! let now = Utc::now();
+//! let meeting = time::relative(now + TimeDelta::minutes(3) + TimeDelta::seconds(30), now);
+//! assert_eq!(meeting.text, "in 3 min");
+//! assert_eq!(meeting.stale_at, Some(now + TimeDelta::seconds(30)));
+//! let menu = Menu(vec![MenuItem::new(format!("Meeting {}", meeting.text))]);
+//! ```
+
+use {
+    std::time::Duration,
+    chrono::{
+        DateTime,
+        TimeDelta,
+        TimeZone,
+        Utc,
+    },
+};
+
+/// A formatted point in time, along with the time at which the formatted text no longer applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relative {
+    /// The formatted text, e.g. `in 3 min` or `2 h ago`.
+    pub text: String,
+    /// The time at which `text` goes out of date, or `None` if it stays accurate.
+    pub stale_at: Option<DateTime<Utc>>,
+}
+
+impl Relative {
+    /// The time from `now` until this becomes stale, or `None` if it stays accurate. Returns [`Duration::ZERO`] if it is already stale.
+    pub fn stale_in(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.stale_at.map(|stale_at| (stale_at - now).to_std().unwrap_or_default())
+    }
+}
+
+const UNITS: [(i64, &str, &str); 4] = [
+    (24 * 60 * 60, " day", " days"),
+    (60 * 60, " h", " h"),
+    (60, " min", " min"),
+    (1, " s", " s"),
+];
+
+/// Splits a non-negative number of seconds into the largest unit which fits at least once, returning the number of whole units, the unit length in seconds, and its formatted name.
+fn largest_unit(secs: i64) -> (i64, i64, &'static str) {
+    for (unit, singular, plural) in UNITS {
+        if secs >= unit {
+            let n = secs / unit;
+            return (n, unit, if n == 1 { singular } else { plural })
+        }
+    }
+    (0, 1, " s")
+}
+
+/// Formats `at` relative to `now`, like `in 3 min`, `2 h ago`, or `in 5 days`.
+///
+/// Times less than a minute in the future are formatted as `in less than 1 min`, and times less than a minute in the past as `just now`. Numbers are rounded toward `now`, so `in 1 h` means at least an hour from now.
+pub fn relative<Tz: TimeZone>(at: DateTime<Tz>, now: DateTime<Utc>) -> Relative {
+    let at = at.with_timezone(&Utc);
+    let delta = at - now;
+    if delta > TimeDelta::zero() {
+        let secs = delta.num_seconds();
+        if secs < 60 {
+            return Relative { text: "in less than 1 min".to_owned(), stale_at: Some(at) }
+        }
+        let (n, unit, name) = largest_unit(secs);
+        Relative {
+            text: format!("in {n}{name}"),
+            stale_at: Some(at - TimeDelta::seconds(n * unit)),
+        }
+    } else {
+        let secs = -delta.num_seconds();
+        if secs < 60 {
+            return Relative { text: "just now".to_owned(), stale_at: Some(at + TimeDelta::minutes(1)) }
+        }
+        let (n, unit, name) = largest_unit(secs);
+        Relative {
+            text: format!("{n}{name} ago"),
+            stale_at: Some(at + TimeDelta::seconds((n + 1) * unit)),
+        }
+    }
+}
+
+/// Formats `at` relative to the current time. See [`relative`].
+pub fn relative_to_now<Tz: TimeZone>(at: DateTime<Tz>) -> Relative {
+    relative(at, Utc::now())
+}
+
+/// Formats the time remaining from `now` until `end` as a countdown like `4:05` or `1:02:03`, rounded up to whole seconds.
+///
+/// The countdown becomes stale every second until it reaches `0:00`.
+pub fn countdown<Tz: TimeZone>(end: DateTime<Tz>, now: DateTime<Utc>) -> Relative {
+    let end = end.with_timezone(&Utc);
+    let remaining = end - now;
+    if remaining <= TimeDelta::zero() {
+        return Relative { text: "0:00".to_owned(), stale_at: None }
+    }
+    let mut secs = remaining.num_seconds();
+    let subsec = remaining - TimeDelta::seconds(secs);
+    if subsec > TimeDelta::zero() { secs += 1 }
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    Relative {
+        text: if hours > 0 { format!("{hours}:{minutes:02}:{seconds:02}") } else { format!("{minutes}:{seconds:02}") },
+        stale_at: Some(end - TimeDelta::seconds(secs - 1)),
+    }
+}
+
+/// Returns the time from `now` until the earliest of the given times at which a formatted text becomes stale, or `None` if none of them do.
+pub fn refresh_in<'a>(items: impl IntoIterator<Item = &'a Relative>, now: DateTime<Utc>) -> Option<Duration> {
+    items.into_iter().filter_map(|item| item.stale_in(now)).min()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::time::Duration,
+        chrono::{
+            DateTime,
+            TimeDelta,
+            TimeZone as _,
+            Utc,
+        },
+        super::{
+            countdown,
+            refresh_in,
+            relative,
+        },
+    };
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn relative_future() {
+        let now = now();
+        let soon = relative(now + TimeDelta::seconds(30), now);
+        assert_eq!(soon.text, "in less than 1 min");
+        assert_eq!(soon.stale_at, Some(now + TimeDelta::seconds(30)));
+        let later = relative(now + TimeDelta::days(1) + TimeDelta::seconds(1), now);
+        assert_eq!(later.text, "in 1 day");
+        assert_eq!(later.stale_at, Some(now + TimeDelta::seconds(1)));
+        assert_eq!(relative(now + TimeDelta::days(2), now).text, "in 2 days");
+    }
+
+    #[test]
+    fn relative_past() {
+        let now = now();
+        let recent = relative(now - TimeDelta::seconds(30), now);
+        assert_eq!(recent.text, "just now");
+        assert_eq!(recent.stale_at, Some(now + TimeDelta::seconds(30)));
+        let earlier = relative(now - TimeDelta::seconds(90), now);
+        assert_eq!(earlier.text, "1 min ago");
+        assert_eq!(earlier.stale_at, Some(now + TimeDelta::seconds(30)));
+        assert_eq!(relative(now - TimeDelta::hours(5), now).text, "5 h ago");
+    }
+
+    #[test]
+    fn countdown_rounds_up() {
+        let now = now();
+        let short = countdown(now + TimeDelta::seconds(245), now);
+        assert_eq!(short.text, "4:05");
+        assert_eq!(short.stale_at, Some(now + TimeDelta::seconds(1)));
+        let long = countdown(now + TimeDelta::milliseconds(3_723_500), now);
+        assert_eq!(long.text, "1:02:04");
+        assert_eq!(long.stale_at, Some(now + TimeDelta::milliseconds(500)));
+        let done = countdown(now - TimeDelta::seconds(1), now);
+        assert_eq!(done.text, "0:00");
+        assert_eq!(done.stale_at, None);
+    }
+
+    #[test]
+    fn refresh_in_earliest() {
+        let now = now();
+        let items = [
+            relative(now + TimeDelta::hours(3) + TimeDelta::minutes(10), now),
+            countdown(now + TimeDelta::seconds(10), now),
+            countdown(now, now),
+        ];
+        assert_eq!(refresh_in(&items, now), Some(Duration::from_secs(1)));
+        assert_eq!(refresh_in(&items[2..], now), None);
+        assert_eq!(items[0].stale_in(now + TimeDelta::hours(1)), Some(Duration::ZERO));
+    }
+}
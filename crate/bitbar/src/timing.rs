@@ -0,0 +1,138 @@
+//! Measuring how long a plugin takes to generate its menu, to help diagnose slow plugins.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{Menu, MenuItem, timing::{Timed, Timings}};
+//!
+//! #[bitbar::main]
+//! fn main() -> Timed<Menu> {
+//!     let mut timings = Timings::start();
+//!     let data = timings.time("fetch", || vec!["a", "b"]);
+//!     timings.finish(data.into_iter().map(MenuItem::new).collect())
+//! }
+//! ```
+//!
+//! This appends an item like “Generated in 412 ms at 14:03:07” to the menu, with a submenu listing the timed sections as well as the time taken to render the menu.
+
+use {
+    std::{
+        fmt,
+        io,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    chrono::{
+        DateTime,
+        Local,
+    },
+    crate::{
+        ContentItem,
+        MainOutput,
+        Menu,
+        MenuItem,
+        attr,
+    },
+};
+#[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))] use std::future::Future;
+
+/// Records how long named sections of menu generation take.
+#[derive(Debug, Clone)]
+pub struct Timings {
+    start: Instant,
+    started_at: DateTime<Local>,
+    sections: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    /// Starts measuring the total time.
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            started_at: Local::now(),
+            sections: Vec::default(),
+        }
+    }
+
+    /// Runs the given function, recording how long it took under the given name.
+    pub fn time<T>(&mut self, name: impl ToString, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let output = f();
+        self.record(name, start.elapsed());
+        output
+    }
+
+    /// Awaits the given future, recording how long it took under the given name.
+    #[cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "async-std", feature = "futures-executor", feature = "tokio"))))]
+    pub async fn time_async<F: Future>(&mut self, name: impl ToString, fut: F) -> F::Output {
+        let start = Instant::now();
+        let output = fut.await;
+        self.record(name, start.elapsed());
+        output
+    }
+
+    /// Records a section which was timed separately.
+    pub fn record(&mut self, name: impl ToString, duration: Duration) {
+        self.sections.push((name.to_string(), duration));
+    }
+
+    /// The time elapsed since [`start`](Self::start).
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Returns a separator followed by an item showing the elapsed time, with a submenu listing the recorded sections.
+    pub fn items(&self) -> Vec<MenuItem> {
+        let mut item = ContentItem::new(format!("Generated in {} at {}", Millis(self.elapsed()), self.started_at.format("%H:%M:%S")));
+        if !self.sections.is_empty() {
+            item = item.sub(self.sections.iter().map(|(name, duration)| MenuItem::new(format!("{name}: {}", Millis(*duration)))));
+        }
+        vec![MenuItem::Sep, item.into()]
+    }
+
+    /// Wraps the output of `main` so that the [timing items](Self::items) are appended to it, including the time taken to render it.
+    pub fn finish<T: MainOutput>(self, output: T) -> Timed<T> {
+        Timed { output, timings: self }
+    }
+}
+
+struct Millis(Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ms", self.0.as_millis())
+    }
+}
+
+/// The output of `main` along with [`Timings`], returned by [`Timings::finish`].
+///
+/// The output is rendered into a buffer first, so the render time can be included.
+#[derive(Debug)]
+pub struct Timed<T> {
+    output: T,
+    timings: Timings,
+}
+
+impl<T: MainOutput> MainOutput for Timed<T> {
+    fn write_main_output(self, output: &mut dyn io::Write, error_template_image: Option<attr::Image>) -> io::Result<()> {
+        let Self { output: inner, mut timings } = self;
+        let mut buf = Vec::default();
+        timings.time("render", || inner.write_main_output(&mut buf, error_template_image))?;
+        output.write_all(&buf)?;
+        crate::write_menu(output, &Menu(timings.items()))
+    }
+
+    fn into_error_menu(self) -> Result<Menu, Self> {
+        let Self { output, timings } = self;
+        match output.into_error_menu() {
+            Ok(mut menu) => {
+                menu.extend(timings.items());
+                Ok(menu)
+            }
+            Err(output) => Err(Self { output, timings }),
+        }
+    }
+}
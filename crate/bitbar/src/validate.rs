@@ -0,0 +1,424 @@
+//! Checking menus for mistakes which BitBar implementations silently ignore or display incorrectly.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{
+//!     ContentItem,
+//!     Flavor,
+//!     Menu,
+//!     validate::IssueKind,
+//! };
+//!
+//! let menu = Menu(vec![
+//!     ContentItem::new("").color("red").unwrap().into(),
+//!     ContentItem::new("Title").size(0).into(),
+//! ]);
+//! let issues = menu.validate(Flavor::BitBar);
+//! assert_eq!(issues.len(), 2);
+//! assert_eq!(issues[0].kind, IssueKind::EmptyTextWithParams);
+//! assert_eq!(issues[1].to_string(), "item 2: font size must not be 0");
+//! ```
+//!
+//! Overly long lines, command parameters, and images, as well as deeply nested submenus, are reported according to configurable [`Limits`]:
+//!
+//! ```rust
+//! use bitbar::{
+//!     Flavor,
+//!     Menu,
+//!     MenuItem,
+//!     validate::{
+//!         IssueKind,
+//!         Limits,
+//!     },
+//! };
+//!
+//! let menu = Menu(vec![MenuItem::new("a".repeat(100))]);
+//! let issues = menu.validate_with(Flavor::BitBar, &Limits { line: 80, ..Limits::default() });
+//! assert_eq!(issues[0].kind, IssueKind::LineTooLong { len: 100, max: 80 });
+//! ```
+//!
+//! Long parameters to the plugin's own subcommands can be moved out of the rendered menu using [`Menu::spill_long_params`].
+
+use {
+    std::{
+        fmt,
+        fs,
+        io,
+        path::{
+            Path,
+            PathBuf,
+        },
+        time::Duration,
+    },
+    thiserror::Error,
+    crate::{
+        ContentItem,
+        Flavor,
+        Menu,
+        MenuItem,
+        attr::Extra,
+    },
+};
+
+/// Images with more base64-encoded data than this are reported as [`IssueKind::ImageTooLarge`]. Larger images make the plugin output slow to parse and are usually far larger than a menu bar icon needs to be.
+pub const MAX_IMAGE_LEN: usize = 100 * 1024;
+
+/// The maximum number of parameters to a `bash=` command supported by the original BitBar.
+pub const MAX_BITBAR_PARAMS: usize = 5;
+
+/// Command parameters longer than this are reported as [`IssueKind::ParamTooLong`]. Commands are run with their parameters as arguments, and Linux limits the length of a single argument to 128 KiB, while macOS limits the total length of all arguments and environment variables to 1 MiB.
+pub const MAX_PARAM_LEN: usize = 128 * 1024;
+
+/// Rendered lines longer than this are reported as [`IssueKind::LineTooLong`].
+pub const MAX_LINE_LEN: usize = 256 * 1024;
+
+/// Submenus nested more deeply than this are reported as [`IssueKind::TooDeep`]. Deeply nested menus are hard to navigate with the mouse, since each level opens further to the side. [`Menu::flatten_beyond`] can be used to flatten them.
+pub const MAX_DEPTH: usize = 5;
+
+/// Configurable limits for [`Menu::validate_with`]. The [`Default`] implementation uses [`MAX_LINE_LEN`], [`MAX_PARAM_LEN`], [`MAX_IMAGE_LEN`], and [`MAX_DEPTH`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum length of a rendered line in bytes, including the `--` prefixes of submenu items.
+    pub line: usize,
+    /// The maximum length of a command parameter (`bash=`, `param1=`, etc.) in bytes. Longer parameters to the plugin's own subcommands can be moved to temporary files using [`Menu::spill_long_params`].
+    pub param: usize,
+    /// The maximum length of base64-encoded image data.
+    pub image: usize,
+    /// The maximum nesting depth of submenus, as returned by [`Menu::max_depth`].
+    pub depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            line: MAX_LINE_LEN,
+            param: MAX_PARAM_LEN,
+            image: MAX_IMAGE_LEN,
+            depth: MAX_DEPTH,
+        }
+    }
+}
+
+/// A problem found by [`Menu::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The 0-based position of the item, starting with its index in the top-level menu, followed by its index in each submenu.
+    pub path: Vec<usize>,
+    /// Whether the issue is with the alternate item of the item at `path` rather than the item itself.
+    pub alternate: bool,
+    /// The kind of problem.
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "item ")?;
+        for (depth, idx) in self.path.iter().enumerate() {
+            if depth > 0 { write!(f, " > ")? }
+            write!(f, "{}", idx + 1)?;
+        }
+        if self.alternate { write!(f, " (alternate)")? }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// The kinds of problems found by [`Menu::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// A command has more parameters than the original BitBar supports.
+    TooManyParams {
+        /// The number of parameters.
+        count: usize,
+    },
+    /// The `font=` parameter is empty.
+    EmptyFont,
+    /// The `size=` parameter is 0.
+    ZeroSize,
+    /// The item has no text but has parameters, so it is displayed as an empty line which may still be clickable.
+    EmptyTextWithParams,
+    /// The base64-encoded image data is longer than [`Limits::image`].
+    ImageTooLarge {
+        /// The length of the base64-encoded image data.
+        len: usize,
+        /// The limit which was exceeded.
+        max: usize,
+    },
+    /// A command parameter is longer than [`Limits::param`].
+    ParamTooLong {
+        /// The name of the parameter, e.g. `param1`.
+        name: String,
+        /// The length of the parameter.
+        len: usize,
+        /// The limit which was exceeded.
+        max: usize,
+    },
+    /// The rendered line is longer than [`Limits::line`].
+    LineTooLong {
+        /// The length of the rendered line, excluding the line break.
+        len: usize,
+        /// The limit which was exceeded.
+        max: usize,
+    },
+    /// The item's submenu is nested more deeply than [`Limits::depth`]. This is only reported for the outermost item whose submenu exceeds the limit.
+    TooDeep {
+        /// The nesting depth of the submenu, counting from 1 for submenus of top-level items.
+        depth: usize,
+        /// The limit which was exceeded.
+        max: usize,
+    },
+    /// A parameter is omitted because the BitBar implementation doesn't [support](Flavor::supports_param) it.
+    UnsupportedParam(String),
+    /// A parameter value contains a line break, or both a space and a double quote, so it can't be rendered correctly.
+    InvalidParamValue(String),
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyParams { count } => write!(f, "command has {count} parameters, but BitBar supports at most {MAX_BITBAR_PARAMS}"),
+            Self::EmptyFont => write!(f, "font name is empty"),
+            Self::ZeroSize => write!(f, "font size must not be 0"),
+            Self::EmptyTextWithParams => write!(f, "item has parameters but no text"),
+            Self::ImageTooLarge { len, max } => write!(f, "image data is {len} bytes long, the maximum is {max}"),
+            Self::ParamTooLong { name, len, max } => write!(f, "parameter {name}= is {len} bytes long, the maximum is {max}"),
+            Self::LineTooLong { len, max } => write!(f, "rendered line is {len} bytes long, the maximum is {max}"),
+            Self::TooDeep { depth, max } => write!(f, "submenu is nested {depth} levels deep, the maximum is {max}"),
+            Self::UnsupportedParam(name) => write!(f, "parameter {name}= is not supported and will be omitted"),
+            Self::InvalidParamValue(name) => write!(f, "value of parameter {name}= can't be rendered on a single line"),
+        }
+    }
+}
+
+pub(crate) fn menu(menu: &Menu, flavor: Flavor, limits: &Limits) -> Vec<Issue> {
+    let mut issues = Vec::default();
+    validate_menu(&mut issues, &mut Vec::default(), menu, flavor, limits);
+    issues
+}
+
+fn validate_menu(issues: &mut Vec<Issue>, path: &mut Vec<usize>, menu: &Menu, flavor: Flavor, limits: &Limits) {
+    for (idx, item) in menu.0.iter().enumerate() {
+        let MenuItem::Content(item) = item else { continue };
+        path.push(idx);
+        validate_item(issues, path, item, false, flavor, limits);
+        match &item.extra {
+            Some(Extra::Alternate(alt)) => validate_item(issues, path, alt, true, flavor, limits),
+            Some(Extra::Submenu(sub)) => {
+                if path.len() == limits.depth + 1 {
+                    issues.push(Issue { path: path.clone(), alternate: false, kind: IssueKind::TooDeep { depth: path.len(), max: limits.depth } });
+                }
+                validate_menu(issues, path, sub, flavor, limits);
+            }
+            None => {}
+        }
+        path.pop();
+    }
+}
+
+fn validate_item(issues: &mut Vec<Issue>, path: &[usize], item: &ContentItem, alternate: bool, flavor: Flavor, limits: &Limits) {
+    let mut push = |kind| issues.push(Issue { path: path.to_owned(), alternate, kind });
+    if let Some(ref command) = item.command {
+        let count = command.params.params.len();
+        if count > MAX_BITBAR_PARAMS && !matches!(flavor, Flavor::SwiftBar(_)) { push(IssueKind::TooManyParams { count }) }
+    }
+    if item.font.as_ref().is_some_and(|font| font.trim().is_empty()) { push(IssueKind::EmptyFont) }
+    if item.size == Some(0) { push(IssueKind::ZeroSize) }
+    if let Some(ref image) = item.image {
+        let len = image.base64_data.len();
+        if len > limits.image { push(IssueKind::ImageTooLarge { len, max: limits.image }) }
+    }
+    let params = item.params(alternate, None);
+    for (name, value) in &params {
+        if (name == "bash" || name.starts_with("param")) && value.len() > limits.param { push(IssueKind::ParamTooLong { name: name.to_string(), len: value.len(), max: limits.param }) }
+    }
+    // matches the rendering in ContentItem::render, which replaces | with the 2-byte ¦, omits parameters the flavor doesn't support, and quotes parameter values containing spaces
+    let len = 2 * (path.len() - 1) + item.text.len() + item.text.matches('|').count() + params.iter()
+        .filter(|(name, _)| flavor.supports_param(name))
+        .map(|(name, value)| 2 + name.len() + value.len() + if value.contains(' ') { 2 } else { 0 })
+        .reduce(|acc, len| acc + len)
+        .map_or(0, |len| len + 2);
+    if len > limits.line { push(IssueKind::LineTooLong { len, max: limits.line }) }
+    // the alternate= parameter is implied by the structure of the menu
+    if item.text.trim().is_empty() && params.iter().any(|(name, _)| name != "alternate") { push(IssueKind::EmptyTextWithParams) }
+    for (name, value) in &params {
+        if !flavor.supports_param(name) { push(IssueKind::UnsupportedParam(name.to_string())) }
+        if value.contains(['\n', '\r']) || value.contains(' ') && value.contains('"') { push(IssueKind::InvalidParamValue(name.to_string())) }
+    }
+}
+
+/// Command parameters starting with this prefix are replaced with the contents of the file at the path following it before being passed to the plugin's subcommands.
+pub(crate) const SPILL_PREFIX: &str = "@bitbar-spill:";
+
+/// Spill files which haven't been used by a rendered menu for this long are removed the next time a new parameter is spilled.
+const SPILL_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The directory containing spilled parameters, a subdirectory of the [plugin cache directory](crate::cache_dir).
+fn spill_dir() -> io::Result<PathBuf> {
+    Ok(crate::cache_dir()?.join("spill"))
+}
+
+pub(crate) fn spill_menu(menu: &mut Menu, max_len: usize, exe: &str) -> io::Result<()> {
+    spill_menu_in(menu, max_len, exe, &spill_dir()?)
+}
+
+fn spill_menu_in(menu: &mut Menu, max_len: usize, exe: &str, dir: &Path) -> io::Result<()> {
+    let mut created = false;
+    spill_items(menu, max_len, exe, dir, &mut created)?;
+    // files still referenced by this menu have just been touched, so they're not removed
+    if created { crate::prune_dir(dir, SPILL_MAX_AGE)? }
+    Ok(())
+}
+
+fn spill_items(menu: &mut Menu, max_len: usize, exe: &str, dir: &Path, created: &mut bool) -> io::Result<()> {
+    for item in &mut menu.0 {
+        let MenuItem::Content(item) = item else { continue };
+        spill_item(item, max_len, exe, dir, created)?;
+    }
+    Ok(())
+}
+
+fn spill_item(item: &mut ContentItem, max_len: usize, exe: &str, dir: &Path, created: &mut bool) -> io::Result<()> {
+    if let Some(ref mut command) = item.command {
+        // only the plugin's own subcommands know how to read spilled parameters
+        let (cmd, params) = command.target_mut();
+        if cmd == exe {
+            // the first parameter is the subcommand name, which is never unspilled
+            for param in params.iter_mut().skip(1) {
+                if param.len() > max_len {
+                    // content-addressed, so a menu which is rendered on each refresh doesn't create a new file each time
+                    let path = dir.join(format!("{:016x}", crate::stable_hash(param.as_bytes())));
+                    if path.exists() {
+                        crate::touch(&path)?;
+                    } else {
+                        crate::write_atomic(&path, param.as_bytes())?;
+                        *created = true;
+                    }
+                    let path = path.into_os_string().into_string().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF-8 cache directory path"))?;
+                    *param = format!("{SPILL_PREFIX}{path}");
+                }
+            }
+        }
+    }
+    match item.extra {
+        Some(Extra::Alternate(ref mut alt)) => spill_item(&mut alt.0, max_len, exe, dir, created),
+        Some(Extra::Submenu(ref mut sub)) => spill_items(sub, max_len, exe, dir, created),
+        None => Ok(()),
+    }
+}
+
+/// An error that can occur while reading back a parameter moved to a file by [`Menu::spill_long_params`].
+#[derive(Debug, Error)]
+pub(crate) enum UnspillError {
+    #[error("failed to locate the directory for spilled parameters")]
+    Dir(#[source] io::Error),
+    #[error("spilled parameter {} is not in the directory for spilled parameters", .0.display())]
+    OutsideDir(PathBuf),
+    #[error("failed to read spilled parameter from {}", .0.display())]
+    Read(PathBuf, #[source] io::Error),
+}
+
+/// Replaces a spilled parameter with its original value. Other arguments are left unchanged.
+pub(crate) fn unspill(arg: &mut String) -> Result<(), UnspillError> {
+    if !arg.starts_with(SPILL_PREFIX) { return Ok(()) }
+    unspill_in(arg, &spill_dir().map_err(UnspillError::Dir)?)
+}
+
+fn unspill_in(arg: &mut String, dir: &Path) -> Result<(), UnspillError> {
+    let Some(path) = arg.strip_prefix(SPILL_PREFIX) else { return Ok(()) };
+    let path = PathBuf::from(path);
+    // canonicalize to make sure the file is actually in the spill directory, e.g. not referenced via `..`
+    let dir = dir.canonicalize().map_err(UnspillError::Dir)?;
+    let canonical_path = path.canonicalize().map_err(|e| UnspillError::Read(path.clone(), e))?;
+    if canonical_path.parent() != Some(&*dir) { return Err(UnspillError::OutsideDir(path)) }
+    *arg = fs::read_to_string(canonical_path).map_err(|e| UnspillError::Read(path, e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::{
+            fs,
+            path::PathBuf,
+            process,
+        },
+        crate::{
+            ContentItem,
+            Menu,
+        },
+        super::*,
+    };
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bitbar-test-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn params(menu: &mut Menu) -> Vec<String> {
+        let MenuItem::Content(item) = &mut menu.0[0] else { panic!("expected content item") };
+        item.command.as_mut().unwrap().target_mut().1.to_vec()
+    }
+
+    #[test]
+    fn spill_round_trip() {
+        let dir = test_dir("spill-round-trip");
+        let long = "x".repeat(100);
+        let mut menu = Menu(vec![ContentItem::new("item").command(("/plugin", "x".repeat(100), long.clone(), "short")).unwrap().into()]);
+        spill_menu_in(&mut menu, 10, "/plugin", &dir).unwrap();
+        let mut params = params(&mut menu);
+        assert_eq!(params[0], long, "subcommand name must not be spilled");
+        assert!(params[1].starts_with(SPILL_PREFIX));
+        assert_eq!(params[2], "short");
+        unspill_in(&mut params[1], &dir).unwrap();
+        assert_eq!(params[1], long);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_spill_files_pruned() {
+        let dir = test_dir("stale-files");
+        let stale = dir.join("stale");
+        fs::write(&stale, "stale").unwrap();
+        fs::File::options().write(true).open(&stale).unwrap().set_modified(std::time::SystemTime::now() - SPILL_MAX_AGE * 2).unwrap();
+        let mut menu = Menu(vec![ContentItem::new("item").command(("/plugin", "sub", "x".repeat(100))).unwrap().into()]);
+        spill_menu_in(&mut menu, 10, "/plugin", &dir).unwrap();
+        assert!(!stale.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn other_commands_not_spilled() {
+        let dir = test_dir("other-commands");
+        let long = "x".repeat(100);
+        let mut menu = Menu(vec![ContentItem::new("item").command(("/bin/echo", "sub", long.clone())).unwrap().into()]);
+        spill_menu_in(&mut menu, 10, "/plugin", &dir).unwrap();
+        assert_eq!(params(&mut menu)[1], long);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unspill_rejects_outside_dir() {
+        let dir = test_dir("outside-dir");
+        let outside = test_dir("outside-dir-target").join("secret");
+        fs::write(&outside, "secret").unwrap();
+        let mut arg = format!("{SPILL_PREFIX}{}", outside.display());
+        assert!(matches!(unspill_in(&mut arg, &dir), Err(UnspillError::OutsideDir(_))));
+        let mut arg = format!("{SPILL_PREFIX}{}", dir.join("..").join(format!("bitbar-test-{}-outside-dir-target", process::id())).join("secret").display());
+        assert!(matches!(unspill_in(&mut arg, &dir), Err(UnspillError::OutsideDir(_))));
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(outside.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unspill_missing_file() {
+        let dir = test_dir("missing-file");
+        let mut arg = format!("{SPILL_PREFIX}{}", dir.join("0000000000000000").display());
+        assert!(matches!(unspill_in(&mut arg, &dir), Err(UnspillError::Read(..))));
+        let mut arg = String::from("not spilled");
+        unspill_in(&mut arg, &dir).unwrap();
+        assert_eq!(arg, "not spilled");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! Ready-made menu items for common system metrics.
+//!
+//! Each function returns a [`Reading`], which can be displayed as a detailed menu item using [`Reading::item`] or as compact text suitable for the menu bar using [`Reading::header`]. Readings which exceed their [`Thresholds`] are colored.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bitbar::{Menu, MenuItem, widgets};
+//!
+//! #[bitbar::main]
+//! fn main() -> Menu {
+//!     let cpu = widgets::cpu();
+//!     let mut menu = vec![cpu.header().into(), MenuItem::Sep, cpu.item().into(), widgets::memory().item().into()];
+//!     if let Some(disk) = widgets::disk("/") {
+//!         menu.push(disk.item().into());
+//!     }
+//!     if let Ok(Some(battery)) = widgets::battery() {
+//!         menu.push(battery.item().into());
+//!     }
+//!     Menu(menu)
+//! }
+//! ```
+
+use {
+    std::{
+        io,
+        path::Path,
+        process::Command,
+        thread,
+        time::Duration,
+    },
+    sysinfo::{
+        Disks,
+        MINIMUM_CPU_UPDATE_INTERVAL,
+        Networks,
+        System,
+    },
+    crate::{
+        ContentItem,
+        attr::Color,
+        format::{
+            self,
+            NumberFormat,
+        },
+    },
+};
+
+/// The values at which a [`Reading`] is colored as a warning or as critical.
+///
+/// If `warning` is greater than `critical`, lower values are considered worse, e.g. for battery charge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// Readings at or beyond this value are colored [`Thresholds::WARNING_COLOR`].
+    pub warning: f64,
+    /// Readings at or beyond this value are colored [`Thresholds::CRITICAL_COLOR`].
+    pub critical: f64,
+}
+
+impl Thresholds {
+    /// The default thresholds for usage fractions such as CPU, memory, and disk usage: warning at 75%, critical at 90%.
+    pub const USAGE: Self = Self { warning: 0.75, critical: 0.9 };
+    /// The default thresholds for battery charge: warning at 20%, critical at 10%.
+    pub const BATTERY: Self = Self { warning: 0.2, critical: 0.1 };
+    /// The color used for readings past the warning threshold.
+    pub const WARNING_COLOR: Color = Color { light: css_color_parser::Color { r: 0xff, g: 0xaa, b: 0x00, a: 1.0 }, dark: None };
+    /// The color used for readings past the critical threshold.
+    pub const CRITICAL_COLOR: Color = Color { light: css_color_parser::Color { r: 0xff, g: 0x33, b: 0x33, a: 1.0 }, dark: None };
+
+    /// The color for the given value, or `None` if it's not past the warning threshold.
+    pub fn color(&self, value: f64) -> Option<Color> {
+        let past = |threshold: f64| if self.warning > self.critical { value <= threshold } else { value >= threshold };
+        if past(self.critical) {
+            Some(Self::CRITICAL_COLOR)
+        } else if past(self.warning) {
+            Some(Self::WARNING_COLOR)
+        } else {
+            None
+        }
+    }
+}
+
+/// A measurement of a system metric, returned by the functions in this module.
+#[derive(Debug, Clone)]
+pub struct Reading {
+    /// The measured value, as a fraction between 0 and 1 for usage and battery readings, or in bytes per second for network throughput.
+    pub value: f64,
+    /// A detailed description of the reading, e.g. `Memory: 12.3 GB of 16.0 GB (77%)`.
+    pub text: String,
+    /// A short description of the reading, e.g. `MEM 77%`.
+    pub compact: String,
+    /// The thresholds used to color this reading, if any.
+    pub thresholds: Option<Thresholds>,
+}
+
+impl Reading {
+    /// Replaces the thresholds used to color this reading.
+    pub fn thresholds(mut self, thresholds: impl Into<Option<Thresholds>>) -> Self {
+        self.thresholds = thresholds.into();
+        self
+    }
+
+    fn colored(&self, text: &str) -> ContentItem {
+        let item = ContentItem::new(text);
+        match self.thresholds.and_then(|thresholds| thresholds.color(self.value)) {
+            Some(color) => item.color(color).unwrap_or_else(|never| match never {}),
+            None => item,
+        }
+    }
+
+    /// A menu item showing the detailed [`text`](Self::text), colored according to the thresholds.
+    pub fn item(&self) -> ContentItem {
+        self.colored(&self.text)
+    }
+
+    /// A menu item showing the [`compact`](Self::compact) text, colored according to the thresholds, for use as the menu bar title.
+    pub fn header(&self) -> ContentItem {
+        self.colored(&self.compact)
+    }
+}
+
+fn percent(fraction: f64) -> String {
+    NumberFormat::new().precision(0).percent(fraction)
+}
+
+/// Measures the total CPU usage. This takes a short time since CPU usage is measured over an interval.
+pub fn cpu() -> Reading {
+    let mut system = System::new();
+    system.refresh_cpu_usage();
+    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_cpu_usage();
+    let value = f64::from(system.global_cpu_usage()) / 100.0;
+    Reading {
+        value,
+        text: format!("CPU: {}", percent(value)),
+        compact: format!("CPU {}", percent(value)),
+        thresholds: Some(Thresholds::USAGE),
+    }
+}
+
+/// Measures memory usage.
+pub fn memory() -> Reading {
+    let mut system = System::new();
+    system.refresh_memory();
+    let total = system.total_memory() as f64;
+    let used = system.used_memory() as f64;
+    let value = if total > 0.0 { used / total } else { 0.0 };
+    Reading {
+        value,
+        text: format!("Memory: {} of {} ({})", format::bytes(used as u64), format::bytes(total as u64), percent(value)),
+        compact: format!("MEM {}", percent(value)),
+        thresholds: Some(Thresholds::USAGE),
+    }
+}
+
+/// Measures usage of the disk mounted at the given path, e.g. `/`. Returns `None` if there is no disk mounted there.
+pub fn disk(mount_point: impl AsRef<Path>) -> Option<Reading> {
+    let disks = Disks::new_with_refreshed_list();
+    let disk = disks.iter().find(|disk| disk.mount_point() == mount_point.as_ref())?;
+    let total = disk.total_space() as f64;
+    let used = total - disk.available_space() as f64;
+    let value = if total > 0.0 { used / total } else { 0.0 };
+    Some(Reading {
+        value,
+        text: format!("Disk {}: {} of {} ({})", disk.mount_point().display(), format::bytes(used as u64), format::bytes(total as u64), percent(value)),
+        compact: format!("DISK {}", percent(value)),
+        thresholds: Some(Thresholds::USAGE),
+    })
+}
+
+/// Measures the combined receive and transmit throughput of all network interfaces over the given interval, blocking for its duration.
+///
+/// The [`value`](Reading::value) is the total in bytes per second. This reading has no thresholds by default.
+pub fn network(interval: Duration) -> Reading {
+    let mut networks = Networks::new_with_refreshed_list();
+    thread::sleep(interval);
+    networks.refresh(true);
+    let secs = interval.as_secs_f64().max(f64::EPSILON);
+    let (received, transmitted) = networks.iter().fold((0, 0), |(received, transmitted), (_, data)| (received + data.received(), transmitted + data.transmitted()));
+    let (received, transmitted) = (received as f64 / secs, transmitted as f64 / secs);
+    Reading {
+        value: received + transmitted,
+        text: format!("Network: ↓ {} ↑ {}", format::rate(received), format::rate(transmitted)),
+        compact: format!("↓{} ↑{}", format::rate(received), format::rate(transmitted)),
+        thresholds: None,
+    }
+}
+
+/// Reads the battery charge using `pmset`. Returns `Ok(None)` if the computer has no battery.
+pub fn battery() -> io::Result<Option<Reading>> {
+    let output = Command::new("pmset").arg("-g").arg("batt").output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("pmset exited with {}", output.status)))
+    }
+    let stdout = String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(parse_pmset(&stdout))
+}
+
+/// Parses the tab-separated status from a line like ` -InternalBattery-0 (id=1234)<tab>85%; discharging; 4:31 remaining present: true`.
+fn parse_pmset(output: &str) -> Option<Reading> {
+    let line = output.lines().find(|line| line.contains("InternalBattery"))?;
+    let (_, status) = line.split_once('\t')?;
+    let mut fields = status.split(';').map(str::trim);
+    let value = fields.next()?.strip_suffix('%')?.parse::<f64>().ok()? / 100.0;
+    let state = fields.next().unwrap_or_default();
+    let charging = matches!(state, "charging" | "charged" | "finishing charge" | "AC attached");
+    Some(Reading {
+        value,
+        text: format!("Battery: {} ({state})", percent(value)),
+        compact: format!("{}{}", if charging { "⚡" } else { "" }, percent(value)),
+        // don't warn about low charge while plugged in
+        thresholds: (!charging).then_some(Thresholds::BATTERY),
+    })
+}
@@ -3,16 +3,46 @@
 
 use {
     std::{
-        collections::HashMap,
-        io::prelude::*,
-        path::PathBuf,
+        collections::BTreeMap,
+        env,
+        ffi::OsString,
+        fmt,
+        fs,
+        io::{
+            self,
+            prelude::*,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+        process::{
+            self,
+            Command,
+            Stdio,
+        },
+        slice,
+        thread,
+        time::{
+            Duration,
+            Instant,
+            SystemTime,
+        },
     },
-    anyhow::Result,
+    anyhow::{
+        Result,
+        bail,
+    },
+    base64::Engine as _,
     cargo_metadata::{
+        Metadata,
         MetadataCommand,
         Package,
     },
-    clap::Parser as _,
+    clap::{
+        CommandFactory as _,
+        Parser as _,
+    },
     itertools::Itertools as _,
     serde::Deserialize,
 };
@@ -23,21 +53,19 @@ struct CustomMetadata {
     bitbar: BitBarMetadata,
 }
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 enum PluginKind {
+    #[default]
     Default,
     Streamable,
 }
 
-impl Default for PluginKind {
-    fn default() -> Self {
-        Self::Default
-    }
-}
+/// The name of the extended attribute in which SwiftBar looks for the metadata of binary plugins.
+const XATTR_NAME: &str = "com.ameba.SwiftBar";
 
 #[derive(Default, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct BitBarMetadata {
     #[serde(default, with = "serde_with::rust::double_option")]
     title: Option<Option<String>>,
@@ -53,8 +81,8 @@ struct BitBarMetadata {
     dependencies: Option<Option<String>>,
     #[serde(default, with = "serde_with::rust::double_option")]
     abouturl: Option<Option<String>>,
-    //TODO xbar variables? (unsure if xbar supports binary plugin metadata)
-    //TODO SwiftBar droptypes
+    #[serde(default)]
+    droptypes: Vec<String>,
     #[serde(default)]
     hide_about: bool,
     #[serde(default)]
@@ -72,21 +100,155 @@ struct BitBarMetadata {
     run_in_bash: bool,
     #[serde(default, rename = "type")]
     kind: PluginKind,
+    /// Defaults to `true` for streamable plugins.
+    use_trailing_stream_separator: Option<bool>,
     #[serde(default)]
-    environment: HashMap<String, String>,
+    environment: BTreeMap<String, String>,
+    /// A dotenv-style file with additional `environment` entries, relative to the manifest.
+    environment_file: Option<PathBuf>,
+    #[serde(default)]
+    vars: BTreeMap<String, ManifestVar>,
+}
+
+/// Parses the `NAME=value` lines of a dotenv-style file. Blank lines, comments starting with `#`, and `export` prefixes are ignored, and values may be quoted.
+fn parse_env_file(text: &str) -> Vec<(String, String)> {
+    text.lines().filter_map(|line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { return None }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line.split_once('=')?;
+        let value = value.trim();
+        let value = ['"', '\''].into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+        Some((name.trim().to_owned(), value.to_owned()))
+    }).collect()
+}
+
+/// Command-line flags overriding metadata fields from the manifest, e.g. to inject a release version in CI.
+#[derive(Clone, clap::Args)]
+struct MetaOverrides {
+    /// Override the plugin title.
+    #[clap(long)]
+    title: Option<String>,
+    /// Override the plugin version, e.g. `v1.2.3`.
+    #[clap(long)]
+    version: Option<String>,
+    /// Override the plugin author.
+    #[clap(long)]
+    author: Option<String>,
+    /// Override the GitHub username of the plugin author.
+    #[clap(long)]
+    author_github: Option<String>,
+    /// Override the plugin description.
+    #[clap(long)]
+    desc: Option<String>,
+    /// Override the URL of the plugin screenshot.
+    #[clap(long)]
+    image: Option<String>,
+    /// Override the plugin dependencies.
+    #[clap(long)]
+    dependencies: Option<String>,
+    /// Override the URL with more info about the plugin.
+    #[clap(long)]
+    abouturl: Option<String>,
+    /// Override the SwiftBar refresh schedule, a cron expression like `*/5 * * * *`.
+    #[clap(long)]
+    schedule: Option<String>,
+}
+
+impl BitBarMetadata {
+    /// Parses the bitbar metadata from the custom metadata of a package or workspace, and adds the entries from the `environment-file`, if any.
+    ///
+    /// The `environment-file` is relative to the manifest of `package` if the metadata is from that package, and to the workspace root otherwise.
+    fn load(metadata: &Metadata, package: Option<&Package>, custom_metadata: serde_json::Value) -> Result<Self> {
+        let mut bitbar = match serde_json::from_value::<CustomMetadata>(custom_metadata) {
+            Ok(custom_metadata) => custom_metadata.bitbar,
+            // the error lists the expected keys if there is an unknown one
+            Err(e) => bail!("invalid bitbar metadata: {e}"),
+        };
+        if let Some(ref environment_file) = bitbar.environment_file {
+            let base_dir = package
+                .filter(|package| package.metadata.get("bitbar").is_some())
+                .and_then(|package| package.manifest_path.parent())
+                .unwrap_or(&metadata.workspace_root);
+            let path = base_dir.as_std_path().join(environment_file);
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => bail!("failed to read environment file {}: {e}", path.display()),
+            };
+            for (name, default_value) in parse_env_file(&text) {
+                // entries in Cargo.toml take precedence
+                bitbar.environment.entry(name).or_insert(default_value);
+            }
+        }
+        Ok(bitbar)
+    }
+
+    fn apply(&mut self, overrides: MetaOverrides) {
+        let MetaOverrides { title, version, author, author_github, desc, image, dependencies, abouturl, schedule } = overrides;
+        if let Some(title) = title { self.title = Some(Some(title)) }
+        if let Some(version) = version { self.version = Some(Some(version)) }
+        if let Some(author) = author { self.author = Some(Some(author)) }
+        if let Some(author_github) = author_github { self.author_github = Some(author_github) }
+        if let Some(desc) = desc { self.desc = Some(Some(desc)) }
+        if let Some(image) = image { self.image = Some(image) }
+        if let Some(dependencies) = dependencies { self.dependencies = Some(Some(dependencies)) }
+        if let Some(abouturl) = abouturl { self.abouturl = Some(Some(abouturl)) }
+        if let Some(schedule) = schedule { self.schedule = Some(schedule) }
+    }
+}
+
+/// The type of a plugin variable declared in `[package.metadata.bitbar.vars]`.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum VarKind {
+    #[default]
+    String,
+    Number,
+    Boolean,
+}
+
+/// A plugin variable declared in `[package.metadata.bitbar.vars]`.
+#[derive(Deserialize)]
+struct ManifestVar {
+    #[serde(default, rename = "type")]
+    kind: VarKind,
+    default: Option<serde_json::Value>,
+    desc: Option<String>,
+}
+
+impl ManifestVar {
+    fn into_plugin_var(self, name: String) -> PluginVar {
+        PluginVar {
+            name,
+            kind: match self.kind {
+                VarKind::String => "string",
+                VarKind::Number => "number",
+                VarKind::Boolean => "boolean",
+            }.to_owned(),
+            default: match self.default {
+                None => String::default(),
+                Some(serde_json::Value::String(default)) => default,
+                Some(default) => default.to_string(),
+            },
+            desc: self.desc,
+        }
+    }
 }
 
 impl BitBarMetadata {
-    fn format(self, package: Option<&Package>) -> Result<Vec<u8>> {
-        let Self { title, version, author, author_github, desc, image, dependencies, abouturl, hide_about, hide_run_in_terminal, hide_last_updated, hide_disable_plugin, hide_swiftbar, schedule, refresh_on_open, run_in_bash, kind, environment } = self;
-        let mut buf = base64::write::EncoderWriter::new(Vec::default(), &base64::engine::general_purpose::STANDARD);
+    /// Formats the metadata as comment lines, using the given prefix (`bitbar` or `xbar`) for the tags shared by all BitBar implementations.
+    fn format(self, package: Option<&Package>, vars: Vec<PluginVar>, prefix: &str) -> Result<Vec<u8>> {
+        let Self { title, version, author, author_github, desc, image, dependencies, abouturl, droptypes, hide_about, hide_run_in_terminal, hide_last_updated, hide_disable_plugin, hide_swiftbar, schedule, refresh_on_open, run_in_bash, kind, use_trailing_stream_separator, mut environment, environment_file: _, vars: manifest_vars } = self;
+        let mut buf = Vec::default();
 
         macro_rules! double_option {
             ($field:ident, $fallback:expr) => {
                 match $field {
-                    Some(Some(field)) => { writeln!(&mut buf, concat!("# <bitbar.", stringify!($field), ">{}</bitbar.", stringify!($field), ">"), field)?; }
+                    Some(Some(field)) => { writeln!(&mut buf, concat!("# <{prefix}.", stringify!($field), ">{}</{prefix}.", stringify!($field), ">"), field, prefix = prefix)?; }
                     Some(None) => {}
-                    None => { writeln!(&mut buf, concat!("# <bitbar.", stringify!($field), ">{}</bitbar.", stringify!($field), ">"), $fallback)?; }
+                    None => { writeln!(&mut buf, concat!("# <{prefix}.", stringify!($field), ">{}</{prefix}.", stringify!($field), ">"), $fallback, prefix = prefix)?; }
                 }
             };
         }
@@ -94,10 +256,10 @@ impl BitBarMetadata {
         macro_rules! triple_option {
             ($field:ident, $fallback:expr) => {
                 match $field {
-                    Some(Some(field)) => { writeln!(&mut buf, concat!("# <bitbar.", stringify!($field), ">{}</bitbar.", stringify!($field), ">"), field)?; }
+                    Some(Some(field)) => { writeln!(&mut buf, concat!("# <{prefix}.", stringify!($field), ">{}</{prefix}.", stringify!($field), ">"), field, prefix = prefix)?; }
                     Some(None) => {}
                     None => if let Some(ref fallback) = $fallback {
-                        writeln!(&mut buf, concat!("# <bitbar.", stringify!($field), ">{}</bitbar.", stringify!($field), ">"), fallback)?;
+                        writeln!(&mut buf, concat!("# <{prefix}.", stringify!($field), ">{}</{prefix}.", stringify!($field), ">"), fallback, prefix = prefix)?;
                     },
                 }
             };
@@ -106,11 +268,12 @@ impl BitBarMetadata {
         triple_option!(title, package.map(|package| &package.name));
         triple_option!(version, package.map(|package| format!("v{}", package.version)));
         triple_option!(author, package.map(|package| package.authors.iter().map(|author| author.rsplit_once(" <").map(|(name, _)| name).unwrap_or(author)).join(", ")));
-        if let Some(author_github) = author_github { writeln!(&mut buf, "# <bitbar.author.github>{}</bitbar.author.github>", author_github)?; }
+        if let Some(author_github) = author_github { writeln!(&mut buf, "# <{prefix}.author.github>{}</{prefix}.author.github>", author_github)?; }
         triple_option!(desc, package.and_then(|package| package.description.as_ref()));
-        if let Some(image) = image { writeln!(&mut buf, "# <bitbar.image>{}</bitbar.image>", image)?; }
+        if let Some(image) = image { writeln!(&mut buf, "# <{prefix}.image>{}</{prefix}.image>", image)?; }
         double_option!(dependencies, "rust");
         triple_option!(abouturl, package.and_then(|package| package.homepage.as_ref()));
+        if !droptypes.is_empty() { writeln!(&mut buf, "# <swiftbar.droptypes>{}</swiftbar.droptypes>", droptypes.join(","))?; }
         if hide_about { writeln!(&mut buf, "# <swiftbar.hideAbout>true</swiftbar.hideAbout>")?; }
         if hide_run_in_terminal { writeln!(&mut buf, "# <swiftbar.hideRunInTerminal>true</swiftbar.hideRunInTerminal>")?; }
         if hide_last_updated { writeln!(&mut buf, "# <swiftbar.hideLastUpdated>true</swiftbar.hideLastUpdated>")?; }
@@ -119,57 +282,1381 @@ impl BitBarMetadata {
         if let Some(schedule) = schedule { writeln!(&mut buf, "# <swiftbar.schedule>{}</swiftbar.schedule>", schedule)?; }
         if refresh_on_open { writeln!(&mut buf, "# <swiftbar.refreshOnOpen>true</swiftbar.refreshOnOpen>")?; }
         if !run_in_bash { writeln!(&mut buf, "# <swiftbar.runInBash>false</swiftbar.runInBash>")?; }
-        match kind {
-            PluginKind::Default => {}
-            PluginKind::Streamable => {
-                writeln!(&mut buf, "# <swiftbar.type>streamable</swiftbar.type>")?;
-                writeln!(&mut buf, "# <swiftbar.useTrailingStreamSeparator>true</swiftbar.useTrailingStreamSeparator>")?;
+        if let PluginKind::Streamable = kind { writeln!(&mut buf, "# <swiftbar.type>streamable</swiftbar.type>")?; }
+        if use_trailing_stream_separator.unwrap_or(matches!(kind, PluginKind::Streamable)) { writeln!(&mut buf, "# <swiftbar.useTrailingStreamSeparator>true</swiftbar.useTrailingStreamSeparator>")?; }
+        for var in merge_vars(manifest_vars, vars) {
+            let xbar_name = var.xbar_name();
+            let PluginVar { name, kind, default, desc } = var;
+            let desc = desc.as_ref().unwrap_or(&name);
+            match &*kind {
+                "boolean" => writeln!(&mut buf, "# <xbar.var>boolean({}={}): {}</xbar.var>", xbar_name, if default.is_empty() { "false" } else { &default }, desc)?,
+                "number" => writeln!(&mut buf, "# <xbar.var>number({}={}): {}</xbar.var>", xbar_name, if default.is_empty() { "0" } else { &default }, desc)?,
+                // xbar reads the default as a Go string literal, which uses the same escapes as a TOML basic string
+                _ => writeln!(&mut buf, "# <xbar.var>string({}={}): {}</xbar.var>", xbar_name, toml_string(&default), desc)?,
             }
+            environment.entry(name).or_insert(default);
         }
         if !environment.is_empty() {
-            writeln!(&mut buf, "# <swiftbar.environment>[{}]</swiftbar.environment", environment.into_iter().map(|(var, default_value)| format!("{}:{}", var, default_value)).join(", "))?;
+            // SwiftBar splits the list at commas and has no way to escape them
+            if let Some((var, _)) = environment.iter().find(|(_, default_value)| default_value.contains([',', '\n'])) {
+                bail!("the default value of the environment variable {var} contains a comma or line break, which SwiftBar doesn't support")
+            }
+            writeln!(&mut buf, "# <swiftbar.environment>[{}]</swiftbar.environment>", environment.into_iter().map(|(var, default_value)| format!("{}:{}", var, default_value)).join(", "))?;
+        }
+        Ok(buf)
+    }
+}
+
+/// A plugin variable declared via `#[bitbar::main(vars(...))]` or in `[package.metadata.bitbar.vars]`.
+struct PluginVar {
+    name: String,
+    kind: String,
+    default: String,
+    desc: Option<String>,
+}
+
+impl PluginVar {
+    /// The name of the environment variable xbar uses for this variable, which must start with `VAR_`.
+    fn xbar_name(&self) -> String {
+        if self.name.starts_with("VAR_") { self.name.clone() } else { format!("VAR_{}", self.name) }
+    }
+
+    /// The default value as it appears in an xbar `.vars.json` file.
+    fn json_default(&self) -> serde_json::Value {
+        match &*self.kind {
+            "boolean" => serde_json::Value::Bool(self.default == "true"),
+            "number" => serde_json::from_str::<serde_json::Number>(&self.default).map_or(serde_json::Value::from(0), serde_json::Value::Number),
+            _ => serde_json::Value::String(self.default.clone()),
+        }
+    }
+}
+
+/// Combines the variables declared in the manifest with those declared in the binary. Variables declared in both places use the declaration from the manifest.
+fn merge_vars(manifest_vars: BTreeMap<String, ManifestVar>, binary_vars: Vec<PluginVar>) -> Vec<PluginVar> {
+    let mut vars = manifest_vars.into_iter().map(|(name, var)| var.into_plugin_var(name)).collect_vec();
+    for var in binary_vars {
+        if !vars.iter().any(|existing| existing.name == var.name) { vars.push(var) }
+    }
+    vars
+}
+
+/// Writes the default values of the plugin variables to the xbar `.vars.json` file for the plugin at the given path. Values already present in the file are kept.
+fn write_vars_json(plugin_path: &Path, vars: Vec<PluginVar>) -> Result<()> {
+    let mut path = plugin_path.to_owned().into_os_string();
+    path.push(".vars.json");
+    let path = PathBuf::from(path);
+    let mut values = match fs::read_to_string(&path) {
+        Ok(existing) => serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&existing)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::Map::default(),
+        Err(e) => return Err(e.into()),
+    };
+    for var in vars {
+        values.entry(var.xbar_name()).or_insert_with(|| var.json_default());
+    }
+    fs::write(&path, format!("{}\n", serde_json::to_string_pretty(&values)?))?;
+    println!("Wrote plugin variables to {}", path.display());
+    Ok(())
+}
+
+/// Runs the plugin binary to get the variables declared via `#[bitbar::main(vars(...))]`.
+fn read_vars(exe_path: &Path) -> Result<Vec<PluginVar>> {
+    let output = Command::new(exe_path).arg("bitbar-vars").output()?;
+    if !output.status.success() { bail!("failed to read plugin variables: {}", output.status) }
+    String::from_utf8(output.stdout)?.lines().map(|line| {
+        let Some((name, kind, default)) = line.splitn(3, '\t').collect_tuple() else { bail!("unexpected plugin variable description: {line:?}") };
+        Ok(PluginVar { name: name.to_owned(), kind: kind.to_owned(), default: default.to_owned(), desc: None })
+    }).collect()
+}
+
+/// Reads the metadata of the package or workspace with the given manifest, or the one in the current directory.
+fn read_manifest(manifest: Option<PathBuf>) -> Result<Metadata> {
+    let mut metadata_cmd = MetadataCommand::new();
+    metadata_cmd.no_deps();
+    if let Some(manifest) = manifest {
+        metadata_cmd.manifest_path(manifest);
+    }
+    Ok(metadata_cmd.exec()?)
+}
+
+/// Returns the root package, if any, and the custom metadata of that package or of the workspace if there is no root package.
+fn custom_metadata(metadata: &Metadata) -> (Option<&Package>, serde_json::Value) {
+    let package = metadata.root_package();
+    let custom_metadata = if let Some(package) = package {
+        package.metadata.clone()
+    } else {
+        metadata.workspace_metadata.clone()
+    };
+    (package, custom_metadata)
+}
+
+/// Formats the metadata for the given binary, as embedded by `cargo bitbar meta`.
+fn binary_metadata(metadata: &Metadata, packages: &[String], vars: bool, overrides: &MetaOverrides, exe_path: &Path) -> Result<Vec<u8>> {
+    let (package, custom_metadata) = package_for_binary(metadata, packages, exe_path)?;
+    let vars = if vars { read_vars(exe_path)? } else { Vec::default() };
+    let mut bitbar = BitBarMetadata::load(metadata, package, custom_metadata)?;
+    bitbar.apply(overrides.clone());
+    bitbar.format(package, vars, "bitbar")
+}
+
+/// Writes formatted metadata to the extended attribute of the given binary.
+fn embed_metadata(exe_path: &Path, bitbar_metadata: &[u8]) -> Result<()> {
+    xattr::set(exe_path, XATTR_NAME, base64::engine::general_purpose::STANDARD.encode(bitbar_metadata).as_bytes())?;
+    Ok(())
+}
+
+/// Returns the workspace member with the given name.
+fn workspace_package<'a>(metadata: &'a Metadata, name: &str) -> Result<&'a Package> {
+    let Some(package) = metadata.workspace_packages().into_iter().find(|package| package.name == name) else { bail!("package {name} is not a member of the workspace") };
+    Ok(package)
+}
+
+/// Returns the package selected using `--package` (or the root package) and the name of the binary target selected using `--bin` (or the package's only binary target).
+fn select_bin<'a>(metadata: &'a Metadata, package: Option<&str>, bin: Option<String>) -> Result<(&'a Package, String)> {
+    let package = if let Some(package) = package {
+        workspace_package(metadata, package)?
+    } else {
+        let Some(package) = metadata.root_package() else { bail!("the manifest is a virtual workspace manifest, use --package to select a package") };
+        package
+    };
+    let bin = if let Some(bin) = bin {
+        bin
+    } else {
+        let Ok(target) = package.targets.iter().filter(|target| target.is_bin()).exactly_one() else { bail!("package {} does not have exactly one binary target, use --bin to select one", package.name) };
+        target.name.clone()
+    };
+    Ok((package, bin))
+}
+
+/// The targets included in a universal macOS binary.
+const UNIVERSAL_TARGETS: [&str; 2] = ["aarch64-apple-darwin", "x86_64-apple-darwin"];
+
+/// Builds the plugin for each of the [`UNIVERSAL_TARGETS`], combines the binaries using `lipo`, and embeds the metadata into the result.
+fn universal(manifest: Option<PathBuf>, package: Option<String>, bin: Option<String>, vars: bool, overrides: MetaOverrides, output: Option<PathBuf>) -> Result<()> {
+    let metadata = read_manifest(manifest.clone())?;
+    let (_, bin) = select_bin(&metadata, package.as_deref(), bin)?;
+    let mut exe_paths = Vec::default();
+    for target in UNIVERSAL_TARGETS {
+        let mut build = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+        build.arg("build").arg("--release").arg("--target").arg(target).arg("--bin").arg(&bin);
+        if let Some(ref manifest) = manifest { build.arg("--manifest-path").arg(manifest); }
+        if let Some(ref package) = package { build.arg("--package").arg(package); }
+        let status = build.status()?;
+        if !status.success() { bail!("building for {target} failed: {status}") }
+        exe_paths.push(metadata.target_directory.join(target).join("release").join(&bin).into_std_path_buf());
+    }
+    let output = output.unwrap_or_else(|| metadata.target_directory.join("universal-apple-darwin").join("release").join(&bin).into_std_path_buf());
+    if let Some(parent) = output.parent() { fs::create_dir_all(parent)?; }
+    let status = Command::new("lipo").arg("-create").arg("-output").arg(&output).args(&exe_paths).status()?;
+    if !status.success() { bail!("lipo failed: {status}") }
+    let packages = package.into_iter().collect_vec();
+    let bitbar_metadata = binary_metadata(&metadata, &packages, vars, &overrides, &output)?;
+    embed_metadata(&output, &bitbar_metadata)?;
+    println!("Created universal binary {}", output.display());
+    Ok(())
+}
+
+/// The archive formats supported by `cargo bitbar package`.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ArchiveFormat {
+    /// A gzip-compressed tarball, created using `tar`.
+    TarGz,
+    /// A zip file, created using `zip`.
+    Zip,
+}
+
+/// Options for `cargo bitbar package`.
+#[derive(clap::Args)]
+struct PackageArgs {
+    /// The path to the Cargo manifest for the package or workspace.
+    #[clap(long)]
+    manifest: Option<PathBuf>,
+    /// The workspace member to package. Required for virtual workspaces.
+    #[clap(short, long)]
+    package: Option<String>,
+    /// The binary target to package. Required if the package has multiple binary targets.
+    #[clap(long)]
+    bin: Option<String>,
+    /// The binary to package, e.g. one created by `cargo bitbar universal`. Defaults to `target/release/<bin>`, which must already be built.
+    #[clap(long)]
+    exe: Option<PathBuf>,
+    /// The refresh interval to include in the plugin file name, e.g. `5m`.
+    #[clap(long)]
+    interval: Option<String>,
+    /// Also include a wrapper script with the metadata for xbar (see `cargo bitbar wrapper`).
+    #[clap(long)]
+    wrapper: bool,
+    /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]`, by running the binary.
+    #[clap(long)]
+    vars: bool,
+    #[clap(flatten)]
+    overrides: MetaOverrides,
+    /// Additional files to include, e.g. a preview image. The package's readme is included automatically.
+    #[clap(long = "include")]
+    includes: Vec<PathBuf>,
+    /// The archive format.
+    #[clap(long, value_enum, default_value = "tar-gz")]
+    format: ArchiveFormat,
+    /// The directory in which to create the archive. Defaults to `target/bitbar-package`.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Runs a command, returning an error if it fails.
+fn run(cmd: &mut Command) -> Result<()> {
+    let status = cmd.status()?;
+    if !status.success() { bail!("{:?} failed: {status}", cmd.get_program()) }
+    Ok(())
+}
+
+/// Assembles a release archive containing the plugin binary with its metadata, an optional wrapper script for xbar, the readme and other files, and a `SHA256SUMS` file.
+fn package(args: PackageArgs) -> Result<()> {
+    let PackageArgs { manifest, package, bin, exe, interval, wrapper, vars, overrides, includes, format, output } = args;
+    let metadata = read_manifest(manifest)?;
+    let (package, bin) = select_bin(&metadata, package.as_deref(), bin)?;
+    let exe_path = exe.unwrap_or_else(|| metadata.target_directory.join("release").join(&bin).into_std_path_buf());
+    if !exe_path.exists() { bail!("{} does not exist, build the plugin first", exe_path.display()) }
+    let version = overrides.version.clone().unwrap_or_else(|| format!("v{}", package.version));
+    let output = output.unwrap_or_else(|| metadata.target_directory.join("bitbar-package").into_std_path_buf());
+    let dir_name = format!("{bin}-{version}");
+    let staging = output.join(&dir_name);
+    if staging.exists() { fs::remove_dir_all(&staging)?; }
+    fs::create_dir_all(&staging)?;
+    // look up the package using the original binary name, since the copy is renamed
+    let packages = [package.name.clone()];
+    let plugin_name = if let Some(ref interval) = interval { format!("{bin}.{interval}") } else { bin.clone() };
+    let plugin_file_name = format!("{plugin_name}.o");
+    fs::copy(&exe_path, staging.join(&plugin_file_name))?;
+    embed_metadata(&staging.join(&plugin_file_name), &binary_metadata(&metadata, &packages, vars, &overrides, &exe_path)?)?;
+    if wrapper {
+        let (package, custom_metadata) = package_for_binary(&metadata, &packages, &exe_path)?;
+        let binary_vars = if vars { read_vars(&exe_path)? } else { Vec::default() };
+        let mut bitbar = BitBarMetadata::load(&metadata, package, custom_metadata)?;
+        bitbar.apply(overrides);
+        write_wrapper(&staging.join(format!("{plugin_name}.sh")), Path::new(&plugin_file_name), true, &bitbar.format(package, binary_vars, "xbar")?)?;
+    }
+    let readme = package.readme.clone().map(|readme| readme.into_std_path_buf()).or_else(|| {
+        let readme = package.manifest_path.parent()?.join("README.md").into_std_path_buf();
+        readme.exists().then_some(readme)
+    });
+    for path in readme.into_iter().chain(includes) {
+        let Some(file_name) = path.file_name() else { bail!("invalid path: {}", path.display()) };
+        fs::copy(&path, staging.join(file_name))?;
+    }
+    let mut file_names = fs::read_dir(&staging)?.map(|entry| Ok(entry?.file_name())).collect::<Result<Vec<_>>>()?;
+    file_names.sort();
+    let checksums = Command::new("shasum").arg("--algorithm").arg("256").args(&file_names).current_dir(&staging).output()?;
+    if !checksums.status.success() { bail!("shasum failed: {}", checksums.status) }
+    fs::write(staging.join("SHA256SUMS"), checksums.stdout)?;
+    let archive = match format {
+        ArchiveFormat::TarGz => {
+            let archive = output.join(format!("{dir_name}.tar.gz"));
+            run(Command::new("tar").arg("-czf").arg(&archive).arg("-C").arg(&output).arg(&dir_name))?;
+            archive
+        }
+        ArchiveFormat::Zip => {
+            let archive = output.join(format!("{dir_name}.zip"));
+            if archive.exists() { fs::remove_file(&archive)?; }
+            run(Command::new("zip").arg("-qr").arg(format!("{dir_name}.zip")).arg(&dir_name).current_dir(&output))?;
+            archive
+        }
+    };
+    println!("Created {}", archive.display());
+    Ok(())
+}
+
+/// Options for `cargo bitbar watch`.
+#[derive(clap::Args)]
+struct WatchArgs {
+    /// The path to the Cargo manifest for the package or workspace.
+    #[clap(long)]
+    manifest: Option<PathBuf>,
+    /// The workspace member to build. Required for virtual workspaces.
+    #[clap(short, long)]
+    package: Option<String>,
+    /// The binary target to build. Required if the package has multiple binary targets.
+    #[clap(long)]
+    bin: Option<String>,
+    /// Build in release mode.
+    #[clap(long)]
+    release: bool,
+    /// The SwiftBar plugin folder. Defaults to the one configured in SwiftBar.
+    #[clap(long)]
+    plugin_dir: Option<PathBuf>,
+    /// The refresh interval to include in the plugin file name, e.g. `5m`.
+    #[clap(long)]
+    interval: Option<String>,
+    /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]`, by running the binary.
+    #[clap(long)]
+    vars: bool,
+    #[clap(flatten)]
+    overrides: MetaOverrides,
+}
+
+/// How often `cargo bitbar watch` checks for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Returns the latest modification time of the files in the given directory, skipping hidden files and the target directory.
+fn latest_mtime(dir: &Path, target_dir: &Path) -> Result<Option<SystemTime>> {
+    let mut latest = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_str().is_none_or(|name| name.starts_with('.')) || path == target_dir { continue }
+        let mtime = if entry.file_type()?.is_dir() { latest_mtime(&path, target_dir)? } else { Some(entry.metadata()?.modified()?) };
+        latest = latest.max(mtime);
+    }
+    Ok(latest)
+}
+
+/// Builds the plugin, installs it into the plugin folder with its metadata, and refreshes it in SwiftBar.
+fn build_and_install(args: &WatchArgs, metadata: &Metadata, package: &Package, bin: &str, plugin_dir: &Path, plugin_name: &str) -> Result<()> {
+    let mut build = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    build.arg("build").arg("--bin").arg(bin).arg("--package").arg(&package.name);
+    if args.release { build.arg("--release"); }
+    if let Some(ref manifest) = args.manifest { build.arg("--manifest-path").arg(manifest); }
+    run(&mut build)?;
+    let exe_path = metadata.target_directory.join(if args.release { "release" } else { "debug" }).join(bin).into_std_path_buf();
+    // copy to a hidden file first so SwiftBar doesn't run a partially written binary
+    let tmp_path = plugin_dir.join(format!(".{plugin_name}.tmp"));
+    fs::copy(&exe_path, &tmp_path)?;
+    let install = binary_metadata(metadata, slice::from_ref(&package.name), args.vars, &args.overrides, &exe_path)
+        .and_then(|bitbar_metadata| embed_metadata(&tmp_path, &bitbar_metadata))
+        .and_then(|()| Ok(fs::rename(&tmp_path, plugin_dir.join(plugin_name))?));
+    if install.is_err() { let _ = fs::remove_file(&tmp_path); }
+    install?;
+    let url = url::Url::parse_with_params("swiftbar://refreshplugin", [("name", plugin_name)])?;
+    run(Command::new("open").arg("-g").arg(url.as_str()))?;
+    Ok(())
+}
+
+/// Returns the given plugin folder, or the one configured in SwiftBar if none is given.
+fn swiftbar_plugin_dir(plugin_dir: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(plugin_dir) = plugin_dir { return Ok(plugin_dir) }
+    let output = Command::new("defaults").arg("read").arg("com.ameba.SwiftBar").arg("PluginDirectory").output()?;
+    if !output.status.success() { bail!("failed to read the SwiftBar plugin folder, use --plugin-dir to specify it") }
+    Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
+}
+
+/// Rebuilds and reinstalls the plugin whenever a file in the package changes.
+fn watch(args: WatchArgs) -> Result<()> {
+    let metadata = read_manifest(args.manifest.clone())?;
+    let (package, bin) = select_bin(&metadata, args.package.as_deref(), args.bin.clone())?;
+    let plugin_dir = swiftbar_plugin_dir(args.plugin_dir.clone())?;
+    let plugin_name = if let Some(ref interval) = args.interval { format!("{bin}.{interval}.o") } else { format!("{bin}.o") };
+    let Some(package_dir) = package.manifest_path.parent() else { bail!("invalid manifest path: {}", package.manifest_path) };
+    let target_dir = metadata.target_directory.as_std_path();
+    let mut last_mtime = None;
+    loop {
+        let mtime = latest_mtime(package_dir.as_std_path(), target_dir)?;
+        if mtime != last_mtime {
+            last_mtime = mtime;
+            match build_and_install(&args, &metadata, package, &bin, &plugin_dir, &plugin_name) {
+                Ok(()) => eprintln!("installed {} and refreshed the plugin, watching for changes", plugin_dir.join(&plugin_name).display()),
+                Err(e) => eprintln!("error: {e}, watching for changes"),
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// The BitBar implementations which can be simulated by `cargo bitbar run`.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SimulatedFlavor {
+    /// The original BitBar.
+    Bitbar,
+    /// xbar, which is detected by the `bitbar` crate the same way as the original BitBar.
+    Xbar,
+    /// SwiftBar, with the build number given by `--build`.
+    Swiftbar,
+}
+
+/// The environment variables set by SwiftBar. These are removed when simulating other BitBar implementations.
+const SWIFTBAR_VARS: [&str; 7] = [
+    "SWIFTBAR",
+    "SWIFTBAR_BUILD",
+    "SWIFTBAR_VERSION",
+    "SWIFTBAR_PLUGIN_PATH",
+    "SWIFTBAR_PLUGIN_CACHE_PATH",
+    "SWIFTBAR_PLUGIN_DATA_PATH",
+    "SWIFTBAR_PLUGINS_PATH",
+];
+
+/// Returns the environment variables set by the simulated BitBar implementation, except for the SwiftBar cache and data directories.
+///
+/// BitBar and xbar set `BitBar` and `XBARDarkMode`, respectively. Without these, the `bitbar` crate assumes it's being run on its own and prints a colored tree instead of the plugin output if stdout is a terminal.
+fn simulated_env(flavor: SimulatedFlavor, build: usize, exe_path: &Path) -> Vec<(&'static str, OsString)> {
+    match flavor {
+        SimulatedFlavor::Bitbar => vec![("BitBar", "1".into())],
+        SimulatedFlavor::Xbar => vec![("XBARDarkMode", "false".into())],
+        SimulatedFlavor::Swiftbar => vec![
+            ("SWIFTBAR", "1".into()),
+            ("SWIFTBAR_BUILD", build.to_string().into()),
+            ("SWIFTBAR_PLUGIN_PATH", exe_path.into()),
+        ],
+    }
+}
+
+/// Options for `cargo bitbar run`.
+#[derive(clap::Args)]
+struct RunArgs {
+    /// The path to the Cargo manifest for the package or workspace.
+    #[clap(long)]
+    manifest: Option<PathBuf>,
+    /// The workspace member to build. Required for virtual workspaces.
+    #[clap(short, long)]
+    package: Option<String>,
+    /// The binary target to build. Required if the package has multiple binary targets.
+    #[clap(long)]
+    bin: Option<String>,
+    /// Build in release mode.
+    #[clap(long)]
+    release: bool,
+    /// The BitBar implementation to simulate.
+    #[clap(long, value_enum, default_value = "swiftbar")]
+    flavor: SimulatedFlavor,
+    /// The SwiftBar build number to simulate. Defaults to the newest build checked for features by the `bitbar` crate.
+    #[clap(long, default_value_t = bitbar::flavor::swiftbar::MAX_BUILD)]
+    build: usize,
+    /// Print the plugin output as is instead of as a tree.
+    #[clap(long)]
+    raw: bool,
+    /// Arguments to pass to the plugin, e.g. to run a command instead of printing the menu. Implies `--raw`.
+    #[clap(last = true)]
+    args: Vec<OsString>,
+}
+
+//TODO `cargo bitbar preview` subcommand which runs the plugin like `cargo bitbar run` and renders its menu to a PNG at the path of the `image` metadata field, blocked on a menu-to-PNG renderer
+
+/// Builds the plugin and runs it in a simulated BitBar environment, printing its menu as a tree.
+fn run_plugin(args: RunArgs) -> Result<()> {
+    let RunArgs { manifest, package, bin, release, flavor, build, raw, args } = args;
+    let metadata = read_manifest(manifest.clone())?;
+    let (package, bin) = select_bin(&metadata, package.as_deref(), bin)?;
+    let mut build_cmd = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    build_cmd.arg("build").arg("--bin").arg(&bin).arg("--package").arg(&package.name);
+    if release { build_cmd.arg("--release"); }
+    if let Some(ref manifest) = manifest { build_cmd.arg("--manifest-path").arg(manifest); }
+    run(&mut build_cmd)?;
+    let exe_path = metadata.target_directory.join(if release { "release" } else { "debug" }).join(&bin).into_std_path_buf();
+    let mut plugin = Command::new(&exe_path);
+    for var in SWIFTBAR_VARS {
+        plugin.env_remove(var);
+    }
+    plugin.envs(simulated_env(flavor, build, &exe_path));
+    if let SimulatedFlavor::Swiftbar = flavor {
+        // keep files written by the plugin out of the real SwiftBar folders
+        let run_dir = metadata.target_directory.join("bitbar-run").join(&bin).into_std_path_buf();
+        let cache_dir = run_dir.join("cache");
+        let data_dir = run_dir.join("data");
+        fs::create_dir_all(&cache_dir)?;
+        fs::create_dir_all(&data_dir)?;
+        plugin.env("SWIFTBAR_PLUGIN_CACHE_PATH", cache_dir);
+        plugin.env("SWIFTBAR_PLUGIN_DATA_PATH", data_dir);
+    }
+    if args.is_empty() {
+        if !raw { plugin.arg("--bitbar-preview"); }
+    } else {
+        plugin.args(args);
+    }
+    run(&mut plugin)
+}
+
+/// Prints the `<xbar.*>` comment header for submitting the plugin to the xbar plugin repository.
+fn submission_header(manifest: Option<PathBuf>, package: Option<String>, exe: Option<PathBuf>, overrides: MetaOverrides) -> Result<()> {
+    let metadata = read_manifest(manifest)?;
+    let (package, custom_metadata) = if let Some(package) = package {
+        package_metadata(&metadata, Some(workspace_package(&metadata, &package)?))
+    } else {
+        custom_metadata(&metadata)
+    };
+    let vars = if let Some(exe) = exe { read_vars(&exe)? } else { Vec::default() };
+    let mut bitbar = BitBarMetadata::load(&metadata, package, custom_metadata)?;
+    bitbar.apply(overrides);
+    if bitbar.image.is_none() { eprintln!("warning: no preview image, which is required for submission. Set `image` in the bitbar metadata or use --image.") }
+    let header = String::from_utf8(bitbar.format(package, vars, "xbar")?)?;
+    // SwiftBar-specific items aren't used by the xbar plugin repository
+    for line in header.lines().filter(|line| line.starts_with("# <xbar.")) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Path prefixes of dynamic libraries which are available on every Mac.
+const SYSTEM_LIBRARY_PREFIXES: [&str; 2] = ["/usr/lib/", "/System/Library/"];
+
+/// Parses the output of `otool -L`, returning the install names of the linked libraries without duplicates.
+///
+/// The output for each architecture starts with an unindented header line ending in `:` (`path:` for single-architecture binaries, `path (architecture arm64):` for universal binaries), followed by an indented line for each library.
+fn parse_otool_libraries(output: &str) -> Vec<&str> {
+    let mut libraries = Vec::default();
+    for line in output.lines() {
+        if line.ends_with(':') { continue }
+        let Some(library) = line.trim().split(" (").next().filter(|library| !library.is_empty()) else { continue };
+        if !libraries.contains(&library) { libraries.push(library) }
+    }
+    libraries
+}
+
+/// Parses the output of `otool -l`, returning the paths of the `LC_RPATH` load commands.
+fn parse_otool_rpaths(output: &str) -> Vec<&str> {
+    let mut rpaths = Vec::default();
+    let mut in_rpath = false;
+    for line in output.lines().map(str::trim) {
+        if let Some(cmd) = line.strip_prefix("cmd ") {
+            in_rpath = cmd == "LC_RPATH";
+        } else if let Some(path) = line.strip_prefix("path ").filter(|_| in_rpath) {
+            let path = path.rsplit_once(" (offset ").map_or(path, |(path, _)| path);
+            if !rpaths.contains(&path) { rpaths.push(path) }
+        }
+    }
+    rpaths
+}
+
+/// Resolves `@executable_path`, `@loader_path`, and `@rpath` in a library install name. For the plugin binary, `@loader_path` is the same as `@executable_path`.
+///
+/// Returns `None` if an `@rpath` name isn't found in any of the `rpaths`. Candidates in system library directories count as found even if they don't exist on disk, since recent macOS versions only ship system libraries in the dyld shared cache.
+fn resolve_library(library: &str, exe_dir: &Path, rpaths: &[&str], exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    fn expand(path: &str, exe_dir: &Path) -> PathBuf {
+        match path.strip_prefix("@executable_path/").or_else(|| path.strip_prefix("@loader_path/")) {
+            Some(rest) => exe_dir.join(rest),
+            None => PathBuf::from(path),
+        }
+    }
+
+    if let Some(rest) = library.strip_prefix("@rpath/") {
+        let candidates = rpaths.iter().map(|rpath| expand(rpath, exe_dir).join(rest)).collect_vec();
+        candidates.iter().find(|candidate| exists(candidate)).cloned()
+            .or_else(|| candidates.into_iter().find(|candidate| SYSTEM_LIBRARY_PREFIXES.iter().any(|prefix| candidate.starts_with(prefix))))
+    } else {
+        Some(expand(library, exe_dir))
+    }
+}
+
+/// Lists the dynamic libraries linked by the given binary using `otool -L`, and fails if any of them are not part of macOS.
+fn deps(exe_path: &Path) -> Result<()> {
+    let output = Command::new("otool").arg("-L").arg(exe_path).output()?;
+    if !output.status.success() { bail!("otool failed: {}", output.status) }
+    let libraries = String::from_utf8(output.stdout)?;
+    let load_commands = Command::new("otool").arg("-l").arg(exe_path).output()?;
+    if !load_commands.status.success() { bail!("otool failed: {}", load_commands.status) }
+    let load_commands = String::from_utf8(load_commands.stdout)?;
+    let rpaths = parse_otool_rpaths(&load_commands);
+    let exe_dir = exe_path.parent().unwrap_or(Path::new("."));
+    let mut non_system = 0;
+    for library in parse_otool_libraries(&libraries) {
+        match resolve_library(library, exe_dir, &rpaths, Path::exists) {
+            Some(path) if SYSTEM_LIBRARY_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) => println!("{}", path.display()),
+            Some(path) => {
+                eprintln!("warning: {} is not part of macOS, so the plugin may not work on other machines", path.display());
+                non_system += 1;
+            }
+            None => {
+                eprintln!("warning: {library} could not be found in the binary's rpaths, so the plugin may not work on other machines");
+                non_system += 1;
+            }
+        }
+    }
+    if non_system > 0 { bail!("found {non_system} non-system libraries") }
+    Ok(())
+}
+
+/// Finds the package whose metadata should be applied to the given binary, and returns it along with its custom metadata.
+///
+/// The package is the one with a binary target named like the binary, among the selected packages (or all workspace members if none are selected). If there is no such package, the root package (or the single selected package) is used. If there is no root package either, the workspace metadata is used.
+fn package_for_binary<'a>(metadata: &'a Metadata, selected: &[String], exe_path: &Path) -> Result<(Option<&'a Package>, serde_json::Value)> {
+    let candidates = metadata.workspace_packages().into_iter().filter(|package| selected.is_empty() || selected.contains(&package.name)).collect_vec();
+    for name in selected {
+        if !candidates.iter().any(|package| package.name == *name) { bail!("package {name} is not a member of the workspace") }
+    }
+    let Some(file_name) = exe_path.file_name().and_then(|file_name| file_name.to_str()) else { bail!("invalid binary path: {}", exe_path.display()) };
+    let package = if let Some(package) = candidates.iter().find(|package| package.targets.iter().any(|target| target.is_bin() && target.name == file_name)) {
+        Some(*package)
+    } else if let [package] = &*candidates {
+        Some(*package)
+    } else if selected.is_empty() {
+        metadata.root_package()
+    } else {
+        bail!("none of the selected packages has a binary named {file_name}")
+    };
+    Ok(package_metadata(metadata, package))
+}
+
+/// Returns the given package along with its custom metadata, falling back to the workspace metadata if the package has no `bitbar` metadata of its own.
+fn package_metadata<'a>(metadata: &Metadata, package: Option<&'a Package>) -> (Option<&'a Package>, serde_json::Value) {
+    if let Some(package) = package {
+        let custom_metadata = if package.metadata.get("bitbar").is_some() { package.metadata.clone() } else { metadata.workspace_metadata.clone() };
+        (Some(package), custom_metadata)
+    } else {
+        (None, metadata.workspace_metadata.clone())
+    }
+}
+
+/// Expands `*` and `?` wildcards in the file name of the given path, e.g. `target/release/*-plugin`. Paths without wildcards are returned as is.
+fn expand_wildcards(path: &Path) -> Result<Vec<PathBuf>> {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => matches(&pattern[1..], name) || !name.is_empty() && matches(pattern, &name[1..]),
+            (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(n)) => p == n && matches(&pattern[1..], &name[1..]),
+            (_, _) => false,
+        }
+    }
+
+    let Some(pattern) = path.file_name().and_then(|file_name| file_name.to_str()).filter(|file_name| file_name.contains(['*', '?'])) else { return Ok(vec![path.to_owned()]) };
+    let pattern = pattern.chars().collect_vec();
+    let dir = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut paths = Vec::default();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() { continue }
+        let Some(name) = entry.file_name().to_str().map(|name| name.chars().collect_vec()) else { continue };
+        if matches(&pattern, &name) { paths.push(entry.path()) }
+    }
+    if paths.is_empty() { bail!("no files match {}", path.display()) }
+    paths.sort();
+    Ok(paths)
+}
+
+/// How long `cargo bitbar lint --run` waits for the plugin to print its menu.
+const RUN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Diagnostics {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Diagnostics {
+    fn error(&mut self, msg: impl fmt::Display) {
+        eprintln!("error: {msg}");
+        self.errors += 1;
+    }
+
+    fn warning(&mut self, msg: impl fmt::Display) {
+        eprintln!("warning: {msg}");
+        self.warnings += 1;
+    }
+}
+
+/// Checks a `<swiftbar.schedule>` value, which consists of one or more cron expressions separated by `|`.
+fn check_schedule(schedule: &str) -> Result<(), String> {
+    const FIELDS: [(&str, u32, u32, &[&str]); 5] = [
+        ("minute", 0, 59, &[]),
+        ("hour", 0, 23, &[]),
+        ("day of month", 1, 31, &[]),
+        ("month", 1, 12, &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"]),
+        ("day of week", 0, 7, &["sun", "mon", "tue", "wed", "thu", "fri", "sat"]),
+    ];
+
+    for expr in schedule.split('|') {
+        let fields = expr.split_whitespace().collect_vec();
+        if fields.len() != FIELDS.len() { return Err(format!("cron expression {:?} has {} fields, expected 5 (minute, hour, day of month, month, day of week)", expr.trim(), fields.len())) }
+        for (field, (name, min, max, names)) in fields.into_iter().zip(FIELDS) {
+            let value = |value: &str| if let Some(idx) = names.iter().position(|name| name.eq_ignore_ascii_case(value)) {
+                Ok(idx as u32 + if min == 1 { 1 } else { 0 })
+            } else {
+                value.parse::<u32>().ok().filter(|value| (min..=max).contains(value)).ok_or_else(|| format!("invalid {name} {value:?} in cron expression {:?}, expected a number from {min} to {max}", expr.trim()))
+            };
+            for part in field.split(',') {
+                let (range, step) = part.split_once('/').map_or((part, None), |(range, step)| (range, Some(step)));
+                if let Some(step) = step {
+                    if step.parse::<u32>().map_or(true, |step| step == 0) { return Err(format!("invalid step {step:?} in cron expression {:?}", expr.trim())) }
+                }
+                if range == "*" { continue }
+                if let Some((start, end)) = range.split_once('-') {
+                    if value(start)? > value(end)? { return Err(format!("empty range {range:?} in cron expression {:?}", expr.trim())) }
+                } else {
+                    value(range)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the plugin binary with `--bitbar-validate` and returns the reported issues.
+fn run_validate(exe_path: &Path) -> Result<Vec<String>> {
+    let mut child = Command::new(exe_path).arg("--bitbar-validate").stdout(Stdio::piped()).spawn()?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut buf = String::default();
+        stdout.read_to_string(&mut buf).map(|_| buf)
+    });
+    let deadline = Instant::now() + RUN_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? { break status }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            bail!("plugin did not exit within {} seconds", RUN_TIMEOUT.as_secs())
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let output = reader.join().expect("stdout reader panicked")?;
+    if !status.success() { bail!("plugin exited with {status}") }
+    Ok(output.lines().map(str::to_owned).collect())
+}
+
+/// Validates the plugin metadata and optionally the output of the given plugin binary.
+fn lint(manifest: Option<PathBuf>, run: Option<PathBuf>) -> Result<()> {
+    let metadata = read_manifest(manifest)?;
+    let (package, custom_metadata) = custom_metadata(&metadata);
+    let mut diagnostics = Diagnostics::default();
+    let (bitbar, loaded) = match BitBarMetadata::load(&metadata, package, custom_metadata) {
+        Ok(bitbar) => (bitbar, true),
+        Err(e) => {
+            diagnostics.error(e);
+            (BitBarMetadata::default(), false)
+        }
+    };
+    if let Some(ref schedule) = bitbar.schedule {
+        if let Err(e) = check_schedule(schedule) { diagnostics.error(e) }
+    }
+    // required by https://github.com/matryer/xbar-plugins/blob/main/CONTRIBUTING.md, taking the fallbacks used by `cargo bitbar meta` into account
+    let mut missing = Vec::default();
+    if !matches!(bitbar.title, Some(Some(_))) && package.is_none() { missing.push("title") }
+    if !matches!(bitbar.version, Some(Some(_))) && package.is_none() { missing.push("version") }
+    if !matches!(bitbar.author, Some(Some(_))) && package.is_none_or(|package| package.authors.is_empty()) { missing.push("author") }
+    if bitbar.author_github.is_none() { missing.push("author-github") }
+    if !matches!(bitbar.desc, Some(Some(_))) && package.is_none_or(|package| package.description.is_none()) { missing.push("desc") }
+    if bitbar.image.is_none() { missing.push("image") }
+    if !matches!(bitbar.abouturl, Some(Some(_))) && package.is_none_or(|package| package.homepage.is_none()) { missing.push("abouturl") }
+    // skip if the metadata couldn't be loaded since every field would be reported as missing
+    if loaded && !missing.is_empty() {
+        diagnostics.warning(format_args!("missing bitbar metadata required for submission to the xbar plugin repository: {}", missing.into_iter().map(|key| format!("`{key}`")).join(", ")));
+    }
+    if let Some(exe_path) = run {
+        if let PluginKind::Streamable = bitbar.kind {
+            diagnostics.warning("not running streamable plugin since it doesn't exit on its own");
+        } else {
+            match run_validate(&exe_path) {
+                Ok(issues) => for issue in issues {
+                    diagnostics.warning(format_args!("plugin output: {issue}"));
+                },
+                Err(e) => diagnostics.error(format_args!("failed to run plugin: {e}")),
+            }
+        }
+    }
+    if diagnostics.errors > 0 { bail!("found {} errors and {} warnings", diagnostics.errors, diagnostics.warnings) }
+    eprintln!("found {} warnings", diagnostics.warnings);
+    Ok(())
+}
+
+/// Reads the metadata attribute of the given binary and returns the decoded metadata items as pairs of tag name and value, in the order they appear.
+fn read_metadata(exe_path: &Path) -> Result<Vec<(String, String)>> {
+    let Some(value) = xattr::get(exe_path, XATTR_NAME)? else { bail!("{} has no plugin metadata, add it using `cargo bitbar meta`", exe_path.display()) };
+    let decoded = base64::engine::general_purpose::STANDARD.decode(value.trim_ascii())?;
+    Ok(parse_metadata(String::from_utf8(decoded)?.lines()))
+}
+
+/// Parses metadata comment lines like `# <xbar.title>My Plugin</xbar.title>` into pairs of tag name and value, skipping lines which aren't metadata.
+fn parse_metadata<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<(String, String)> {
+    lines.into_iter().filter_map(|line| {
+        let line = line.trim_start_matches(|c: char| c == '#' || c == '/' || c.is_whitespace());
+        let (tag, rest) = line.strip_prefix('<')?.split_once('>')?;
+        let value = rest.strip_suffix(&format!("</{tag}>")).unwrap_or(rest);
+        Some((tag.to_owned(), value.to_owned()))
+    }).collect()
+}
+
+/// Prints a table of the plugins installed in the given plugin folder (defaulting to the one configured in SwiftBar), with some of their metadata.
+///
+/// Metadata is read from the extended attribute written by `cargo bitbar meta` or, for scripts, from their comment header.
+fn list(plugin_dir: Option<PathBuf>) -> Result<()> {
+    let plugin_dir = swiftbar_plugin_dir(plugin_dir)?;
+    let mut rows = Vec::default();
+    for entry in fs::read_dir(&plugin_dir)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("warning: failed to read an entry of {}: {e}", plugin_dir.display());
+                continue
+            }
+        };
+        let Some(file_name) = entry.file_name().to_str().map(str::to_owned) else { continue };
+        if file_name.starts_with('.') { continue }
+        let path = entry.path();
+        match plugin_row(&path, &file_name) {
+            Ok(Some(row)) => rows.push(row),
+            Ok(None) => {}
+            Err(e) => eprintln!("warning: skipping {}: {e}", path.display()),
+        }
+    }
+    rows.sort_by_key(|row| row[0].to_lowercase());
+    let header = ["NAME", "VERSION", "SCHEDULE", "TYPE", "CARGO BITBAR"].map(str::to_owned);
+    let widths = (0..header.len()).map(|col| rows.iter().chain([&header]).map(|row| row[col].chars().count()).max().unwrap_or_default()).collect_vec();
+    for row in [&header].into_iter().chain(&rows) {
+        println!("{}", row.iter().zip(&widths).map(|(cell, &width)| format!("{cell:width$}")).join("  ").trim_end());
+    }
+    Ok(())
+}
+
+/// Returns the row of the `cargo bitbar list` table for the plugin at the given path, or `None` if the path is a directory.
+fn plugin_row(path: &Path, file_name: &str) -> Result<Option<[String; 5]>> {
+    // follow symlinks, which are commonly used to install plugins
+    if fs::metadata(path)?.is_dir() { return Ok(None) }
+    // not all file systems support extended attributes, so treat errors as missing metadata
+    let (items, built) = if matches!(xattr::get(path, XATTR_NAME), Ok(Some(_))) {
+        (read_metadata(path)?, true)
+    } else {
+        (read_script_metadata(path)?, false)
+    };
+    let get = |tags: &[&str]| tags.iter().find_map(|tag| items.iter().find(|(item_tag, _)| item_tag == tag).map(|(_, value)| value.clone()));
+    // plugin file names have the form `name.interval.ext`, with the interval being optional
+    let mut name_parts = file_name.split('.').collect_vec();
+    if name_parts.len() > 1 { name_parts.pop(); }
+    let interval = if name_parts.len() > 1 { name_parts.pop().map(str::to_owned) } else { None };
+    Ok(Some([
+        get(&["xbar.title", "bitbar.title"]).unwrap_or_else(|| name_parts.join(".")),
+        get(&["xbar.version", "bitbar.version"]).unwrap_or_else(|| "-".to_owned()),
+        get(&["swiftbar.schedule"]).or(interval).unwrap_or_else(|| "-".to_owned()),
+        get(&["swiftbar.type"]).unwrap_or_else(|| "default".to_owned()),
+        if built { "yes".to_owned() } else { "no".to_owned() },
+    ]))
+}
+
+/// The number of bytes at the start of a script which are searched for metadata comments.
+const SCRIPT_HEADER_LEN: u64 = 64 * 1024;
+
+/// Reads the metadata from the comment header of a script. Files which don't start with a shebang line, e.g. binaries without metadata, have none.
+fn read_script_metadata(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut header = Vec::default();
+    fs::File::open(path)?.take(SCRIPT_HEADER_LEN).read_to_end(&mut header)?;
+    Ok(if header.starts_with(b"#!") {
+        parse_metadata(String::from_utf8_lossy(&header).lines().filter(|line| line.starts_with('#') || line.starts_with("//")))
+    } else {
+        Vec::default()
+    })
+}
+
+/// Prints the metadata embedded in the given binary, either one item per line or as a JSON object mapping tag names to values.
+///
+/// In JSON output, tags which occur multiple times (e.g. `xbar.var`) map to an array of values.
+fn show(exe_path: &Path, json: bool) -> Result<()> {
+    let items = read_metadata(exe_path)?;
+    if json {
+        let mut map = serde_json::Map::default();
+        for (tag, value) in items {
+            match map.entry(tag) {
+                serde_json::map::Entry::Vacant(entry) => { entry.insert(serde_json::Value::String(value)); }
+                serde_json::map::Entry::Occupied(mut entry) => match entry.get_mut() {
+                    serde_json::Value::Array(values) => values.push(serde_json::Value::String(value)),
+                    prev => *prev = serde_json::Value::Array(vec![prev.take(), serde_json::Value::String(value)]),
+                },
+            }
+        }
+        println!("{}", serde_json::to_string_pretty(&map)?);
+    } else {
+        let width = items.iter().map(|(tag, _)| tag.len()).max().unwrap_or_default();
+        for (tag, value) in items {
+            println!("{tag:width$}  {value}");
+        }
+    }
+    Ok(())
+}
+
+/// Writes an executable shell script with the given metadata comments which runs the given binary, passing along its arguments.
+///
+/// If `relative` is `true`, the binary is looked up relative to the directory containing the script, so they can be distributed together. Otherwise, the absolute path to the binary is used.
+fn write_wrapper(path: &Path, exe_path: &Path, relative: bool, metadata: &[u8]) -> Result<()> {
+    let exe_path = if relative { exe_path.to_owned() } else { fs::canonicalize(exe_path)? };
+    let Some(exe_path) = exe_path.to_str() else { bail!("the path to the binary is not valid UTF-8") };
+    let mut script = Vec::default();
+    writeln!(&mut script, "#!/bin/sh")?;
+    writeln!(&mut script)?;
+    script.extend_from_slice(metadata);
+    writeln!(&mut script)?;
+    if relative {
+        writeln!(&mut script, "exec \"$(dirname \"$0\")\"/'{}' \"$@\"", exe_path.replace('\'', "'\\''"))?;
+    } else {
+        writeln!(&mut script, "exec '{}' \"$@\"", exe_path.replace('\'', "'\\''"))?;
+    }
+    fs::write(path, script)?;
+    #[cfg(unix)] {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    println!("Created wrapper script {}", path.display());
+    Ok(())
+}
+
+/// Removes the extended attributes written by `cargo bitbar meta` from the given binary.
+fn clear(exe_path: &Path) -> Result<()> {
+    if xattr::get(exe_path, XATTR_NAME)?.is_some() {
+        xattr::remove(exe_path, XATTR_NAME)?;
+        println!("Removed plugin metadata from {}", exe_path.display());
+    } else {
+        println!("{} has no plugin metadata", exe_path.display());
+    }
+    Ok(())
+}
+
+/// Quotes the given value as a TOML basic string. This is also a valid Go string literal.
+fn toml_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", u32::from(c))),
+            c => quoted.push(c),
         }
-        Ok(buf.finish()?)
     }
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes the given value as a Rust string literal.
+fn rust_string(value: &str) -> String {
+    format!("\"{}\"", value.escape_default())
+}
+
+/// Creates a new plugin package in the given directory.
+fn new_plugin(path: &Path, name: Option<String>, title: Option<String>, streamable: bool) -> Result<()> {
+    if path.exists() { bail!("destination {} already exists", path.display()) }
+    let name = match name {
+        Some(name) => name,
+        None => path.file_name().and_then(|name| name.to_str()).ok_or_else(|| anyhow::anyhow!("can't determine package name from path, use --name to specify one"))?.to_owned(),
+    };
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!("invalid package name {name:?}, use --name to specify a different one")
+    }
+    let title = title.unwrap_or_else(|| name.clone());
+    let mut manifest = Vec::default();
+    writeln!(&mut manifest, "[package]")?;
+    writeln!(&mut manifest, "name = {}", toml_string(&name))?;
+    writeln!(&mut manifest, "version = \"0.1.0\"")?;
+    writeln!(&mut manifest, "edition = \"2021\"")?;
+    writeln!(&mut manifest)?;
+    writeln!(&mut manifest, "[package.metadata.bitbar]")?;
+    writeln!(&mut manifest, "title = {}", toml_string(&title))?;
+    if streamable { writeln!(&mut manifest, "type = \"streamable\"")?; }
+    writeln!(&mut manifest)?;
+    writeln!(&mut manifest, "[dependencies]")?;
+    writeln!(&mut manifest, "bitbar = {}", toml_string(bitbar::VERSION))?;
+    let main_rs = if streamable { include_str!("../templates/streamable.rs") } else { include_str!("../templates/main.rs") }.replace("\"{title}\"", &rust_string(&title));
+    fs::create_dir_all(path.join("src"))?;
+    fs::write(path.join("Cargo.toml"), manifest)?;
+    fs::write(path.join("src").join("main.rs"), main_rs)?;
+    fs::write(path.join(".gitignore"), "/target\n")?;
+    println!("Created plugin package {name} in {}", path.display());
+    println!("To install it, build it using `cargo build --release`, add its metadata using `cargo bitbar meta target/release/{name}`, then copy the binary to your plugin folder with a name like {name}.1m.o, where 1m is the refresh interval.");
+    Ok(())
+}
+
+/// Writes a completion script for the `cargo-bitbar` binary. This doesn't use the `cargo` binary name since the script would replace the completions for all of cargo.
+fn completions(shell: clap_complete::Shell, output: &mut impl Write) {
+    clap_complete::generate(shell, &mut Args::command().name("cargo-bitbar"), "cargo-bitbar", output)
 }
 
 #[derive(clap::Parser)]
+#[clap(bin_name = "cargo")]
 enum Args {
-    #[clap(subcommand)]
+    /// Tools for developing plugins for BitBar, SwiftBar, and xbar using the bitbar crate.
+    #[clap(subcommand, version, display_name = "cargo-bitbar")]
     Bitbar(ArgsInner),
 }
 
 #[derive(clap::Subcommand)]
 enum ArgsInner {
-    /// Read plugin metadata from Cargo.toml and encode it into the given binary.
+    /// Read plugin metadata from Cargo.toml and encode it into the given binaries.
+    ///
+    /// In a workspace, each binary gets the metadata of the package with a binary target of the same name.
     Meta {
+        /// The path to the Cargo manifest for the package or workspace.
+        #[clap(long)]
+        manifest: Option<PathBuf>,
+        /// Only consider the given workspace members when looking up the package of each binary. Can be specified multiple times.
+        #[clap(short, long = "package")]
+        packages: Vec<String>,
+        /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]`, by running the binary.
+        #[clap(long)]
+        vars: bool,
+        /// Print the metadata that would be embedded instead of editing the binaries.
+        #[clap(long)]
+        dry_run: bool,
+        #[clap(flatten)]
+        overrides: MetaOverrides,
+        /// The paths to the binaries that should be edited. `*` and `?` wildcards in the file name are expanded.
+        #[clap(required = true)]
+        exe_paths: Vec<PathBuf>,
+    },
+    /// Check the plugin metadata in Cargo.toml for mistakes, and optionally the output of the plugin.
+    Lint {
         /// The path to the Cargo manifest for the package.
         #[clap(long)]
         manifest: Option<PathBuf>,
+        /// Also run the given plugin binary once and check its menu using `Menu::validate`.
+        #[clap(long)]
+        run: Option<PathBuf>,
+    },
+    /// Print the plugin metadata embedded in the given binary by `cargo bitbar meta`.
+    Show {
+        /// Print the metadata as a JSON object instead of one item per line.
+        #[clap(long)]
+        json: bool,
+        /// The path to the plugin binary.
+        exe_path: PathBuf,
+    },
+    /// Print a table of the plugins installed in the SwiftBar plugin folder, with their name, version, schedule, and type, and whether their metadata was added by `cargo bitbar meta`.
+    List {
+        /// The SwiftBar plugin folder. Defaults to the one configured in SwiftBar.
+        #[clap(long)]
+        plugin_dir: Option<PathBuf>,
+    },
+    /// Remove the plugin metadata added by `cargo bitbar meta` from the given binary.
+    Clear {
         /// The path to the binary that should be edited.
         exe_path: PathBuf,
     },
+    /// Generate a shell script which runs the given binary and contains the plugin metadata from Cargo.toml as comments.
+    ///
+    /// xbar can't read metadata from a binary's extended attributes, so the wrapper script can be installed as the plugin instead of the binary.
+    Wrapper {
+        /// The path to the Cargo manifest for the package.
+        #[clap(long)]
+        manifest: Option<PathBuf>,
+        /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]`, by running the binary.
+        #[clap(long)]
+        vars: bool,
+        #[clap(flatten)]
+        overrides: MetaOverrides,
+        /// Where to write the script. Defaults to the path of the binary with `.sh` appended.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// The path to the binary that should be run by the script.
+        exe_path: PathBuf,
+    },
+    /// Write the default values of the plugin variables to the `.vars.json` file xbar reads them from.
+    ///
+    /// Variables are declared in `[package.metadata.bitbar.vars]` in Cargo.toml, and optionally via `#[bitbar::main(vars(...))]`. Values already present in the file are kept.
+    VarsJson {
+        /// The path to the Cargo manifest for the package.
+        #[clap(long)]
+        manifest: Option<PathBuf>,
+        /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]` by running the given binary.
+        #[clap(long)]
+        exe: Option<PathBuf>,
+        /// The path to the installed plugin, e.g. `~/Library/Application Support/xbar/plugins/my-plugin.1m.sh`. The file is written next to it.
+        plugin_path: PathBuf,
+    },
+    /// Build the plugin for Apple Silicon and Intel Macs, combine the binaries into a universal binary using `lipo`, and embed the plugin metadata into it.
+    Universal {
+        /// The path to the Cargo manifest for the package or workspace.
+        #[clap(long)]
+        manifest: Option<PathBuf>,
+        /// The workspace member to build. Required for virtual workspaces.
+        #[clap(short, long)]
+        package: Option<String>,
+        /// The binary target to build. Required if the package has multiple binary targets.
+        #[clap(long)]
+        bin: Option<String>,
+        /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]`, by running the binary.
+        #[clap(long)]
+        vars: bool,
+        #[clap(flatten)]
+        overrides: MetaOverrides,
+        /// Where to write the universal binary. Defaults to `target/universal-apple-darwin/release/<bin>`.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Assemble a release archive with the plugin binary including its metadata, the readme, and a checksums file.
+    Package(PackageArgs),
+    /// Rebuild the plugin whenever a file in the package changes, install it into the SwiftBar plugin folder, and refresh it.
+    Watch(WatchArgs),
+    /// Build the plugin and run it in a simulated BitBar environment, printing its menu as a tree.
+    Run(RunArgs),
+    /// Check the dynamic libraries linked by the given binary, warning about any which are not part of macOS (e.g. Homebrew's openssl).
+    Deps {
+        /// The path to the plugin binary.
+        exe_path: PathBuf,
+    },
+    /// Print a shell completion script for cargo bitbar.
+    ///
+    /// The script completes the `cargo-bitbar` binary rather than replacing the completions for cargo itself. For example, for zsh, add `eval "$(cargo bitbar completions zsh)"` to your `.zshrc`.
+    Completions {
+        /// The shell to generate the completion script for.
+        shell: clap_complete::Shell,
+    },
+    /// Print the `<xbar.*>` comment header used by the xbar plugin repository, e.g. for a wrapper script or readme.
+    Header {
+        /// The path to the Cargo manifest for the package or workspace.
+        #[clap(long)]
+        manifest: Option<PathBuf>,
+        /// The workspace member whose metadata should be used.
+        #[clap(short, long)]
+        package: Option<String>,
+        /// Also add the plugin variables declared via `#[bitbar::main(vars(...))]`, by running the given binary.
+        #[clap(long)]
+        exe: Option<PathBuf>,
+        #[clap(flatten)]
+        overrides: MetaOverrides,
+    },
+    /// Create a new plugin package with a sample menu and command.
+    New {
+        /// The package name. Defaults to the name of the directory.
+        #[clap(long)]
+        name: Option<String>,
+        /// The plugin title shown in the menu and in the plugin metadata. Defaults to the package name.
+        #[clap(long)]
+        title: Option<String>,
+        /// Create a SwiftBar plugin which streams its menu instead of being run once per refresh.
+        #[clap(long)]
+        streamable: bool,
+        /// The directory to create.
+        path: PathBuf,
+    },
 }
 
-fn main() -> Result<()> {
+fn main() {
     let Args::Bitbar(args) = Args::parse();
+    if let Err(e) = cli(args) {
+        eprintln!("error: {e:#}");
+        process::exit(1);
+    }
+}
+
+fn cli(args: ArgsInner) -> Result<()> {
     match args {
-        ArgsInner::Meta { manifest, exe_path } => {
-            let mut metadata_cmd = MetadataCommand::new();
-            metadata_cmd.no_deps();
-            if let Some(manifest) = manifest {
-                metadata_cmd.manifest_path(manifest);
+        ArgsInner::Clear { exe_path } => clear(&exe_path)?,
+        ArgsInner::Completions { shell } => completions(shell, &mut io::stdout()),
+        ArgsInner::Deps { exe_path } => deps(&exe_path)?,
+        ArgsInner::Header { manifest, package, exe, overrides } => submission_header(manifest, package, exe, overrides)?,
+        ArgsInner::Lint { manifest, run } => lint(manifest, run)?,
+        ArgsInner::List { plugin_dir } => list(plugin_dir)?,
+        ArgsInner::Meta { manifest, packages, vars, dry_run, overrides, exe_paths } => {
+            let metadata = read_manifest(manifest)?;
+            let exe_paths = exe_paths.iter().map(|exe_path| expand_wildcards(exe_path)).flatten_ok().collect::<Result<Vec<_>>>()?;
+            for (idx, exe_path) in exe_paths.iter().enumerate() {
+                let bitbar_metadata = binary_metadata(&metadata, &packages, vars, &overrides, exe_path)?;
+                if dry_run {
+                    let mut stdout = io::stdout().lock();
+                    if exe_paths.len() > 1 {
+                        if idx > 0 { writeln!(&mut stdout)?; }
+                        writeln!(&mut stdout, "==> {} <==", exe_path.display())?;
+                    }
+                    stdout.write_all(&bitbar_metadata)?;
+                } else {
+                    embed_metadata(exe_path, &bitbar_metadata)?;
+                }
             }
-            let metadata = metadata_cmd.exec()?;
-            let package = metadata.root_package();
-            let custom_metadata = if let Some(package) = package {
-                package.metadata.clone()
-            } else {
-                metadata.workspace_metadata.clone()
-            };
-            let bitbar_metadata = serde_json::from_value::<CustomMetadata>(custom_metadata)?.bitbar.format(package)?;
-            xattr::set(exe_path, "com.ameba.SwiftBar", &bitbar_metadata)?;
+        }
+        ArgsInner::New { name, title, streamable, path } => new_plugin(&path, name, title, streamable)?,
+        ArgsInner::Package(args) => package(args)?,
+        ArgsInner::Run(args) => run_plugin(args)?,
+        ArgsInner::Show { json, exe_path } => show(&exe_path, json)?,
+        ArgsInner::Watch(args) => watch(args)?,
+        ArgsInner::Universal { manifest, package, bin, vars, overrides, output } => universal(manifest, package, bin, vars, overrides, output)?,
+        ArgsInner::VarsJson { manifest, exe, plugin_path } => {
+            let metadata = read_manifest(manifest)?;
+            let (_, custom_metadata) = custom_metadata(&metadata);
+            let binary_vars = if let Some(exe) = exe { read_vars(&exe)? } else { Vec::default() };
+            let manifest_vars = serde_json::from_value::<CustomMetadata>(custom_metadata)?.bitbar.vars;
+            write_vars_json(&plugin_path, merge_vars(manifest_vars, binary_vars))?;
+        }
+        ArgsInner::Wrapper { manifest, vars, overrides, output, exe_path } => {
+            let metadata = read_manifest(manifest)?;
+            let (package, custom_metadata) = custom_metadata(&metadata);
+            let vars = if vars { read_vars(&exe_path)? } else { Vec::default() };
+            let mut bitbar = BitBarMetadata::load(&metadata, package, custom_metadata)?;
+            bitbar.apply(overrides);
+            let bitbar_metadata = bitbar.format(package, vars, "xbar")?;
+            let output = output.unwrap_or_else(|| {
+                let mut output = exe_path.clone().into_os_string();
+                output.push(".sh");
+                output.into()
+            });
+            write_wrapper(&output, &exe_path, false, &bitbar_metadata)?;
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_string_round_trip() {
+        for value in ["plain", "with \"quotes\"", "back\\slash", "new\nline", "tab\tand\u{7f}delete", "emoji 🦀"] {
+            let parsed = toml::from_str::<toml::Table>(&format!("value = {}", toml_string(value))).unwrap();
+            assert_eq!(parsed["value"].as_str(), Some(value));
+        }
+    }
+
+    #[test]
+    fn otool_universal_binary() {
+        let output = "target/release/plugin (architecture x86_64):\n\t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1336.0.0)\n\t@rpath/libswiftCore.dylib (compatibility version 1.0.0, current version 5.9.2)\ntarget/release/plugin (architecture arm64):\n\t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1336.0.0)\n\t@rpath/libswiftCore.dylib (compatibility version 1.0.0, current version 5.9.2)\n";
+        assert_eq!(parse_otool_libraries(output), ["/usr/lib/libSystem.B.dylib", "@rpath/libswiftCore.dylib"]);
+        assert_eq!(parse_otool_libraries("plugin:\n\t/usr/lib/libiconv.2.dylib (compatibility version 7.0.0, current version 7.0.0)\n"), ["/usr/lib/libiconv.2.dylib"]);
+    }
+
+    #[test]
+    fn otool_rpaths() {
+        let output = "Load command 12\n      cmd LC_LOAD_DYLIB\n  cmdsize 56\n     name /usr/lib/libSystem.B.dylib (offset 24)\nLoad command 13\n          cmd LC_RPATH\n      cmdsize 32\n         path /usr/lib/swift (offset 12)\nLoad command 14\n          cmd LC_RPATH\n      cmdsize 48\n         path @executable_path/../Frameworks (offset 12)\n";
+        assert_eq!(parse_otool_rpaths(output), ["/usr/lib/swift", "@executable_path/../Frameworks"]);
+    }
+
+    #[test]
+    fn resolve_libraries() {
+        let exe_dir = Path::new("/plugins");
+        let rpaths = ["@executable_path/../Frameworks", "/usr/lib/swift"];
+        assert_eq!(resolve_library("/usr/lib/libSystem.B.dylib", exe_dir, &rpaths, |_| false), Some(PathBuf::from("/usr/lib/libSystem.B.dylib")));
+        assert_eq!(resolve_library("@executable_path/libfoo.dylib", exe_dir, &rpaths, |_| false), Some(PathBuf::from("/plugins/libfoo.dylib")));
+        assert_eq!(resolve_library("@loader_path/libfoo.dylib", exe_dir, &rpaths, |_| false), Some(PathBuf::from("/plugins/libfoo.dylib")));
+        assert_eq!(resolve_library("@rpath/libswiftCore.dylib", exe_dir, &rpaths, |_| false), Some(PathBuf::from("/usr/lib/swift/libswiftCore.dylib")));
+        assert_eq!(resolve_library("@rpath/libswiftCore.dylib", exe_dir, &rpaths, |path| path.starts_with("/plugins")), Some(PathBuf::from("/plugins/../Frameworks/libswiftCore.dylib")));
+        assert_eq!(resolve_library("@rpath/libfoo.dylib", exe_dir, &rpaths[..1], |_| false), None);
+    }
+
+    /// A temporary directory for a test, which is removed when dropped, even if the test fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            Self(env::temp_dir().join(format!("cargo-bitbar-test-{name}-{}", process::id())))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    #[ignore = "builds two new packages, which needs registry access and takes minutes"]
+    fn new_plugin_builds() {
+        let workspace_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().parent().unwrap();
+        let dir = TempDir::new("new");
+        for streamable in [false, true] {
+            let path = dir.0.join(if streamable { "streamable" } else { "default" });
+            new_plugin(&path, None, Some("Title with \"quotes\", a \\ backslash,\nand a newline".to_owned()), streamable).unwrap();
+            // build against this version of the bitbar crate rather than the published one
+            let mut manifest = fs::OpenOptions::new().append(true).open(path.join("Cargo.toml")).unwrap();
+            writeln!(manifest, "\n[patch.crates-io]\nbitbar = {{ path = {} }}", toml_string(workspace_dir.join("crate").join("bitbar").to_str().unwrap())).unwrap();
+            let status = Command::new(env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo")))
+                .arg("build")
+                .arg("--quiet")
+                .env("CARGO_TARGET_DIR", workspace_dir.join("target").join("new-plugin-test"))
+                .current_dir(&path)
+                .status().unwrap();
+            assert!(status.success(), "generated plugin failed to build");
+        }
+    }
+
+    #[test]
+    fn env_file() {
+        let text = "# comment\n\nAPI_URL=https://example.com/?a=b\nexport TOKEN = \"secret value\"\nNAME='single'\nMISMATCHED=\"open'\nEMPTY=\"\"\nQUOTE=\"\nnot a variable\n";
+        assert_eq!(parse_env_file(text), [
+            ("API_URL", "https://example.com/?a=b"),
+            ("TOKEN", "secret value"),
+            ("NAME", "single"),
+            ("MISMATCHED", "\"open'"),
+            ("EMPTY", ""),
+            ("QUOTE", "\""),
+        ].map(|(name, value)| (name.to_owned(), value.to_owned())));
+    }
+
+    #[test]
+    fn wildcards() {
+        let dir = TempDir::new("wildcards");
+        fs::create_dir_all(dir.0.join("subdir-plugin")).unwrap();
+        for name in ["a-plugin", "b-plugin", "plugin", "a-plugin.d", "ab"] {
+            fs::write(dir.0.join(name), "").unwrap();
+        }
+        assert_eq!(expand_wildcards(&dir.0.join("*-plugin")).unwrap(), [dir.0.join("a-plugin"), dir.0.join("b-plugin")]);
+        assert_eq!(expand_wildcards(&dir.0.join("?b")).unwrap(), [dir.0.join("ab")]);
+        assert_eq!(expand_wildcards(&dir.0.join("*")).unwrap().len(), 5);
+        assert!(expand_wildcards(&dir.0.join("*.o")).is_err());
+        // paths without wildcards are returned even if they don't exist
+        assert_eq!(expand_wildcards(&dir.0.join("missing")).unwrap(), [dir.0.join("missing")]);
+    }
+
+    #[test]
+    fn metadata_lines() {
+        let lines = [
+            "#!/bin/bash",
+            "# <xbar.title>My Plugin</xbar.title>",
+            "# not metadata",
+            "// <swiftbar.hideAbout>true</swiftbar.hideAbout>",
+            "# <xbar.var>string(VAR_NAME=\"a <b>\"): The name</xbar.var>",
+            "# <bitbar.version>v1.0",
+            "#",
+        ];
+        assert_eq!(parse_metadata(lines), [
+            ("xbar.title", "My Plugin"),
+            ("swiftbar.hideAbout", "true"),
+            ("xbar.var", "string(VAR_NAME=\"a <b>\"): The name"),
+            ("bitbar.version", "v1.0"),
+        ].map(|(tag, value)| (tag.to_owned(), value.to_owned())));
+    }
+
+    #[test]
+    fn unknown_metadata_keys() {
+        assert!(serde_json::from_value::<CustomMetadata>(serde_json::json!({"bitbar": {"title": "Plugin", "refresh-on-open": true}})).is_ok());
+        let e = serde_json::from_value::<CustomMetadata>(serde_json::json!({"bitbar": {"titel": "Plugin"}})).err().unwrap().to_string();
+        assert!(e.contains("unknown field `titel`"), "{e}");
+        assert!(e.contains("`title`") && e.contains("`environment-file`"), "{e}");
+    }
+
+    #[test]
+    fn schedules() {
+        for schedule in ["* * * * *", "*/5 * * * *", "0 9-17 * * mon-fri", "30 8 1,15 jan,jul 0|0 0 * * 7", "0-30/10 */2 * * *"] {
+            assert_eq!(check_schedule(schedule), Ok(()), "{schedule}");
+        }
+        assert!(check_schedule("* * * *").unwrap_err().contains("has 4 fields"));
+        assert!(check_schedule("60 * * * *").unwrap_err().contains("invalid minute \"60\""));
+        assert!(check_schedule("* * 0 * *").unwrap_err().contains("invalid day of month"));
+        assert!(check_schedule("* * * foo *").unwrap_err().contains("invalid month"));
+        assert!(check_schedule("*/0 * * * *").unwrap_err().contains("invalid step"));
+        assert!(check_schedule("* 5-3 * * *").unwrap_err().contains("empty range"));
+        assert!(check_schedule("* * * * *|* *").unwrap_err().contains("\"* *\""));
+    }
+
+    #[test]
+    fn var_defaults_escaped() {
+        let var = |default: &str| PluginVar { name: "GREETING".to_owned(), kind: "string".to_owned(), default: default.to_owned(), desc: None };
+        let formatted = String::from_utf8(BitBarMetadata::default().format(None, vec![var("say \"hi\"\\n\tbye")], "xbar").unwrap()).unwrap();
+        assert!(formatted.contains("# <xbar.var>string(VAR_GREETING=\"say \\\"hi\\\"\\\\n\\tbye\"): GREETING</xbar.var>\n"), "{formatted}");
+        assert!(BitBarMetadata::default().format(None, vec![var("a, b")], "xbar").unwrap_err().to_string().contains("GREETING"));
+    }
+
+    #[test]
+    fn completions_for_cargo_bitbar() {
+        let mut script = Vec::default();
+        completions(clap_complete::Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.lines().any(|line| line.trim().starts_with("complete ") && line.ends_with(" cargo-bitbar")), "{script}");
+        assert!(!script.lines().any(|line| line.trim().starts_with("complete ") && line.ends_with(" cargo")), "{script}");
+    }
+
+    #[test]
+    fn simulated_env_not_standalone() {
+        let exe_path = Path::new("/plugins/example.1m.o");
+        let env = |flavor| simulated_env(flavor, 402, exe_path).into_iter().map(|(name, value)| (name, value.into_string().unwrap())).collect::<BTreeMap<_, _>>();
+        // the bitbar crate prints a tree instead of plugin output if none of these are set
+        assert_eq!(env(SimulatedFlavor::Bitbar).get("BitBar").map(String::as_str), Some("1"));
+        assert!(env(SimulatedFlavor::Xbar).contains_key("XBARDarkMode"));
+        assert!(matches!(bitbar::Flavor::check_from(&env(SimulatedFlavor::Swiftbar)), bitbar::Flavor::SwiftBar(swiftbar) if swiftbar.build() == 402));
+    }
+
+    #[test]
+    fn plugin_rows() {
+        let dir = TempDir::new("list");
+        fs::create_dir_all(dir.0.join("subdir")).unwrap();
+        fs::write(dir.0.join("weather.5m.sh"), "#!/bin/bash\n# <xbar.title>Weather</xbar.title>\n# <xbar.version>v1.0</xbar.version>\necho hi\n").unwrap();
+        fs::write(dir.0.join("binary.1h.o"), b"\xcf\xfa\xed\xfe\0\0# <xbar.title>Not metadata</xbar.title>").unwrap();
+        std::os::unix::fs::symlink(dir.0.join("subdir"), dir.0.join("linked-dir")).unwrap();
+        std::os::unix::fs::symlink(dir.0.join("missing"), dir.0.join("broken.sh")).unwrap();
+        assert_eq!(plugin_row(&dir.0.join("weather.5m.sh"), "weather.5m.sh").unwrap(), Some(["Weather", "v1.0", "5m", "default", "no"].map(str::to_owned)));
+        assert_eq!(plugin_row(&dir.0.join("binary.1h.o"), "binary.1h.o").unwrap(), Some(["binary", "-", "1h", "default", "no"].map(str::to_owned)));
+        assert_eq!(plugin_row(&dir.0.join("linked-dir"), "linked-dir").unwrap(), None);
+        assert!(plugin_row(&dir.0.join("broken.sh"), "broken.sh").is_err());
+    }
+}
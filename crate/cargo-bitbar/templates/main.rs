@@ -0,0 +1,25 @@
+use bitbar::{
+    CommandResult,
+    ContentItem,
+    Menu,
+    MenuItem,
+};
+
+const TITLE: &str = "{title}";
+
+#[bitbar::command]
+fn greet(name: String) -> CommandResult {
+    CommandResult::new().notify(format!("Hello, {name}!"))
+}
+
+#[bitbar::main]
+fn main() -> Menu {
+    Menu(vec![
+        MenuItem::new(TITLE),
+        MenuItem::Sep,
+        match greet(&String::from("world")) {
+            Ok(params) => ContentItem::new("Say hello").command(params).unwrap_or_else(|never| match never {}).into(),
+            Err(e) => MenuItem::new(format!("error: {e}")),
+        },
+    ])
+}
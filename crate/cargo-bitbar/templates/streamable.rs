@@ -0,0 +1,48 @@
+use {
+    std::{
+        iter,
+        thread,
+        time::Duration,
+    },
+    bitbar::{
+        CommandResult,
+        ContentItem,
+        Menu,
+        MenuItem,
+        flavor::swiftbar::{
+            BlockingStream,
+            SwiftBar,
+        },
+    },
+};
+
+const TITLE: &str = "{title}";
+
+#[bitbar::command]
+fn greet(name: String) -> CommandResult {
+    CommandResult::new().notify(format!("Hello, {name}!"))
+}
+
+fn menu(count: u64) -> Menu {
+    Menu(vec![
+        MenuItem::new(format!("{TITLE} ({count})")),
+        MenuItem::Sep,
+        match greet(&String::from("world")) {
+            Ok(params) => ContentItem::new("Say hello").command(params).unwrap_or_else(|never| match never {}).into(),
+            Err(e) => MenuItem::new(format!("error: {e}")),
+        },
+    ])
+}
+
+#[bitbar::main]
+fn main() -> Result<BlockingStream<'static, Menu>, Menu> {
+    let Some(swiftbar) = SwiftBar::check() else {
+        return Err(Menu(vec![MenuItem::new(format!("{TITLE} requires SwiftBar"))]))
+    };
+    let mut count = 0;
+    Ok(BlockingStream::new(swiftbar, iter::repeat_with(move || {
+        if count > 0 { thread::sleep(Duration::from_secs(1)) }
+        count += 1;
+        menu(count)
+    })))
+}